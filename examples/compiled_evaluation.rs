@@ -0,0 +1,57 @@
+extern crate rand;
+extern crate evco;
+
+use rand::{SeedableRng, StdRng};
+use std::time::{Duration, Instant};
+
+use evco::gp::tree::*;
+use evco::gp::regression::*;
+
+/// Sum the finite outputs, so that the work of evaluating them can't be optimised away.
+fn finite_sum<I: Iterator<Item = f64>>(outputs: I) -> f64 {
+    outputs.filter(|output| output.is_finite()).sum()
+}
+
+/// Time `evaluate` over every tree.
+fn time<F>(name: &str, trees: &[BoxTree<Equation>], evaluate: F) -> Duration
+    where F: FnMut(&BoxTree<Equation>) -> f64
+{
+    let start = Instant::now();
+    let sum: f64 = trees.iter().map(evaluate).sum();
+    let elapsed = start.elapsed();
+    println!("{:<24} {:>10.1?}    (sum of finite outputs {:.6e})", name, elapsed, sum);
+    elapsed
+}
+
+/// Compare `Tree::evaluate` with compiled and batch evaluation of the same equations on a
+/// large dataset. Run with `cargo run --release --example compiled_evaluation`.
+fn main() {
+    let mut rng: StdRng = SeedableRng::from_seed(&[27usize][..]);
+    let primitives = PrimitiveSet::all(2);
+    let trees: Vec<BoxTree<Equation>> = {
+        let mut tg = TreeGen::full(&mut rng, 6, 8)
+            .with_weights(primitives.branch_weights(), primitives.leaf_weights());
+        (0..100).map(|_| Equation::tree(&mut tg)).collect()
+    };
+    let envs: Vec<Vec<f64>> =
+        (0..10000).map(|i| vec![i as f64 / 5000.0 - 1.0, (i % 97) as f64 / 10.0]).collect();
+    let nodes: usize = trees.iter().map(|tree| tree.count_nodes()).sum();
+    println!("{} equations, {} nodes in all, over {} samples", trees.len(), nodes, envs.len());
+
+    let tree = time("Tree::evaluate", &trees, |tree| {
+        finite_sum(envs.iter().map(|env| tree.evaluate(env)))
+    });
+    let mut stack = vec![];
+    let compiled = time("Program::evaluate", &trees, |tree| {
+        let program = Program::compile(tree);
+        finite_sum(envs.iter().map(|env| program.evaluate_with_stack(env, &mut stack)))
+    });
+    let batch = time("Program::evaluate_batch", &trees, |tree| {
+        finite_sum(Program::compile(tree).evaluate_batch(&envs).into_iter())
+    });
+
+    let speedup = |elapsed: Duration| tree.as_secs_f64() / elapsed.as_secs_f64();
+    println!("speedup over Tree::evaluate: compiled {:.1}x, batch {:.1}x",
+             speedup(compiled),
+             speedup(batch));
+}
@@ -9,7 +9,7 @@ use std::collections::VecDeque;
 use evco::gp::*;
 use evco::gp::tree::*;
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub enum TurnDirection {
     Left,
     Ahead,
@@ -27,7 +27,7 @@ impl Rand for TurnDirection {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub enum CompassDirection {
     North,
     East,
@@ -37,10 +37,10 @@ pub enum CompassDirection {
 
 impl CompassDirection {
     fn variants() -> &'static [CompassDirection] {
-        static VARIANTS: &'static [CompassDirection] = &[CompassDirection::North,
-                                                         CompassDirection::East,
-                                                         CompassDirection::South,
-                                                         CompassDirection::West];
+        static VARIANTS: &[CompassDirection] = &[CompassDirection::North,
+                                                 CompassDirection::East,
+                                                 CompassDirection::South,
+                                                 CompassDirection::West];
         VARIANTS
     }
 }
@@ -57,7 +57,7 @@ impl Rand for CompassDirection {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
 pub struct Vector {
     x: isize,
     y: isize,
@@ -128,9 +128,9 @@ impl SnakeEnvironment {
     fn sense_danger(&self, turn_direction: TurnDirection) -> bool {
         let compass_direction = self.turn_to_compass_direction(turn_direction);
         let cell_in_direction = self.snake[0].neighbour(&compass_direction);
-        (cell_in_direction.x < 0 || cell_in_direction.y < 0 ||
-         cell_in_direction.x >= self.size.x || cell_in_direction.y >= self.size.y ||
-         self.snake.contains(&cell_in_direction))
+        cell_in_direction.x < 0 || cell_in_direction.y < 0 ||
+        cell_in_direction.x >= self.size.x || cell_in_direction.y >= self.size.y ||
+        self.snake.contains(&cell_in_direction)
     }
 
     fn sense_food(&self, turn_direction: TurnDirection) -> bool {
@@ -147,7 +147,7 @@ impl SnakeEnvironment {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Hash)]
 pub enum SnakeTree {
     IfDanger(TurnDirection, BoxTree<SnakeTree>, BoxTree<SnakeTree>),
     IfFood(TurnDirection, BoxTree<SnakeTree>, BoxTree<SnakeTree>),
@@ -164,7 +164,7 @@ impl Tree for SnakeTree {
         let direction = TurnDirection::rand(tg);
         let true_ = Self::child(tg, current_depth + 1);
         let false_ = Self::child(tg, current_depth + 1);
-        if tg.gen() {
+        if tg.choose_branch(2) == 0 {
             IfDanger(direction, true_, false_).into()
         } else {
             IfFood(direction, true_, false_).into()
@@ -235,7 +235,7 @@ impl fmt::Display for SnakeTree {
                 IfFood(direction, _, _) => write!(f, "IfFood({:?})", direction)?,
                 Move(direction) => write!(f, "Move({:?})", direction)?,
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
 
             let mut children = node.children();
             children.reverse();
@@ -272,6 +272,16 @@ fn main() {
     let mutation = Mutation::uniform();
     mutation.mutate(&mut indv1, &mut tree_gen);
     println!("{}", indv1);
+    println!("---");
+
+    // Graphviz diagram of the mutated tree, with the new subtree coloured.
+    let dot = Dot::new().name("snake").highlight(indv1.last_changed());
+    println!("{}",
+             dot.render_with(&indv1.tree, |node| match *node {
+                 IfDanger(direction, _, _) => format!("IfDanger({:?})", direction),
+                 IfFood(direction, _, _) => format!("IfFood({:?})", direction),
+                 Move(direction) => format!("Move({:?})", direction),
+             }));
 
     let mut env = SnakeEnvironment {
         size: Vector { x: 10, y: 10 },
@@ -0,0 +1,560 @@
+//! Symbolic regression on CSV files from the command line. Run `evco --help` for usage.
+
+extern crate evco;
+extern crate rand;
+extern crate toml;
+
+use evco::gp::*;
+use evco::gp::regression::*;
+use evco::gp::tree::*;
+use evco::selection::*;
+use rand::{OsRng, Rng, SeedableRng, StdRng};
+use std::env;
+use std::f64;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process;
+use std::str::FromStr;
+
+const USAGE: &str = "\
+Evolve an equation predicting one column of a CSV file from the others.
+
+USAGE:
+    evco [OPTIONS] <CSV>
+
+The last column is the target unless --target names another. Every option may also be set
+in a TOML file given by --config, using the same names; flags override the file.
+
+OPTIONS:
+    --config <FILE>              Read options from a TOML file
+    --target <NAME>              Header name of the target column
+    --delimiter <CHAR>           Field delimiter [default: ,]
+    --population <N>             Population size [default: 500]
+    --generations <N>            Number of generations [default: 50]
+    --functions <LIST>           Comma-separated functions from add, sub, mul, div, sin,
+                                 cos, exp, log and sqrt [default: add,sub,mul,div]
+    --constants <BOOL>           Use random constants [default: true]
+    --min-depth <N>              Minimum depth of initial trees [default: 1]
+    --max-depth <N>              Maximum depth of initial trees [default: 4]
+    --max-height <N>             Reject offspring taller than this [default: 10]
+    --mutation-depth <N>         Maximum depth of subtrees made by mutation [default: 2]
+    --crossover-rate <P>         Chance of mating each pair of parents [default: 0.9]
+    --mutation-rate <P>          Chance of mutating each offspring [default: 0.1]
+    --crossover <KIND>           one-point, or leaf-biased to swap leaves 10% of the time
+                                 [default: one-point]
+    --mutation <KIND>            uniform or shrink [default: uniform]
+    --tournament <N>             Tournament size for selection [default: 7]
+    --elitism <N>                Best individuals copied to the next generation [default: 1]
+    --optimise-constants <MODE>  none, nelder-mead or one-plus-one [default: none]
+    --optimise-evaluations <N>   Cost evaluations per constant optimisation [default: 100]
+    --scaling <BOOL>             Linearly scale outputs onto the target [default: false]
+    --validation <FRACTION>      Fraction of samples held out for validation [default: 0.2]
+    --test <FRACTION>            Fraction of samples held out for testing [default: 0.2]
+    --seed <N>                   Seed for repeatable runs [default: random]
+    --logbook <FILE>             Write statistics of each generation to a CSV file
+    -h, --help                   Print this message
+";
+
+/// Settings for a run, from defaults, then a TOML config, then flags.
+#[derive(Debug)]
+struct Config {
+    data: Option<String>,
+    target: Option<String>,
+    delimiter: char,
+    population: usize,
+    generations: usize,
+    functions: Vec<Function>,
+    constants: bool,
+    min_depth: usize,
+    max_depth: usize,
+    max_height: usize,
+    mutation_depth: usize,
+    crossover_rate: f64,
+    mutation_rate: f64,
+    crossover: String,
+    mutation: String,
+    tournament: usize,
+    elitism: usize,
+    optimise_constants: String,
+    optimise_evaluations: usize,
+    scaling: bool,
+    validation: f64,
+    test: f64,
+    seed: Option<u64>,
+    logbook: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            data: None,
+            target: None,
+            delimiter: ',',
+            population: 500,
+            generations: 50,
+            functions: vec![Function::Add, Function::Sub, Function::Mul, Function::Div],
+            constants: true,
+            min_depth: 1,
+            max_depth: 4,
+            max_height: 10,
+            mutation_depth: 2,
+            crossover_rate: 0.9,
+            mutation_rate: 0.1,
+            crossover: "one-point".to_string(),
+            mutation: "uniform".to_string(),
+            tournament: 7,
+            elitism: 1,
+            optimise_constants: "none".to_string(),
+            optimise_evaluations: 100,
+            scaling: false,
+            validation: 0.2,
+            test: 0.2,
+            seed: None,
+            logbook: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the command line arguments. Returns `None` if help was asked for.
+    fn from_args(args: &[String]) -> Result<Option<Config>, String> {
+        let mut flags = vec![];
+        let mut config_path = None;
+        let mut data = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                return Ok(None);
+            }
+            if let Some(flag) = arg.strip_prefix("--") {
+                let (key, value) = match flag.find('=') {
+                    Some(i) => (flag[..i].to_string(), flag[i + 1..].to_string()),
+                    None => {
+                        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                        (flag.to_string(), value.clone())
+                    }
+                };
+                if key == "config" {
+                    config_path = Some(value);
+                } else {
+                    flags.push((key, value));
+                }
+            } else if data.is_none() {
+                data = Some(arg.clone());
+            } else {
+                return Err(format!("unexpected argument {:?}", arg));
+            }
+        }
+
+        let mut config = Config::default();
+        if let Some(path) = config_path {
+            config.load(&path)?;
+        }
+        for (key, value) in flags {
+            config.set(&key, &value)?;
+        }
+        if data.is_some() {
+            config.data = data;
+        }
+        Ok(Some(config))
+    }
+
+    /// Apply the options in a TOML file.
+    fn load(&mut self, path: &str) -> Result<(), String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("could not read {}: {}", path, err))?;
+        let table = match text.parse::<toml::Value>() {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => return Err(format!("{}: expected a table of options", path)),
+            Err(err) => return Err(format!("{}: {}", path, err)),
+        };
+        for (key, value) in &table {
+            let value = match *value {
+                toml::Value::String(ref value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                toml::Value::Array(ref values) => {
+                    let values: Option<Vec<&str>> = values.iter().map(|v| v.as_str()).collect();
+                    values.ok_or_else(|| format!("{}: {} must be a list of strings", path, key))?
+                        .join(",")
+                }
+                _ => return Err(format!("{}: unsupported value for {}", path, key)),
+            };
+            self.set(key, &value).map_err(|err| format!("{}: {}", path, err))?;
+        }
+        Ok(())
+    }
+
+    /// Set an option by name, accepting `_` in place of `-`.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key.replace('_', "-").as_str() {
+            "data" => self.data = Some(value.to_string()),
+            "target" => self.target = Some(value.to_string()),
+            "delimiter" => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(delimiter), None) => self.delimiter = delimiter,
+                    _ => return Err("delimiter must be a single character".to_string()),
+                }
+            }
+            "population" => self.population = parse(key, value)?,
+            "generations" => self.generations = parse(key, value)?,
+            "functions" => {
+                self.functions = value.split(',')
+                    .map(|name| {
+                        Function::from_name(name.trim())
+                            .ok_or_else(|| format!("unknown function {:?}", name.trim()))
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            "constants" => self.constants = parse(key, value)?,
+            "min-depth" => self.min_depth = parse(key, value)?,
+            "max-depth" => self.max_depth = parse(key, value)?,
+            "max-height" => self.max_height = parse(key, value)?,
+            "mutation-depth" => self.mutation_depth = parse(key, value)?,
+            "crossover-rate" => self.crossover_rate = parse(key, value)?,
+            "mutation-rate" => self.mutation_rate = parse(key, value)?,
+            "crossover" => {
+                match value {
+                    "one-point" | "leaf-biased" => self.crossover = value.to_string(),
+                    _ => return Err(format!("unknown crossover {:?}", value)),
+                }
+            }
+            "mutation" => {
+                match value {
+                    "uniform" | "shrink" => self.mutation = value.to_string(),
+                    _ => return Err(format!("unknown mutation {:?}", value)),
+                }
+            }
+            "tournament" => self.tournament = parse(key, value)?,
+            "elitism" => self.elitism = parse(key, value)?,
+            "optimise-constants" => {
+                match value {
+                    "none" | "nelder-mead" | "one-plus-one" => {
+                        self.optimise_constants = value.to_string()
+                    }
+                    _ => return Err(format!("unknown constant optimisation {:?}", value)),
+                }
+            }
+            "optimise-evaluations" => self.optimise_evaluations = parse(key, value)?,
+            "scaling" => self.scaling = parse(key, value)?,
+            "validation" => self.validation = parse(key, value)?,
+            "test" => self.test = parse(key, value)?,
+            "seed" => self.seed = Some(parse(key, value)?),
+            "logbook" => self.logbook = Some(value.to_string()),
+            _ => return Err(format!("unknown option {:?}", key)),
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.functions.is_empty() {
+            return Err("at least one function is needed".to_string());
+        }
+        if self.population < 2 || self.tournament < 1 || self.elitism > self.population {
+            return Err("need a population of at least 2, a tournament of at least 1 and \
+                        no more elites than the population"
+                .to_string());
+        }
+        if self.min_depth > self.max_depth {
+            return Err("min-depth must not exceed max-depth".to_string());
+        }
+        if self.mutation_depth < 1 {
+            return Err("mutation-depth must be at least 1".to_string());
+        }
+        if self.validation < 0.0 || self.test < 0.0 || self.validation + self.test >= 1.0 {
+            return Err("validation and test fractions must leave samples to train on"
+                .to_string());
+        }
+        Ok(())
+    }
+
+    fn crossover(&self) -> Crossover {
+        match self.crossover.as_str() {
+            // Koza's 90/10 rule.
+            "leaf-biased" => Crossover::one_point_leaf_biased(0.1),
+            _ => Crossover::one_point(),
+        }
+    }
+
+    fn mutation(&self) -> Mutation {
+        match self.mutation.as_str() {
+            "shrink" => Mutation::shrink(),
+            _ => Mutation::uniform(),
+        }
+    }
+
+    fn constant_optimisation(&self) -> Option<ConstantOptimisation> {
+        match self.optimise_constants.as_str() {
+            "nelder-mead" => Some(ConstantOptimisation::nelder_mead(self.optimise_evaluations)),
+            "one-plus-one" => Some(ConstantOptimisation::one_plus_one(self.optimise_evaluations)),
+            _ => None,
+        }
+    }
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("invalid value {:?} for {}", value, key))
+}
+
+/// Fit the scaling of outputs if enabled, and measure the MSE. Non-finite errors count as
+/// infinitely bad.
+fn fit(outputs: &[f64], targets: &[f64], scaling: bool) -> (LinearScaling, f64) {
+    let (scaling, error) = if scaling {
+        LinearScaling::fit_mse(outputs, targets)
+    } else {
+        (LinearScaling {
+             slope: 1.0,
+             intercept: 0.0,
+         },
+         mse(outputs, targets))
+    };
+    (scaling, if error.is_finite() { error } else { f64::INFINITY })
+}
+
+/// Measure an equation on a dataset with scaling fitted elsewhere.
+fn measure(dataset: &Dataset, tree: &BoxTree<Equation>, scaling: LinearScaling) -> (f64, f64) {
+    if dataset.is_empty() {
+        return (f64::NAN, f64::NAN);
+    }
+    let outputs: Vec<f64> = dataset.outputs(tree).into_iter().map(|o| scaling.apply(o)).collect();
+    (mse(&outputs, dataset.targets()), r_squared(&outputs, dataset.targets()))
+}
+
+fn run(config: &Config) -> Result<(), String> {
+    config.check()?;
+    let path = config.data.as_ref().ok_or("no CSV file given; see --help")?;
+    let mut csv = Csv::new().delimiter(config.delimiter);
+    if let Some(ref target) = config.target {
+        csv = csv.target(target);
+    }
+    let dataset = csv.load(path).map_err(|err| format!("{}: {}", path, err))?;
+
+    let seed = match config.seed {
+        Some(seed) => seed,
+        None => OsRng::new().map_err(|err| format!("could not seed: {}", err))?.gen(),
+    };
+    let mut rng: StdRng = SeedableRng::from_seed(&[seed as usize, (seed >> 32) as usize][..]);
+    let split = dataset.split(config.validation, config.test, &mut rng);
+
+    let mut primitives = PrimitiveSet::all(dataset.variables())
+        .constant(if config.constants { 1 } else { 0 });
+    for &function in Function::ALL.iter().filter(|f| !config.functions.contains(f)) {
+        primitives = primitives.function(function, 0);
+    }
+    let initialisation = Initialisation::ramped_half_and_half(config.min_depth, config.max_depth)
+        .with_weights(primitives.branch_weights(), primitives.leaf_weights());
+    let mut population: Vec<Individual<Equation>> =
+        initialisation.unique_population(config.population, 10, &mut rng);
+    let mut tg = TreeGen::full(&mut rng, 1, config.mutation_depth)
+        .with_weights(primitives.branch_weights(), primitives.leaf_weights());
+    let crossover = config.crossover();
+    let mutation = config.mutation();
+    let generational = Generational::new(Selection::tournament(config.tournament))
+        .elitism(config.elitism);
+    let optimisation = config.constant_optimisation();
+
+    let mut logbook = match config.logbook {
+        Some(ref path) => {
+            let mut file = File::create(path)
+                .map_err(|err| format!("could not create {}: {}", path, err))?;
+            writeln!(file,
+                     "generation,unique_evaluations,best_train_mse,median_train_mse,\
+                      best_validation_mse,best_nodes,mean_nodes")
+                .map_err(|err| err.to_string())?;
+            Some(file)
+        }
+        None => None,
+    };
+
+    let train = &split.train;
+    let mut cache = EvaluationCache::new();
+    let mut subtree_cache = SubtreeCache::new();
+    // The best individual of any generation, judged on validation samples if there are any.
+    let mut best_of_run: Option<(f64, Individual<Equation>, LinearScaling)> = None;
+    let mut previous_misses = 0;
+    for generation in 0..config.generations + 1 {
+        if let Some(optimisation) = optimisation {
+            for indv in &mut population {
+                optimisation.optimise_individual(indv,
+                                                 |tree| {
+                                                     let outputs = train.outputs(tree);
+                                                     fit(&outputs, train.targets(),
+                                                         config.scaling)
+                                                         .1
+                                                 },
+                                                 &mut tg);
+            }
+        }
+        // Subtrees from earlier generations are rarely seen again, so keep memory bounded.
+        subtree_cache.clear();
+        let fitted: Vec<(LinearScaling, f64)> = population.iter()
+            .map(|indv| {
+                cache.get_or_insert_with(&indv.tree, |tree| {
+                    let outputs = subtree_cache.evaluate_batch(tree, train.inputs());
+                    fit(&outputs, train.targets(), config.scaling)
+                })
+            })
+            .collect();
+        let errors: Vec<f64> = fitted.iter().map(|&(_, error)| error).collect();
+        // Only cache misses are evaluated; repeats of an equation are not.
+        let unique_evaluations = cache.misses() - previous_misses;
+        previous_misses = cache.misses();
+
+        let ranking = ranking(&errors);
+        let best = ranking[0];
+        let validation_error = measure(&split.validation, &population[best].tree, fitted[best].0)
+            .0;
+        let judged = if split.validation.is_empty() {
+            errors[best]
+        } else {
+            validation_error
+        };
+        let improved = match best_of_run {
+            Some((error, _, _)) => judged < error,
+            None => true,
+        };
+        if improved {
+            best_of_run = Some((judged, population[best].clone(), fitted[best].0));
+        }
+
+        let mean_nodes = population.iter().map(|indv| indv.nodes_count()).sum::<usize>() as f64 /
+                         population.len() as f64;
+        eprintln!("generation {}: best train MSE {}, validation MSE {}, {} nodes",
+                  generation,
+                  errors[best],
+                  validation_error,
+                  population[best].nodes_count());
+        if let Some(ref mut file) = logbook {
+            writeln!(file,
+                     "{},{},{},{},{},{},{}",
+                     generation,
+                     unique_evaluations,
+                     errors[best],
+                     errors[ranking[ranking.len() / 2]],
+                     validation_error,
+                     population[best].nodes_count(),
+                     mean_nodes)
+                .map_err(|err| err.to_string())?;
+        }
+        if generation == config.generations {
+            break;
+        }
+
+        population = generational.next(&population, &errors, &mut tg, |parent1, parent2, tg| {
+            let (mut child1, mut child2) = (parent1.clone(), parent2.clone());
+            if tg.gen::<f64>() < config.crossover_rate {
+                crossover.mate(&mut child1, &mut child2, &mut **tg);
+            }
+            for child in &mut [&mut child1, &mut child2] {
+                if tg.gen::<f64>() < config.mutation_rate {
+                    mutation.mutate(child, tg);
+                }
+            }
+            // Offspring which grow too tall are replaced by their parents.
+            if child1.height() > config.max_height {
+                child1 = parent1.clone();
+            }
+            if child2.height() > config.max_height {
+                child2 = parent2.clone();
+            }
+            (child1, child2)
+        });
+    }
+
+    let (_, best, scaling) = best_of_run.expect("there is at least one generation");
+    let simplified = best.tree.simplify();
+    println!("seed: {}", seed);
+    let names = dataset.names();
+    if names.iter().enumerate().any(|(i, name)| *name != format!("x{}", i)) {
+        let legend: Vec<String> =
+            names.iter().enumerate().map(|(i, name)| format!("x{} = {}", i, name)).collect();
+        println!("variables: {}", legend.join(", "));
+    }
+    if config.scaling {
+        println!("equation: {}", scaling.display(&simplified));
+    } else {
+        println!("equation: {}", simplified);
+    }
+    for &(name, set) in &[("train", &split.train),
+                              ("validation", &split.validation),
+                              ("test", &split.test)] {
+        if !set.is_empty() {
+            let (error, r2) = measure(set, &best.tree, scaling);
+            println!("{} MSE: {}    R²: {}    samples: {}", name, error, r2, set.len());
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = Config::from_args(&args).and_then(|config| match config {
+        Some(config) => run(&config),
+        None => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+    });
+    if let Err(err) = result {
+        eprintln!("evco: {}", err);
+        process::exit(2);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Result<Config, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Config::from_args(&args).map(|config| config.expect("help was not asked for"))
+    }
+
+    #[test]
+    fn flags_override_defaults() {
+        let config = config(&["data.csv", "--population=20", "--functions", "add, sin",
+                              "--optimise_constants", "nelder-mead", "--seed", "49",
+                              "--crossover", "leaf-biased", "--mutation=shrink"])
+            .unwrap();
+        assert_eq!(config.data, Some("data.csv".to_string()));
+        assert_eq!(config.population, 20);
+        assert_eq!(config.functions, vec![Function::Add, Function::Sin]);
+        assert!(config.constant_optimisation().is_some());
+        assert_eq!(config.crossover(), Crossover::one_point_leaf_biased(0.1));
+        assert_eq!(config.mutation(), Mutation::shrink());
+        assert_eq!(Config::default().crossover(), Crossover::one_point());
+        assert_eq!(Config::default().mutation(), Mutation::uniform());
+        assert_eq!(config.seed, Some(49));
+        assert_eq!(config.generations, Config::default().generations);
+        assert!(config.check().is_ok());
+    }
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        assert!(Config::from_args(&["--help".to_string()]).unwrap().is_none());
+        assert!(config(&["--population"]).is_err());
+        assert!(config(&["--population", "many"]).is_err());
+        assert!(config(&["--functions", "add,tan"]).is_err());
+        assert!(config(&["--delimiter", ";;"]).is_err());
+        assert!(config(&["--crossover", "two-point"]).is_err());
+        assert!(config(&["--mutation", "insert"]).is_err());
+        assert!(config(&["--unknown", "1"]).is_err());
+        assert!(config(&["a.csv", "b.csv"]).is_err());
+    }
+
+    #[test]
+    fn invalid_configs_fail_the_check() {
+        for args in &[&["--population", "1"][..],
+                      &["--tournament", "0"],
+                      &["--min-depth", "5"],
+                      &["--mutation-depth", "0"],
+                      &["--validation", "0.5", "--test", "0.5"]] {
+            assert!(config(args).unwrap().check().is_err(), "{:?} should fail", args);
+        }
+        assert!(config(&["--mutation-depth", "1"]).unwrap().check().is_ok());
+    }
+}
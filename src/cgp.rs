@@ -0,0 +1,682 @@
+use gp::tree::*;
+use rand::Rng;
+use std::fmt;
+use std::rc::Rc;
+
+/// The shape of a Cartesian GP grid, shared by every program in a population.
+///
+/// Addresses `0..inputs.len()` refer to the inputs and the following addresses to the nodes,
+/// numbered down each column in turn. A node may only read from inputs and from nodes up
+/// to `levels_back` columns to its left, so programs are always acyclic.
+#[derive(Clone, Debug)]
+pub struct CgpLayout<O> {
+    inputs: Vec<O>,
+    rows: usize,
+    columns: usize,
+    levels_back: usize,
+    arity: usize,
+    outputs: usize,
+}
+
+impl<O> CgpLayout<O> {
+    /// Create a layout with a `rows` by `columns` grid of nodes.
+    ///
+    /// `inputs` are opcodes taking no arguments, such as reading an input or a constant,
+    /// executed once per evaluation. Nodes can read from every column to their left, take
+    /// up to two arguments and there is one output, unless configured otherwise.
+    pub fn new(inputs: Vec<O>, rows: usize, columns: usize) -> CgpLayout<O> {
+        assert!(!inputs.is_empty(), "need at least one input");
+        CgpLayout {
+            inputs,
+            rows,
+            columns,
+            levels_back: columns,
+            arity: 2,
+            outputs: 1,
+        }
+    }
+
+    /// Set how many columns to the left a node may read from.
+    pub fn levels_back(mut self, levels_back: usize) -> CgpLayout<O> {
+        assert!(levels_back > 0, "levels back must be at least 1");
+        self.levels_back = levels_back;
+        self
+    }
+
+    /// Set the number of connection genes per node, the most arguments an operator takes.
+    pub fn arity(mut self, arity: usize) -> CgpLayout<O> {
+        self.arity = arity;
+        self
+    }
+
+    /// Set the number of outputs.
+    pub fn outputs(mut self, outputs: usize) -> CgpLayout<O> {
+        assert!(outputs > 0, "need at least one output");
+        self.outputs = outputs;
+        self
+    }
+
+    /// Get the input opcodes.
+    pub fn inputs(&self) -> &[O] {
+        &self.inputs
+    }
+
+    /// Count the nodes in the grid.
+    pub fn count_nodes(&self) -> usize {
+        self.rows * self.columns
+    }
+
+    /// Generate a random address a node may read from, other than `current` if another is
+    /// in range.
+    fn random_connection<R: Rng>(&self,
+                                 rng: &mut R,
+                                 node: usize,
+                                 current: Option<usize>)
+                                 -> usize {
+        let column = node / self.rows;
+        let first_column = column.saturating_sub(self.levels_back);
+        let first = first_column * self.rows;
+        let last = column * self.rows;
+        // Pick among the inputs and the nodes in range, which are contiguous addresses.
+        let current = current.map(|address| if address < self.inputs.len() {
+            address
+        } else {
+            address - first
+        });
+        let choice = choose_except(rng, self.inputs.len() + last - first, current);
+        if choice < self.inputs.len() {
+            choice
+        } else {
+            first + choice
+        }
+    }
+
+    /// Generate a random address an output may read from, other than `current` if there is
+    /// another.
+    fn random_output<R: Rng>(&self, rng: &mut R, current: Option<usize>) -> usize {
+        choose_except(rng, self.inputs.len() + self.count_nodes(), current)
+    }
+}
+
+/// Choose a random index below `count`, other than `current` unless it is the only one.
+fn choose_except<R: Rng>(rng: &mut R, count: usize, current: Option<usize>) -> usize {
+    match current {
+        Some(current) if count > 1 => {
+            let choice = rng.gen_range(0, count - 1);
+            if choice < current { choice } else { choice + 1 }
+        }
+        _ => rng.gen_range(0, count),
+    }
+}
+
+/// A node of a Cartesian GP grid: a function gene and its connection genes.
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub struct CgpNode<O> {
+    /// The node's opcode.
+    pub op: O,
+    /// How many of the connection genes the opcode reads.
+    pub arity: usize,
+    /// Addresses the node reads its arguments from. There is one per `CgpLayout::arity`,
+    /// but only the first `arity` are used.
+    pub connections: Vec<usize>,
+}
+
+/// A Cartesian GP program: a fixed-size grid of nodes described by integer genes.
+///
+/// Only nodes which an output depends on are active and evaluated, so the genotype never
+/// grows and inactive genes can drift neutrally. Nodes are `Operators` opcodes, so the
+/// primitives of a tree GP can be reused for Cartesian GP.
+#[derive(Clone, Debug)]
+pub struct CgpProgram<T>
+    where T: Operators
+{
+    layout: Rc<CgpLayout<T::Op>>,
+    nodes: Vec<CgpNode<T::Op>>,
+    outputs: Vec<usize>,
+}
+
+impl<T> CgpProgram<T>
+    where T: Operators
+{
+    /// Generate a random program for a layout.
+    pub fn random<R: Rng>(layout: Rc<CgpLayout<T::Op>>, rng: &mut R) -> CgpProgram<T> {
+        let nodes = (0..layout.count_nodes())
+            .map(|node| random_node::<T, R>(&layout, rng, node))
+            .collect();
+        let outputs = (0..layout.outputs).map(|_| layout.random_output(rng, None)).collect();
+        CgpProgram {
+            layout,
+            nodes,
+            outputs,
+        }
+    }
+
+    /// Get the layout.
+    pub fn layout(&self) -> &Rc<CgpLayout<T::Op>> {
+        &self.layout
+    }
+
+    /// Get the nodes, in address order.
+    pub fn nodes(&self) -> &[CgpNode<T::Op>] {
+        &self.nodes
+    }
+
+    /// Get the addresses each output reads from.
+    pub fn outputs(&self) -> &[usize] {
+        &self.outputs
+    }
+
+    /// Find which nodes an output depends on.
+    pub fn active(&self) -> Vec<bool> {
+        let inputs = self.layout.inputs.len();
+        let mut active = vec![false; self.nodes.len()];
+        let mut pending: Vec<usize> = self.outputs.clone();
+        while let Some(address) = pending.pop() {
+            if address < inputs || active[address - inputs] {
+                continue;
+            }
+            let node = &self.nodes[address - inputs];
+            active[address - inputs] = true;
+            pending.extend_from_slice(&node.connections[..node.arity]);
+        }
+        active
+    }
+
+    /// Count the nodes an output depends on.
+    pub fn count_active(&self) -> usize {
+        self.active().into_iter().filter(|&active| active).count()
+    }
+
+    /// Evaluate the first output, as `Tree::evaluate` does for trees.
+    pub fn evaluate(&self, env: &T::Environment) -> T::Action
+        where T::Action: Clone
+    {
+        self.evaluate_outputs(env).swap_remove(0)
+    }
+
+    /// Evaluate every output.
+    pub fn evaluate_outputs(&self, env: &T::Environment) -> Vec<T::Action>
+        where T::Action: Clone
+    {
+        let inputs = self.layout.inputs.len();
+        let mut values: Vec<Option<T::Action>> =
+            self.layout.inputs.iter().map(|op| Some(T::execute(op, env, &[]))).collect();
+        values.resize(inputs + self.nodes.len(), None);
+        let mut args = Vec::with_capacity(self.layout.arity);
+        for (i, active) in self.active().into_iter().enumerate() {
+            if !active {
+                continue;
+            }
+            let node = &self.nodes[i];
+            args.clear();
+            args.extend(node.connections[..node.arity].iter().map(|&address| {
+                values[address].clone().expect("nodes only read from earlier addresses")
+            }));
+            values[inputs + i] = Some(T::execute(&node.op, env, &args));
+        }
+        self.outputs
+            .iter()
+            .map(|&address| values[address].clone().expect("outputs read active nodes"))
+            .collect()
+    }
+}
+
+impl<T> fmt::Display for CgpProgram<T>
+    where T: Operators
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inputs = self.layout.inputs.len();
+        for (i, op) in self.layout.inputs.iter().enumerate() {
+            writeln!(f, "n{} = {:?}", i, op)?;
+        }
+        for (i, (node, active)) in self.nodes.iter().zip(self.active()).enumerate() {
+            if !active {
+                continue;
+            }
+            write!(f, "n{} = {:?}", inputs + i, node.op)?;
+            let connections: Vec<String> = node.connections[..node.arity]
+                .iter()
+                .map(|address| format!("n{}", address))
+                .collect();
+            if !connections.is_empty() {
+                write!(f, "({})", connections.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        for (i, address) in self.outputs.iter().enumerate() {
+            writeln!(f, "out{} = n{}", i, address)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generate a random node for an address in the grid.
+fn random_node<T, R>(layout: &CgpLayout<T::Op>, rng: &mut R, node: usize) -> CgpNode<T::Op>
+    where T: Operators,
+          R: Rng
+{
+    let Instruction { op, arity } = random_op::<T, R>(layout, rng);
+    CgpNode {
+        op,
+        arity,
+        connections: (0..layout.arity)
+            .map(|_| layout.random_connection(rng, node, None))
+            .collect(),
+    }
+}
+
+fn random_op<T, R>(layout: &CgpLayout<T::Op>, rng: &mut R) -> Instruction<T::Op>
+    where T: Operators,
+          R: Rng
+{
+    let instruction = T::random_op(rng);
+    assert!(instruction.arity <= layout.arity,
+            "operator takes more arguments than the layout's arity");
+    instruction
+}
+
+/// The CGP mutation mode in use. See `CgpMutation`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum CgpMutationMode {
+    /// Corresponds to `CgpMutation::point`.
+    Point(f64),
+    /// Corresponds to `CgpMutation::single_active`.
+    SingleActive,
+}
+
+/// Configures mutation of Cartesian GP programs.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct CgpMutation {
+    mode: CgpMutationMode,
+}
+
+impl CgpMutation {
+    /// Replace each gene, with `probability`, by a random valid value. Connection and output
+    /// genes are given a different value whenever another is valid.
+    pub fn point(probability: f64) -> CgpMutation {
+        CgpMutation { mode: CgpMutationMode::Point(probability) }
+    }
+
+    /// Replace random genes until one that affects the outputs has been replaced.
+    ///
+    /// This avoids tuning a mutation rate, and usually changes the program's behaviour or
+    /// active structure. Connection and output genes are given a different value whenever
+    /// another is valid, but a function gene may be redrawn as the same operator.
+    pub fn single_active() -> CgpMutation {
+        CgpMutation { mode: CgpMutationMode::SingleActive }
+    }
+
+    /// Mutate a program according to the configured mutation mode.
+    pub fn mutate<T, R>(&self, program: &mut CgpProgram<T>, mut rng: R)
+        where T: Operators,
+              R: Rng
+    {
+        let genes_per_node = 1 + program.layout.arity;
+        let genes = program.nodes.len() * genes_per_node + program.outputs.len();
+        match self.mode {
+            CgpMutationMode::Point(probability) => {
+                for gene in 0..genes {
+                    if rng.gen::<f64>() < probability {
+                        mutate_gene(program, gene, &mut rng);
+                    }
+                }
+            }
+            CgpMutationMode::SingleActive => {
+                loop {
+                    let active = program.active();
+                    let gene = rng.gen_range(0, genes);
+                    let node = gene / genes_per_node;
+                    let affects_outputs = match node.checked_sub(program.nodes.len()) {
+                        Some(_) => true,
+                        None => {
+                            let connection = gene % genes_per_node;
+                            active[node] && connection <= program.nodes[node].arity
+                        }
+                    };
+                    mutate_gene(program, gene, &mut rng);
+                    if affects_outputs {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Replace a gene by a random valid value, different for connection and output genes
+/// whenever another is valid. Genes are numbered by node, each node's function
+/// gene followed by its connection genes, and then the output genes.
+fn mutate_gene<T, R>(program: &mut CgpProgram<T>, gene: usize, rng: &mut R)
+    where T: Operators,
+          R: Rng
+{
+    let layout = &program.layout;
+    let genes_per_node = 1 + layout.arity;
+    let node = gene / genes_per_node;
+    if node >= program.nodes.len() {
+        let output = gene - program.nodes.len() * genes_per_node;
+        program.outputs[output] = layout.random_output(rng, Some(program.outputs[output]));
+        return;
+    }
+    match gene % genes_per_node {
+        0 => {
+            let Instruction { op, arity } = random_op::<T, R>(layout, rng);
+            program.nodes[node].op = op;
+            program.nodes[node].arity = arity;
+        }
+        connection => {
+            let current = program.nodes[node].connections[connection - 1];
+            program.nodes[node].connections[connection - 1] =
+                layout.random_connection(rng, node, Some(current));
+        }
+    }
+}
+
+/// The (1+λ) evolution strategy usually used with Cartesian GP.
+///
+/// Each generation `lambda` mutated copies of the parent are made, and the best replaces the
+/// parent if its cost is no worse. Accepting equal costs lets inactive genes drift.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct OnePlusLambda {
+    lambda: usize,
+    mutation: CgpMutation,
+}
+
+impl OnePlusLambda {
+    /// Create a (1+λ) strategy making `lambda` offspring per generation with `mutation`.
+    pub fn new(lambda: usize, mutation: CgpMutation) -> OnePlusLambda {
+        assert!(lambda > 0, "need at least one offspring per generation");
+        OnePlusLambda {
+            lambda,
+            mutation,
+        }
+    }
+
+    /// Create the standard (1+4) strategy using `CgpMutation::single_active`.
+    pub fn standard() -> OnePlusLambda {
+        Self::new(4, CgpMutation::single_active())
+    }
+
+    /// Run one generation. `cost` is minimised. Returns the cost of the new parent.
+    pub fn step<T, R, F>(&self,
+                         parent: &mut CgpProgram<T>,
+                         parent_cost: f64,
+                         mut cost: F,
+                         mut rng: R)
+                         -> f64
+        where T: Operators,
+              R: Rng,
+              F: FnMut(&CgpProgram<T>) -> f64
+    {
+        let mut best: Option<(CgpProgram<T>, f64)> = None;
+        for _ in 0..self.lambda {
+            let mut offspring = parent.clone();
+            self.mutation.mutate(&mut offspring, &mut rng);
+            let offspring_cost = cost(&offspring);
+            let better = match best {
+                Some((_, best_cost)) => offspring_cost <= best_cost,
+                None => true,
+            };
+            if better {
+                best = Some((offspring, offspring_cost));
+            }
+        }
+        match best {
+            Some((offspring, offspring_cost)) if offspring_cost <= parent_cost => {
+                *parent = offspring;
+                offspring_cost
+            }
+            _ => parent_cost,
+        }
+    }
+
+    /// Run `generations` generations from `parent`. Returns the final parent's cost.
+    pub fn run<T, R, F>(&self,
+                        parent: &mut CgpProgram<T>,
+                        generations: usize,
+                        mut cost: F,
+                        mut rng: R)
+                        -> f64
+        where T: Operators,
+              R: Rng,
+              F: FnMut(&CgpProgram<T>) -> f64
+    {
+        let mut parent_cost = cost(parent);
+        for _ in 0..generations {
+            parent_cost = self.step(parent, parent_cost, &mut cost, &mut rng);
+        }
+        parent_cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    /// Sums of an input and ones, negated.
+    #[derive(Clone, Debug)]
+    enum Calc {
+        X,
+        One,
+        Add(BoxTree<Calc>, BoxTree<Calc>),
+        Neg(BoxTree<Calc>),
+    }
+
+    #[derive(PartialEq, Clone, Copy, Debug)]
+    enum CalcOp {
+        X,
+        One,
+        Add,
+        Neg,
+    }
+
+    impl Tree for Calc {
+        type Environment = f64;
+        type Action = f64;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Calc> {
+            let child = Self::child(tg, current_depth + 1);
+            if tg.gen() {
+                Calc::Neg(child).into()
+            } else {
+                Calc::Add(child, Self::child(tg, current_depth + 1)).into()
+            }
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Calc> {
+            if tg.gen() { Calc::X } else { Calc::One }.into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Calc>> {
+            match *self {
+                Calc::X | Calc::One => vec![],
+                Calc::Add(ref left, ref right) => vec![left, right],
+                Calc::Neg(ref child) => vec![child],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Calc>> {
+            match *self {
+                Calc::X | Calc::One => vec![],
+                Calc::Add(ref mut left, ref mut right) => vec![left, right],
+                Calc::Neg(ref mut child) => vec![child],
+            }
+        }
+
+        fn evaluate(&self, env: &f64) -> f64 {
+            let args: Vec<f64> = self.children().iter().map(|child| child.evaluate(env)).collect();
+            Calc::execute(&self.op(), env, &args)
+        }
+    }
+
+    impl Compile for Calc {
+        type Op = CalcOp;
+
+        fn op(&self) -> CalcOp {
+            match *self {
+                Calc::X => CalcOp::X,
+                Calc::One => CalcOp::One,
+                Calc::Add(..) => CalcOp::Add,
+                Calc::Neg(..) => CalcOp::Neg,
+            }
+        }
+
+        fn execute(op: &CalcOp, env: &f64, args: &[f64]) -> f64 {
+            match *op {
+                CalcOp::X => *env,
+                CalcOp::One => 1.0,
+                CalcOp::Add => args[0] + args[1],
+                CalcOp::Neg => -args[0],
+            }
+        }
+    }
+
+    impl Operators for Calc {
+        fn random_op<R: Rng>(rng: &mut R) -> Instruction<CalcOp> {
+            if rng.gen() {
+                Instruction {
+                    op: CalcOp::Add,
+                    arity: 2,
+                }
+            } else {
+                Instruction {
+                    op: CalcOp::Neg,
+                    arity: 1,
+                }
+            }
+        }
+    }
+
+    fn layout(rows: usize, columns: usize) -> Rc<CgpLayout<CalcOp>> {
+        Rc::new(CgpLayout::new(vec![CalcOp::X, CalcOp::One], rows, columns))
+    }
+
+    fn node(op: CalcOp, connections: &[usize]) -> CgpNode<CalcOp> {
+        CgpNode {
+            op,
+            arity: if op == CalcOp::Add { 2 } else { 1 },
+            connections: connections.to_vec(),
+        }
+    }
+
+    /// `n4 = (x + 1) + (x + 1)`, with an inactive `n3`.
+    fn two_x_plus_two() -> CgpProgram<Calc> {
+        CgpProgram {
+            layout: layout(1, 3),
+            nodes: vec![node(CalcOp::Add, &[0, 1]),
+                        node(CalcOp::Neg, &[0, 0]),
+                        node(CalcOp::Add, &[2, 2])],
+            outputs: vec![4],
+        }
+    }
+
+    /// Connection and output genes of `program`, with their node and connection index, or
+    /// `None` for outputs.
+    fn connection_genes(program: &CgpProgram<Calc>) -> Vec<(Option<(usize, usize)>, usize)> {
+        let mut genes = vec![];
+        for (i, node) in program.nodes().iter().enumerate() {
+            genes.extend(node.connections.iter().enumerate().map(|(j, &address)| {
+                (Some((i, j)), address)
+            }));
+        }
+        genes.extend(program.outputs().iter().map(|&address| (None, address)));
+        genes
+    }
+
+    #[test]
+    fn evaluate_active_nodes() {
+        let program = two_x_plus_two();
+        assert_eq!(program.active(), vec![true, false, true]);
+        assert_eq!(program.count_active(), 2);
+        assert_eq!(program.evaluate(&3.0), 8.0);
+        assert_eq!(program.to_string(),
+                   "n0 = X\nn1 = One\nn2 = Add(n0, n1)\nn4 = Add(n2, n2)\nout0 = n4\n");
+    }
+
+    #[test]
+    fn connections_respect_levels_back() {
+        let layout = Rc::new(CgpLayout::new(vec![CalcOp::X, CalcOp::One], 2, 5).levels_back(2));
+        let mut rng = StdRng::from_seed(&[40usize][..]);
+        for _ in 0..50 {
+            let program = CgpProgram::<Calc>::random(layout.clone(), &mut rng);
+            for (gene, address) in connection_genes(&program) {
+                match gene {
+                    Some((node, _)) => {
+                        let column = node / 2;
+                        let first = column.saturating_sub(2) * 2;
+                        assert!(address < 2 || (first <= address - 2 && address - 2 < column * 2));
+                    }
+                    None => assert!(address < 12),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn choose_except_never_repeats() {
+        let mut rng = StdRng::from_seed(&[40usize][..]);
+        let mut seen = vec![false; 5];
+        for _ in 0..200 {
+            let choice = choose_except(&mut rng, 5, Some(2));
+            assert!(choice != 2);
+            seen[choice] = true;
+        }
+        assert_eq!(seen, vec![true, true, false, true, true]);
+        assert_eq!(choose_except(&mut rng, 1, Some(0)), 0);
+    }
+
+    #[test]
+    fn single_active_changes_one_active_gene() {
+        let mut rng = StdRng::from_seed(&[40usize][..]);
+        let mut program = CgpProgram::<Calc>::random(layout(2, 4), &mut rng);
+        let mut changed = 0;
+        for _ in 0..200 {
+            let parent = program.clone();
+            CgpMutation::single_active().mutate(&mut program, &mut rng);
+            let active = parent.active();
+            let changed_active = connection_genes(&parent)
+                .into_iter()
+                .zip(connection_genes(&program))
+                .filter(|&((gene, before), (_, after))| {
+                    let read = match gene {
+                        Some((node, connection)) => {
+                            active[node] && connection < parent.nodes[node].arity
+                        }
+                        None => true,
+                    };
+                    read && before != after
+                })
+                .count();
+            let changed_ops = parent.nodes
+                .iter()
+                .zip(&program.nodes)
+                .zip(&active)
+                .filter(|&((before, after), &active)| active && before.op != after.op)
+                .count();
+            assert!(changed_active + changed_ops <= 1);
+            changed += changed_active + changed_ops;
+        }
+        // Only redrawing an active node's operator as itself leaves the active genes as they
+        // were, and there are two operators to choose from.
+        assert!(changed > 150, "{}", changed);
+    }
+
+    #[test]
+    fn one_plus_lambda_improves() {
+        let mut rng = StdRng::from_seed(&[40usize][..]);
+        let mut parent = CgpProgram::<Calc>::random(layout(1, 10), &mut rng);
+        let cost = |program: &CgpProgram<Calc>| {
+            (-2..3).map(|x| (program.evaluate(&(x as f64)) - (2 * x + 2) as f64).abs()).sum()
+        };
+        let initial = cost(&parent);
+        let best = OnePlusLambda::standard().run(&mut parent, 500, cost, &mut rng);
+        assert!(best <= initial);
+        assert_eq!(best, 0.0);
+        assert_eq!(cost(&parent), best);
+    }
+}
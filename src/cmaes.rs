@@ -0,0 +1,556 @@
+use ga::RealVector;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+
+/// Covariance Matrix Adaptation Evolution Strategy, which minimises an objective over real
+/// vectors.
+///
+/// Candidates are sampled from a multivariate normal distribution. Each generation its mean
+/// moves towards the best candidates, its covariance matrix learns the shape of the
+/// objective and its step size grows or shrinks with progress. Use `ask` to sample
+/// candidates and `tell` to report their costs, or `Ipop` to run with restarts.
+#[derive(Clone, Debug)]
+pub struct CmaEs {
+    dimension: usize,
+    lambda: usize,
+    weights: Vec<f64>,
+    mueff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    chi_n: f64,
+    mean: Vec<f64>,
+    sigma: f64,
+    covariance: Vec<Vec<f64>>,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    /// Eigenvectors of the covariance matrix, as columns.
+    eigenvectors: Vec<Vec<f64>>,
+    /// Square roots of the eigenvalues of the covariance matrix.
+    eigenvalue_roots: Vec<f64>,
+    /// The covariance matrix's inverse square root.
+    inverse_root: Vec<Vec<f64>>,
+    /// Evaluations when the eigendecomposition was last updated.
+    eigen_evaluations: usize,
+    evaluations: usize,
+    generation: usize,
+    /// Best cost of each recent generation, for `StopReason::TolFun`.
+    recent_best: Vec<f64>,
+    best: Option<(RealVector, f64)>,
+}
+
+impl CmaEs {
+    /// Start from `mean` with step size `sigma`, using the default population size for the
+    /// dimension.
+    ///
+    /// `sigma` should be about a third of the distance the optimum might be from `mean`.
+    pub fn new(mean: Vec<f64>, sigma: f64) -> CmaEs {
+        let dimension = mean.len();
+        assert!(dimension > 0, "CMA-ES needs at least one dimension");
+        let lambda = 4 + (3.0 * (dimension as f64).ln()).floor() as usize;
+        Self::with_population_size(mean, sigma, lambda)
+    }
+
+    /// Start from `mean` with step size `sigma`, sampling `lambda` candidates per generation.
+    pub fn with_population_size(mean: Vec<f64>, sigma: f64, lambda: usize) -> CmaEs {
+        let dimension = mean.len();
+        assert!(dimension > 0, "CMA-ES needs at least one dimension");
+        assert!(lambda >= 2, "CMA-ES needs at least two candidates per generation");
+        assert!(sigma > 0.0, "CMA-ES needs a positive step size");
+        let n = dimension as f64;
+
+        let mu = lambda / 2;
+        let raw: Vec<f64> =
+            (0..mu).map(|i| (mu as f64 + 0.5).ln() - ((i + 1) as f64).ln()).collect();
+        let total: f64 = raw.iter().sum();
+        let weights: Vec<f64> = raw.iter().map(|w| w / total).collect();
+        let mueff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        let cc = (4.0 + mueff / n) / (n + 4.0 + 2.0 * mueff / n);
+        let cs = (mueff + 2.0) / (n + mueff + 5.0);
+        let c1 = 2.0 / ((n + 1.3).powi(2) + mueff);
+        let cmu = (1.0 - c1).min(2.0 * (mueff - 2.0 + 1.0 / mueff) / ((n + 2.0).powi(2) + mueff));
+        let damps = 1.0 + 2.0 * (((mueff - 1.0) / (n + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        let chi_n = n.sqrt() * (1.0 - 1.0 / (4.0 * n) + 1.0 / (21.0 * n * n));
+
+        CmaEs {
+            dimension,
+            lambda,
+            weights,
+            mueff,
+            cc,
+            cs,
+            c1,
+            cmu,
+            damps,
+            chi_n,
+            mean,
+            sigma,
+            covariance: identity(dimension),
+            pc: vec![0.0; dimension],
+            ps: vec![0.0; dimension],
+            eigenvectors: identity(dimension),
+            eigenvalue_roots: vec![1.0; dimension],
+            inverse_root: identity(dimension),
+            eigen_evaluations: 0,
+            evaluations: 0,
+            generation: 0,
+            recent_best: vec![],
+            best: None,
+        }
+    }
+
+    /// Sample a generation of candidates.
+    pub fn ask<R: Rng>(&mut self, rng: &mut R) -> Vec<RealVector> {
+        self.update_eigendecomposition();
+        let normal = Normal::new(0.0, 1.0);
+        (0..self.lambda)
+            .map(|_| {
+                let scaled: Vec<f64> =
+                    self.eigenvalue_roots.iter().map(|d| d * normal.ind_sample(rng)).collect();
+                let step = multiply(&self.eigenvectors, &scaled);
+                RealVector::new(self.mean
+                    .iter()
+                    .zip(step)
+                    .map(|(m, s)| m + self.sigma * s)
+                    .collect())
+            })
+            .collect()
+    }
+
+    /// Update the distribution from the costs of a generation of candidates from `ask`.
+    /// Lower costs are better.
+    pub fn tell(&mut self, candidates: &[RealVector], costs: &[f64]) {
+        assert_eq!(candidates.len(), costs.len(), "need one cost per candidate");
+        assert!(candidates.len() >= self.weights.len(),
+                "need at least as many candidates as ask gives");
+        let n = self.dimension as f64;
+        self.evaluations += candidates.len();
+        self.generation += 1;
+
+        // NaN costs sort last.
+        let mut order: Vec<usize> = (0..candidates.len()).collect();
+        order.sort_by(|&a, &b| {
+            let (a, b) = (nan_last(costs[a]), nan_last(costs[b]));
+            a.partial_cmp(&b).expect("NaNs already replaced")
+        });
+        let best = order[0];
+        let improved = match self.best {
+            Some((_, cost)) => costs[best] < cost,
+            None => !costs[best].is_nan(),
+        };
+        if improved {
+            self.best = Some((candidates[best].clone(), costs[best]));
+        }
+        self.recent_best.push(costs[best]);
+        let history = 10 + (30.0 * n / self.lambda as f64).ceil() as usize;
+        if self.recent_best.len() > history {
+            self.recent_best.remove(0);
+        }
+
+        // Steps of the selected candidates from the old mean, in units of sigma.
+        let steps: Vec<Vec<f64>> = order.iter()
+            .take(self.weights.len())
+            .map(|&i| {
+                candidates[i]
+                    .genes
+                    .iter()
+                    .zip(&self.mean)
+                    .map(|(x, m)| (x - m) / self.sigma)
+                    .collect()
+            })
+            .collect();
+        let mut mean_step = vec![0.0; self.dimension];
+        for (step, weight) in steps.iter().zip(&self.weights) {
+            for (total, s) in mean_step.iter_mut().zip(step) {
+                *total += weight * s;
+            }
+        }
+        for (m, s) in self.mean.iter_mut().zip(&mean_step) {
+            *m += self.sigma * s;
+        }
+
+        // Evolution paths.
+        let whitened = multiply(&self.inverse_root, &mean_step);
+        let ps_scale = (self.cs * (2.0 - self.cs) * self.mueff).sqrt();
+        for (p, w) in self.ps.iter_mut().zip(&whitened) {
+            *p = (1.0 - self.cs) * *p + ps_scale * w;
+        }
+        let ps_norm = norm(&self.ps);
+        let decay = 1.0 - (1.0 - self.cs).powi(2 * self.generation as i32);
+        let hsig = ps_norm / decay.sqrt() / self.chi_n < 1.4 + 2.0 / (n + 1.0);
+        let pc_scale = (self.cc * (2.0 - self.cc) * self.mueff).sqrt();
+        for (p, s) in self.pc.iter_mut().zip(&mean_step) {
+            *p = (1.0 - self.cc) * *p + if hsig { pc_scale * s } else { 0.0 };
+        }
+
+        // Covariance matrix: rank-one update from the path, rank-mu update from the steps.
+        let correction = if hsig {
+            0.0
+        } else {
+            self.c1 * self.cc * (2.0 - self.cc)
+        };
+        for i in 0..self.dimension {
+            for j in 0..=i {
+                let rank_mu: f64 = steps.iter()
+                    .zip(&self.weights)
+                    .map(|(step, weight)| weight * step[i] * step[j])
+                    .sum();
+                let value = (1.0 - self.c1 - self.cmu + correction) * self.covariance[i][j] +
+                            self.c1 * self.pc[i] * self.pc[j] +
+                            self.cmu * rank_mu;
+                self.covariance[i][j] = value;
+                self.covariance[j][i] = value;
+            }
+        }
+
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0)).min(1.0).exp();
+    }
+
+    /// Check whether the search has converged or stalled, and should stop or restart.
+    pub fn should_stop(&self) -> Option<StopReason> {
+        let max_root = self.eigenvalue_roots.iter().cloned().fold(0.0, f64::max);
+        let min_root = self.eigenvalue_roots.iter().cloned().fold(f64::INFINITY, f64::min);
+        if max_root > 1e7 * min_root {
+            return Some(StopReason::ConditionCov);
+        }
+        let spread = self.pc
+            .iter()
+            .zip(0..self.dimension)
+            .all(|(p, i)| self.sigma * p.abs().max(self.covariance[i][i].sqrt()) < 1e-12);
+        if spread {
+            return Some(StopReason::TolX);
+        }
+        let history = 10 + (30.0 * self.dimension as f64 / self.lambda as f64).ceil() as usize;
+        if self.recent_best.len() >= history {
+            let highest = self.recent_best.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let lowest = self.recent_best.iter().cloned().fold(f64::INFINITY, f64::min);
+            if highest - lowest < 1e-12 {
+                return Some(StopReason::TolFun);
+            }
+        }
+        if !self.sigma.is_finite() || self.mean.iter().any(|m| !m.is_finite()) {
+            return Some(StopReason::Diverged);
+        }
+        None
+    }
+
+    /// Get the mean of the distribution, the current estimate of the optimum.
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    /// Get the step size.
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+
+    /// Get the number of candidates per generation.
+    pub fn population_size(&self) -> usize {
+        self.lambda
+    }
+
+    /// Get the number of candidates told so far.
+    pub fn evaluations(&self) -> usize {
+        self.evaluations
+    }
+
+    /// Get the number of generations told so far.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Get the best candidate told so far, with its cost.
+    pub fn best(&self) -> Option<(&RealVector, f64)> {
+        self.best.as_ref().map(|&(ref candidate, cost)| (candidate, cost))
+    }
+
+    /// Recompute the eigendecomposition of the covariance matrix, if it has changed enough
+    /// since it was last computed for this to be worthwhile.
+    fn update_eigendecomposition(&mut self) {
+        let interval = self.lambda as f64 / (self.c1 + self.cmu) / self.dimension as f64 / 10.0;
+        if ((self.evaluations - self.eigen_evaluations) as f64) < interval {
+            return;
+        }
+        self.eigen_evaluations = self.evaluations;
+        let (eigenvalues, eigenvectors) = jacobi_eigen(&self.covariance);
+        self.eigenvalue_roots = eigenvalues.iter().map(|&e| e.max(1e-20).sqrt()).collect();
+        self.eigenvectors = eigenvectors;
+        let n = self.dimension;
+        for i in 0..n {
+            for j in 0..n {
+                self.inverse_root[i][j] = (0..n)
+                    .map(|k| {
+                        self.eigenvectors[i][k] * self.eigenvectors[j][k] /
+                        self.eigenvalue_roots[k]
+                    })
+                    .sum();
+            }
+        }
+    }
+}
+
+/// Why `CmaEs::should_stop` suggests stopping.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum StopReason {
+    /// The distribution is too narrow to make progress.
+    TolX,
+    /// The best cost has not changed for many generations.
+    TolFun,
+    /// The covariance matrix is too badly conditioned to continue.
+    ConditionCov,
+    /// The mean or step size is no longer finite.
+    Diverged,
+}
+
+/// CMA-ES with restarts of increasing population size (IPOP-CMA-ES).
+///
+/// Each run starts from a random point within the bounds. Whenever a run stops, the next
+/// starts with a population twice as large, which helps on objectives with many local
+/// optima.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Ipop {
+    bounds: Vec<(f64, f64)>,
+    restarts: usize,
+    max_evaluations: usize,
+    increase: f64,
+}
+
+impl Ipop {
+    /// Restart within `bounds`, the `(low, high)` range of each gene. Defaults to 9 restarts
+    /// and 100000 evaluations.
+    pub fn new(bounds: Vec<(f64, f64)>) -> Ipop {
+        assert!(!bounds.is_empty(), "CMA-ES needs at least one dimension");
+        for &(low, high) in &bounds {
+            assert!(low < high, "bounds must have low < high");
+        }
+        Ipop {
+            bounds,
+            restarts: 9,
+            max_evaluations: 100_000,
+            increase: 2.0,
+        }
+    }
+
+    /// Set the most restarts after the first run.
+    pub fn restarts(mut self, restarts: usize) -> Ipop {
+        self.restarts = restarts;
+        self
+    }
+
+    /// Set the most evaluations across all runs.
+    pub fn max_evaluations(mut self, max_evaluations: usize) -> Ipop {
+        self.max_evaluations = max_evaluations;
+        self
+    }
+
+    /// Set the factor the population size grows by at each restart. Defaults to 2.
+    pub fn increase(mut self, increase: f64) -> Ipop {
+        assert!(increase >= 1.0, "population size must not shrink");
+        self.increase = increase;
+        self
+    }
+
+    /// Minimise `objective`, returning the best candidate found and its cost.
+    pub fn minimise<F, R>(&self, mut objective: F, rng: &mut R) -> (RealVector, f64)
+        where F: FnMut(&[f64]) -> f64,
+              R: Rng
+    {
+        let widest = self.bounds.iter().map(|&(low, high)| high - low).fold(0.0, f64::max);
+        let mut lambda = CmaEs::new(vec![0.0; self.bounds.len()], 1.0).population_size();
+        let mut evaluations = 0;
+        let mut best: Option<(RealVector, f64)> = None;
+        for _ in 0..self.restarts + 1 {
+            let mean = self.bounds.iter().map(|&(low, high)| rng.gen_range(low, high)).collect();
+            let mut cmaes = CmaEs::with_population_size(mean, 0.3 * widest, lambda);
+            while evaluations < self.max_evaluations && cmaes.should_stop().is_none() {
+                let candidates = cmaes.ask(rng);
+                let costs: Vec<f64> =
+                    candidates.iter().map(|candidate| objective(&candidate.genes)).collect();
+                evaluations += candidates.len();
+                cmaes.tell(&candidates, &costs);
+            }
+            if let Some((candidate, cost)) = cmaes.best() {
+                let improved = match best {
+                    Some((_, best_cost)) => cost < best_cost,
+                    None => true,
+                };
+                if improved {
+                    best = Some((candidate.clone(), cost));
+                }
+            }
+            if evaluations >= self.max_evaluations {
+                break;
+            }
+            lambda = (lambda as f64 * self.increase).round() as usize;
+        }
+        best.unwrap_or_else(|| {
+            let mean: Vec<f64> =
+                self.bounds.iter().map(|&(low, high)| (low + high) / 2.0).collect();
+            let cost = objective(&mean);
+            (RealVector::new(mean), cost)
+        })
+    }
+}
+
+fn nan_last(cost: f64) -> f64 {
+    if cost.is_nan() { f64::INFINITY } else { cost }
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect()
+}
+
+fn multiply(matrix: &[Vec<f64>], vector: &[f64]) -> Vec<f64> {
+    matrix.iter().map(|row| row.iter().zip(vector).map(|(a, b)| a * b).sum()).collect()
+}
+
+fn norm(vector: &[f64]) -> f64 {
+    vector.iter().map(|v| v * v).sum::<f64>().sqrt()
+}
+
+/// Eigendecomposition of a symmetric matrix by cyclic Jacobi rotations. Returns the
+/// eigenvalues and a matrix whose columns are the corresponding eigenvectors.
+fn jacobi_eigen(matrix: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v = identity(n);
+    for _ in 0..100 {
+        let off_diagonal: f64 =
+            (0..n).flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+                .map(|(i, j)| a[i][j] * a[i][j])
+                .sum();
+        let diagonal: f64 = (0..n).map(|i| a[i][i] * a[i][i]).sum();
+        if off_diagonal <= 1e-30 * diagonal || off_diagonal == 0.0 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                // Rotate in the (p, q) plane to zero a[p][q].
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (akp, akq) = (row[p], row[q]);
+                    row[p] = c * akp - s * akq;
+                    row[q] = s * akp + c * akq;
+                }
+                let (above, below) = a.split_at_mut(q);
+                for (apk, aqk) in above[p].iter_mut().zip(below[0].iter_mut()) {
+                    let (old_p, old_q) = (*apk, *aqk);
+                    *apk = c * old_p - s * old_q;
+                    *aqk = s * old_p + c * old_q;
+                }
+                for row in v.iter_mut() {
+                    let (vkp, vkq) = (row[p], row[q]);
+                    row[p] = c * vkp - s * vkq;
+                    row[q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn sphere(x: &[f64]) -> f64 {
+        x.iter().map(|xi| xi * xi).sum()
+    }
+
+    /// Run until `should_stop`, returning why.
+    fn run<F, R>(cmaes: &mut CmaEs, objective: F, rng: &mut R) -> StopReason
+        where F: Fn(&[f64]) -> f64,
+              R: Rng
+    {
+        for _ in 0..10000 {
+            if let Some(reason) = cmaes.should_stop() {
+                return reason;
+            }
+            let candidates = cmaes.ask(rng);
+            let costs: Vec<f64> =
+                candidates.iter().map(|candidate| objective(&candidate.genes)).collect();
+            cmaes.tell(&candidates, &costs);
+        }
+        panic!("CMA-ES never stopped");
+    }
+
+    #[test]
+    fn converges_on_sphere() {
+        let mut rng = StdRng::from_seed(&[44usize][..]);
+        let mut cmaes = CmaEs::new(vec![3.0; 5], 1.0);
+        assert_eq!(cmaes.population_size(), 8);
+        let reason = run(&mut cmaes, sphere, &mut rng);
+        assert!(reason == StopReason::TolX || reason == StopReason::TolFun, "{:?}", reason);
+        let (best, cost) = cmaes.best().unwrap();
+        assert!(cost < 1e-12, "{}", cost);
+        assert_eq!(cost, sphere(&best.genes));
+        assert!(sphere(cmaes.mean()) < 1e-12);
+        assert_eq!(cmaes.evaluations(), cmaes.generation() * 8);
+    }
+
+    #[test]
+    fn learns_an_ill_conditioned_ellipsoid() {
+        let ellipsoid = |x: &[f64]| {
+            x.iter().enumerate().map(|(i, xi)| 1e4f64.powf(i as f64 / 3.0) * xi * xi).sum()
+        };
+        let mut rng = StdRng::from_seed(&[44usize][..]);
+        let mut cmaes = CmaEs::new(vec![1.0; 4], 0.5);
+        run(&mut cmaes, ellipsoid, &mut rng);
+        assert!(cmaes.best().unwrap().1 < 1e-10);
+    }
+
+    #[test]
+    fn ipop_respects_the_budget() {
+        let mut rng = StdRng::from_seed(&[44usize][..]);
+        let mut calls = 0;
+        let counted = |x: &[f64]| {
+            calls += 1;
+            sphere(x)
+        };
+        let (best, cost) = Ipop::new(vec![(-5.0, 5.0); 3])
+            .max_evaluations(3000)
+            .minimise(counted, &mut rng);
+        assert!(calls <= 3000 + 64, "{}", calls);
+        assert!(cost < 1e-8);
+        assert_eq!(cost, sphere(&best.genes));
+    }
+
+    #[test]
+    fn ipop_escapes_local_optima() {
+        let rastrigin = |x: &[f64]| {
+            x.iter()
+                .map(|xi| xi * xi - 10.0 * (2.0 * ::std::f64::consts::PI * xi).cos() + 10.0)
+                .sum::<f64>()
+        };
+        let mut rng = StdRng::from_seed(&[44usize][..]);
+        let (_, cost) = Ipop::new(vec![(-5.12, 5.12); 2]).minimise(rastrigin, &mut rng);
+        assert!(cost < 1e-8, "{}", cost);
+    }
+
+    #[test]
+    fn eigendecomposition() {
+        let matrix = vec![vec![2.0, 1.0, 0.0], vec![1.0, 2.0, 0.0], vec![0.0, 0.0, 5.0]];
+        let (values, vectors) = jacobi_eigen(&matrix);
+        let mut sorted = values.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for (value, expected) in sorted.iter().zip(&[1.0, 3.0, 5.0]) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+        for (k, value) in values.iter().enumerate() {
+            let vector: Vec<f64> = vectors.iter().map(|row| row[k]).collect();
+            let product = multiply(&matrix, &vector);
+            assert!(product.iter().zip(&vector).all(|(p, v)| (p - value * v).abs() < 1e-12));
+            assert!((norm(&vector) - 1.0).abs() < 1e-12);
+        }
+    }
+}
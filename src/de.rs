@@ -0,0 +1,344 @@
+use ga::RealVector;
+use rand::Rng;
+
+/// The DE strategy in use. See `DifferentialEvolution`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum DeMode {
+    /// Corresponds to `DifferentialEvolution::rand_1_bin`.
+    Rand1,
+    /// Corresponds to `DifferentialEvolution::best_1_bin`.
+    Best1,
+    /// Corresponds to `DifferentialEvolution::current_to_best_1_bin`.
+    CurrentToBest1,
+}
+
+/// How to bring genes that leave their bounds back inside.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum BoundHandling {
+    /// Move the gene to the bound it crossed.
+    Clip,
+    /// Reflect the gene back off the bound it crossed.
+    Reflect,
+    /// Replace the gene by a random value within its bounds.
+    Resample,
+}
+
+/// Configures Differential Evolution, which minimises an objective over real vectors.
+///
+/// Each generation every member is challenged by a trial vector, built by adding scaled
+/// differences between other members to a base vector and then crossing it with the member.
+/// The trial replaces the member if its cost is no worse.
+#[derive(PartialEq, Clone, Debug)]
+pub struct DifferentialEvolution {
+    mode: DeMode,
+    bounds: Vec<(f64, f64)>,
+    bound_handling: BoundHandling,
+    population_size: usize,
+    differential_weight: f64,
+    crossover_rate: f64,
+}
+
+impl DifferentialEvolution {
+    /// DE/rand/1/bin: the base vector is a random member. Explores well.
+    ///
+    /// `bounds` gives the `(low, high)` range of each gene, and so the vectors' length.
+    pub fn rand_1_bin(bounds: Vec<(f64, f64)>) -> DifferentialEvolution {
+        Self::with_mode(DeMode::Rand1, bounds)
+    }
+
+    /// DE/best/1/bin: the base vector is the best member. Converges quickly.
+    pub fn best_1_bin(bounds: Vec<(f64, f64)>) -> DifferentialEvolution {
+        Self::with_mode(DeMode::Best1, bounds)
+    }
+
+    /// DE/current-to-best/1/bin: the base vector is the member itself, moved towards the
+    /// best member. Balances DE/rand/1/bin and DE/best/1/bin.
+    pub fn current_to_best_1_bin(bounds: Vec<(f64, f64)>) -> DifferentialEvolution {
+        Self::with_mode(DeMode::CurrentToBest1, bounds)
+    }
+
+    fn with_mode(mode: DeMode, bounds: Vec<(f64, f64)>) -> DifferentialEvolution {
+        for &(low, high) in &bounds {
+            assert!(low <= high, "bounds must have low <= high");
+        }
+        DifferentialEvolution {
+            mode,
+            population_size: (10 * bounds.len()).max(4),
+            bounds,
+            bound_handling: BoundHandling::Reflect,
+            differential_weight: 0.8,
+            crossover_rate: 0.9,
+        }
+    }
+
+    /// Set how genes that leave their bounds are handled. Defaults to reflecting.
+    pub fn bound_handling(mut self, bound_handling: BoundHandling) -> DifferentialEvolution {
+        self.bound_handling = bound_handling;
+        self
+    }
+
+    /// Set the population size, at least 4. Defaults to 10 per gene.
+    pub fn population_size(mut self, population_size: usize) -> DifferentialEvolution {
+        assert!(population_size >= 4, "DE needs a population of at least 4");
+        self.population_size = population_size;
+        self
+    }
+
+    /// Set the differential weight `F` scaling difference vectors. Defaults to 0.8.
+    pub fn differential_weight(mut self, differential_weight: f64) -> DifferentialEvolution {
+        self.differential_weight = differential_weight;
+        self
+    }
+
+    /// Set the crossover rate `CR`, the chance of each gene coming from the mutant vector.
+    /// Defaults to 0.9.
+    pub fn crossover_rate(mut self, crossover_rate: f64) -> DifferentialEvolution {
+        self.crossover_rate = crossover_rate;
+        self
+    }
+
+    /// Create a random population within the bounds and evaluate it.
+    pub fn initialise<F, R>(&self, mut objective: F, rng: &mut R) -> DePopulation
+        where F: FnMut(&[f64]) -> f64,
+              R: Rng
+    {
+        let members: Vec<RealVector> = (0..self.population_size)
+            .map(|_| {
+                RealVector::new(self.bounds
+                    .iter()
+                    .map(|&(low, high)| random_in(rng, low, high))
+                    .collect())
+            })
+            .collect();
+        let costs: Vec<f64> = members.iter().map(|member| objective(&member.genes)).collect();
+        let mut population = DePopulation {
+            members,
+            costs,
+            best: 0,
+            generation: 0,
+        };
+        population.find_best();
+        population
+    }
+
+    /// Run one generation.
+    pub fn step<F, R>(&self, population: &mut DePopulation, mut objective: F, rng: &mut R)
+        where F: FnMut(&[f64]) -> f64,
+              R: Rng
+    {
+        let size = population.members.len();
+        let length = self.bounds.len();
+        let weight = self.differential_weight;
+        for i in 0..size {
+            let (r1, r2, r3) = distinct_three(rng, size, i);
+            let member = &population.members[i].genes;
+            let best = &population.members[population.best].genes;
+            let a = &population.members[r1].genes;
+            let b = &population.members[r2].genes;
+            let c = &population.members[r3].genes;
+            // At least one gene always comes from the mutant vector.
+            let forced = rng.gen_range(0, length.max(1));
+            let mut trial = member.clone();
+            for j in 0..length {
+                if j != forced && rng.gen::<f64>() >= self.crossover_rate {
+                    continue;
+                }
+                let mutant = match self.mode {
+                    DeMode::Rand1 => a[j] + weight * (b[j] - c[j]),
+                    DeMode::Best1 => best[j] + weight * (a[j] - b[j]),
+                    DeMode::CurrentToBest1 => {
+                        member[j] + weight * (best[j] - member[j]) + weight * (a[j] - b[j])
+                    }
+                };
+                trial[j] = self.handle_bounds(rng, mutant, self.bounds[j]);
+            }
+            let trial_cost = objective(&trial);
+            // Members with NaN costs are always replaced.
+            if trial_cost <= population.costs[i] || population.costs[i].is_nan() {
+                population.members[i].genes = trial;
+                population.costs[i] = trial_cost;
+                if trial_cost <= population.costs[population.best] ||
+                   population.costs[population.best].is_nan() {
+                    population.best = i;
+                }
+            }
+        }
+        population.generation += 1;
+    }
+
+    /// Create a population and run `generations` generations, minimising `objective`.
+    pub fn minimise<F, R>(&self, mut objective: F, generations: usize, rng: &mut R) -> DePopulation
+        where F: FnMut(&[f64]) -> f64,
+              R: Rng
+    {
+        let mut population = self.initialise(&mut objective, rng);
+        for _ in 0..generations {
+            self.step(&mut population, &mut objective, rng);
+        }
+        population
+    }
+
+    fn handle_bounds<R: Rng>(&self, rng: &mut R, gene: f64, (low, high): (f64, f64)) -> f64 {
+        if gene >= low && gene <= high {
+            return gene;
+        }
+        match self.bound_handling {
+            BoundHandling::Clip => gene.max(low).min(high),
+            BoundHandling::Reflect => {
+                let reflected = if gene < low {
+                    2.0 * low - gene
+                } else {
+                    2.0 * high - gene
+                };
+                // Genes far outside their bounds can reflect past the other bound.
+                reflected.max(low).min(high)
+            }
+            BoundHandling::Resample => random_in(rng, low, high),
+        }
+    }
+}
+
+/// A Differential Evolution population, with the cost of each member.
+#[derive(PartialEq, Clone, Debug)]
+pub struct DePopulation {
+    members: Vec<RealVector>,
+    costs: Vec<f64>,
+    best: usize,
+    generation: usize,
+}
+
+impl DePopulation {
+    /// Get the members.
+    pub fn members(&self) -> &[RealVector] {
+        &self.members
+    }
+
+    /// Get the cost of each member.
+    pub fn costs(&self) -> &[f64] {
+        &self.costs
+    }
+
+    /// Get the member with the lowest cost.
+    pub fn best(&self) -> &RealVector {
+        &self.members[self.best]
+    }
+
+    /// Get the lowest cost.
+    pub fn best_cost(&self) -> f64 {
+        self.costs[self.best]
+    }
+
+    /// Get the number of generations run.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    fn find_best(&mut self) {
+        for (i, &cost) in self.costs.iter().enumerate() {
+            // NaN costs are never best.
+            if cost < self.costs[self.best] || self.costs[self.best].is_nan() {
+                self.best = i;
+            }
+        }
+    }
+}
+
+/// A random value in `[low, high)`, or `low` if the range is empty.
+fn random_in<R: Rng>(rng: &mut R, low: f64, high: f64) -> f64 {
+    if low < high {
+        rng.gen_range(low, high)
+    } else {
+        low
+    }
+}
+
+/// Three distinct random indices below `size`, none equal to `exclude`.
+fn distinct_three<R: Rng>(rng: &mut R, size: usize, exclude: usize) -> (usize, usize, usize) {
+    let mut chosen = [exclude; 3];
+    for k in 0..3 {
+        loop {
+            let candidate = rng.gen_range(0, size);
+            if candidate != exclude && !chosen[..k].contains(&candidate) {
+                chosen[k] = candidate;
+                break;
+            }
+        }
+    }
+    (chosen[0], chosen[1], chosen[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn sphere(x: &[f64]) -> f64 {
+        x.iter().map(|xi| xi * xi).sum()
+    }
+
+    #[test]
+    fn converges_on_sphere() {
+        let bounds = vec![(-5.0, 5.0); 5];
+        let strategies = [DifferentialEvolution::rand_1_bin(bounds.clone()),
+                          DifferentialEvolution::best_1_bin(bounds.clone()),
+                          DifferentialEvolution::current_to_best_1_bin(bounds)];
+        for strategy in &strategies {
+            let mut rng = StdRng::from_seed(&[43usize][..]);
+            let population = strategy.minimise(sphere, 300, &mut rng);
+            assert_eq!(population.generation(), 300);
+            assert!(population.best_cost() < 1e-6, "{:?}", population.best());
+            assert_eq!(population.best_cost(), sphere(&population.best().genes));
+            let lowest = population.costs().iter().cloned().fold(f64::INFINITY, f64::min);
+            assert_eq!(population.best_cost(), lowest);
+        }
+    }
+
+    #[test]
+    fn members_stay_in_bounds() {
+        let mut rng = StdRng::from_seed(&[43usize][..]);
+        let bounds = vec![(1.0, 2.0), (-3.0, -2.5)];
+        for &handling in &[BoundHandling::Clip, BoundHandling::Reflect, BoundHandling::Resample] {
+            // The optimum lies outside the bounds, so genes keep leaving them.
+            let population = DifferentialEvolution::rand_1_bin(bounds.clone())
+                .bound_handling(handling)
+                .population_size(8)
+                .minimise(sphere, 50, &mut rng);
+            for member in population.members() {
+                assert!(member.genes.iter().zip(&bounds).all(|(&gene, &(low, high))| {
+                    low <= gene && gene <= high
+                }));
+            }
+        }
+    }
+
+    #[test]
+    fn bound_handling() {
+        let mut rng = StdRng::from_seed(&[43usize][..]);
+        let de = DifferentialEvolution::rand_1_bin(vec![(0.0, 1.0)]);
+        let clip = de.clone().bound_handling(BoundHandling::Clip);
+        assert_eq!(clip.handle_bounds(&mut rng, 1.5, (0.0, 1.0)), 1.0);
+        assert_eq!(de.handle_bounds(&mut rng, -0.25, (0.0, 1.0)), 0.25);
+        assert_eq!(de.handle_bounds(&mut rng, 1.25, (0.0, 1.0)), 0.75);
+        assert_eq!(de.handle_bounds(&mut rng, 5.0, (0.0, 1.0)), 0.0);
+        assert_eq!(de.handle_bounds(&mut rng, 0.5, (0.0, 1.0)), 0.5);
+    }
+
+    #[test]
+    fn nan_members_are_replaced() {
+        let mut rng = StdRng::from_seed(&[43usize][..]);
+        let de = DifferentialEvolution::best_1_bin(vec![(-1.0, 1.0); 2]);
+        let mut population = de.initialise(|_| f64::NAN, &mut rng);
+        de.step(&mut population, sphere, &mut rng);
+        assert!(population.costs().iter().all(|cost| !cost.is_nan()));
+        assert!(!population.best_cost().is_nan());
+    }
+
+    #[test]
+    fn distinct_indices() {
+        let mut rng = StdRng::from_seed(&[43usize][..]);
+        for _ in 0..100 {
+            let (a, b, c) = distinct_three(&mut rng, 4, 1);
+            assert!(a != b && b != c && a != c && ![a, b, c].contains(&1));
+        }
+    }
+}
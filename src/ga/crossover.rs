@@ -0,0 +1,140 @@
+use ga::*;
+use rand::Rng;
+use std::mem;
+
+/// The crossover mode in use. See `Crossover`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum CrossoverMode {
+    /// Corresponds to `Crossover::one_point`.
+    OnePoint,
+    /// Corresponds to `Crossover::two_point`.
+    TwoPoint,
+    /// Corresponds to `Crossover::uniform`.
+    Uniform(f64),
+}
+
+/// Configures crossover (mating) between GA chromosomes.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Crossover {
+    mode: CrossoverMode,
+}
+
+impl Crossover {
+    /// Get an operator to perform one-point crossover between two chromosomes.
+    ///
+    /// The genes after a random position are swapped between the chromosomes.
+    pub fn one_point() -> Crossover {
+        Crossover { mode: CrossoverMode::OnePoint }
+    }
+
+    /// Get an operator to perform two-point crossover between two chromosomes.
+    ///
+    /// The genes between two random positions are swapped between the chromosomes.
+    pub fn two_point() -> Crossover {
+        Crossover { mode: CrossoverMode::TwoPoint }
+    }
+
+    /// Get an operator to perform uniform crossover between two chromosomes.
+    ///
+    /// Each gene is swapped between the chromosomes with `probability`, usually 0.5.
+    pub fn uniform(probability: f64) -> Crossover {
+        Crossover { mode: CrossoverMode::Uniform(probability) }
+    }
+
+    /// Crossover (mate) two chromosomes according to the configured crossover mode.
+    ///
+    /// Panics unless the chromosomes have the same length.
+    pub fn mate<G, R>(&self,
+                      chromosome1: &mut Chromosome<G>,
+                      chromosome2: &mut Chromosome<G>,
+                      mut rng: R)
+        where R: Rng
+    {
+        assert_eq!(chromosome1.len(), chromosome2.len(), "chromosomes must have the same length");
+        let length = chromosome1.len();
+        let (genes1, genes2) = (&mut chromosome1.genes, &mut chromosome2.genes);
+        match self.mode {
+            CrossoverMode::OnePoint => {
+                let point = rng.gen_range(0, length + 1);
+                genes1[point..].swap_with_slice(&mut genes2[point..]);
+            }
+            CrossoverMode::TwoPoint => {
+                let mut start = rng.gen_range(0, length + 1);
+                let mut end = rng.gen_range(0, length + 1);
+                if start > end {
+                    mem::swap(&mut start, &mut end);
+                }
+                genes1[start..end].swap_with_slice(&mut genes2[start..end]);
+            }
+            CrossoverMode::Uniform(probability) => {
+                for (gene1, gene2) in genes1.iter_mut().zip(genes2.iter_mut()) {
+                    if rng.gen::<f64>() < probability {
+                        mem::swap(gene1, gene2);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn genes_keep_their_positions() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let crossovers = [Crossover::one_point(), Crossover::two_point(), Crossover::uniform(0.5)];
+        for crossover in &crossovers {
+            for _ in 0..20 {
+                let parent1 = IntVector::new((0..10).collect());
+                let parent2 = IntVector::new((10..20).collect());
+                let (mut child1, mut child2) = (parent1.clone(), parent2.clone());
+                crossover.mate(&mut child1, &mut child2, &mut rng);
+                for i in 0..10 {
+                    let genes = (child1.genes[i], child2.genes[i]);
+                    assert!(genes == (i as i64, i as i64 + 10) ||
+                            genes == (i as i64 + 10, i as i64));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn point_crossovers_swap_runs() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        for _ in 0..20 {
+            let mut child1 = BitString::new(vec![false; 12]);
+            let mut child2 = BitString::new(vec![true; 12]);
+            Crossover::one_point().mate(&mut child1, &mut child2, &mut rng);
+            let point = child1.genes.iter().position(|&bit| bit).unwrap_or(12);
+            assert!(child1.genes[point..].iter().all(|&bit| bit));
+
+            let mut child1 = BitString::new(vec![false; 12]);
+            let mut child2 = BitString::new(vec![true; 12]);
+            Crossover::two_point().mate(&mut child1, &mut child2, &mut rng);
+            let swapped: Vec<usize> = (0..12).filter(|&i| child1.genes[i]).collect();
+            if let (Some(&first), Some(&last)) = (swapped.first(), swapped.last()) {
+                assert_eq!(swapped.len(), last - first + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn uniform_with_certainty_swaps_every_gene() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let mut child1 = RealVector::new(vec![0.0; 5]);
+        let mut child2 = RealVector::new(vec![1.0; 5]);
+        Crossover::uniform(1.0).mate(&mut child1, &mut child2, &mut rng);
+        assert_eq!((child1.genes, child2.genes), (vec![1.0; 5], vec![0.0; 5]));
+    }
+
+    #[test]
+    #[should_panic(expected = "same length")]
+    fn lengths_must_match() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let (mut child1, mut child2) = (BitString::new(vec![true]), BitString::new(vec![]));
+        Crossover::one_point().mate(&mut child1, &mut child2, &mut rng);
+    }
+}
@@ -0,0 +1,81 @@
+mod crossover;
+mod mutation;
+mod permutation;
+
+/// Genetic Algorithm crossover (mating).
+pub use self::crossover::*;
+/// Genetic Algorithm mutation.
+pub use self::mutation::*;
+/// Permutation chromosomes and their operators.
+pub use self::permutation::*;
+
+use rand::Rng;
+use std::fmt;
+
+/// A fixed-length chromosome for a Genetic Algorithm.
+///
+/// Use the `BitString`, `IntVector` and `RealVector` aliases for the usual encodings.
+#[derive(PartialEq, Clone, Debug, Hash)]
+pub struct Chromosome<G> {
+    /// The genes.
+    pub genes: Vec<G>,
+}
+
+/// A chromosome of bits.
+pub type BitString = Chromosome<bool>;
+/// A chromosome of integers.
+pub type IntVector = Chromosome<i64>;
+/// A chromosome of real numbers.
+pub type RealVector = Chromosome<f64>;
+
+impl<G> Chromosome<G> {
+    /// Create a chromosome from genes.
+    pub fn new(genes: Vec<G>) -> Chromosome<G> {
+        Chromosome { genes }
+    }
+
+    /// Number of genes.
+    pub fn len(&self) -> usize {
+        self.genes.len()
+    }
+
+    /// Whether there are no genes.
+    pub fn is_empty(&self) -> bool {
+        self.genes.is_empty()
+    }
+}
+
+impl Chromosome<bool> {
+    /// Generate a random bit string of `length` bits.
+    pub fn random<R: Rng>(rng: &mut R, length: usize) -> BitString {
+        Chromosome { genes: (0..length).map(|_| rng.gen()).collect() }
+    }
+
+    /// Count the set bits.
+    pub fn count_ones(&self) -> usize {
+        self.genes.iter().filter(|&&bit| bit).count()
+    }
+}
+
+impl Chromosome<i64> {
+    /// Generate a random vector of `length` integers in `[low, high]`.
+    pub fn random<R: Rng>(rng: &mut R, length: usize, low: i64, high: i64) -> IntVector {
+        Chromosome { genes: (0..length).map(|_| rng.gen_range(low, high + 1)).collect() }
+    }
+}
+
+impl Chromosome<f64> {
+    /// Generate a random vector of `length` reals in `[low, high)`.
+    pub fn random<R: Rng>(rng: &mut R, length: usize, low: f64, high: f64) -> RealVector {
+        Chromosome { genes: (0..length).map(|_| rng.gen_range(low, high)).collect() }
+    }
+}
+
+impl fmt::Display for Chromosome<bool> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &bit in &self.genes {
+            write!(f, "{}", if bit { '1' } else { '0' })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use ga::*;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+use std::fmt::Debug;
+
+/// Gene types whose chromosomes can be mutated. Implemented for `bool`, `i64` and `f64`.
+pub trait Gene: Sized {
+    /// The mutation modes available for chromosomes of this gene type.
+    type MutationMode: PartialEq + Clone + Copy + Debug;
+
+    /// Mutate genes according to a mutation mode.
+    fn mutate<R: Rng>(mode: Self::MutationMode, genes: &mut [Self], rng: &mut R);
+}
+
+/// Mutation modes of each gene type. Public so that `Gene` can name them, but private to
+/// this module.
+mod modes {
+    /// Mutation modes of bits.
+    #[derive(PartialEq, Clone, Copy, Debug)]
+    pub enum BitMode {
+        /// Corresponds to `Mutation::bit_flip`.
+        BitFlip(f64),
+    }
+
+    /// Mutation modes of integers.
+    #[derive(PartialEq, Clone, Copy, Debug)]
+    pub enum IntMode {
+        /// Corresponds to `Mutation::random_reset`.
+        RandomReset(f64, i64, i64),
+    }
+
+    /// Mutation modes of reals.
+    #[derive(PartialEq, Clone, Copy, Debug)]
+    pub enum RealMode {
+        /// Corresponds to `Mutation::gaussian`.
+        Gaussian(f64, f64),
+        /// Corresponds to `Mutation::polynomial`.
+        Polynomial(f64, f64, f64, f64),
+    }
+}
+
+use self::modes::*;
+
+/// Configures mutation of GA chromosomes with genes of type `G`.
+///
+/// Each mutation only exists for the gene type it applies to, so for example bit-flip
+/// mutation can only be applied to a `BitString`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Mutation<G>
+    where G: Gene
+{
+    mode: G::MutationMode,
+}
+
+impl<G> Mutation<G>
+    where G: Gene
+{
+    /// Mutate a chromosome according to the configured mutation mode.
+    pub fn mutate<R: Rng>(&self, chromosome: &mut Chromosome<G>, mut rng: R) {
+        G::mutate(self.mode, &mut chromosome.genes, &mut rng)
+    }
+}
+
+impl Mutation<bool> {
+    /// Flip each bit with `probability`.
+    pub fn bit_flip(probability: f64) -> Mutation<bool> {
+        Mutation { mode: BitMode::BitFlip(probability) }
+    }
+}
+
+impl Gene for bool {
+    type MutationMode = BitMode;
+
+    fn mutate<R: Rng>(mode: BitMode, genes: &mut [bool], rng: &mut R) {
+        match mode {
+            BitMode::BitFlip(probability) => {
+                for bit in genes {
+                    if rng.gen::<f64>() < probability {
+                        *bit = !*bit;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Mutation<i64> {
+    /// Replace each gene, with `probability`, by a random integer in `[low, high]`.
+    pub fn random_reset(probability: f64, low: i64, high: i64) -> Mutation<i64> {
+        Mutation { mode: IntMode::RandomReset(probability, low, high) }
+    }
+}
+
+impl Gene for i64 {
+    type MutationMode = IntMode;
+
+    fn mutate<R: Rng>(mode: IntMode, genes: &mut [i64], rng: &mut R) {
+        match mode {
+            IntMode::RandomReset(probability, low, high) => {
+                for gene in genes {
+                    if rng.gen::<f64>() < probability {
+                        *gene = rng.gen_range(low, high + 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Mutation<f64> {
+    /// Add normally-distributed noise with standard deviation `sigma` to each gene, with
+    /// `probability`.
+    pub fn gaussian(probability: f64, sigma: f64) -> Mutation<f64> {
+        Mutation { mode: RealMode::Gaussian(probability, sigma) }
+    }
+
+    /// Apply Deb's polynomial mutation to each gene with `probability`, keeping genes in
+    /// `[low, high]`.
+    ///
+    /// Larger distribution indexes `eta` make smaller changes more likely; 20 is common.
+    pub fn polynomial(probability: f64, eta: f64, low: f64, high: f64) -> Mutation<f64> {
+        assert!(low < high, "polynomial mutation needs low < high");
+        Mutation { mode: RealMode::Polynomial(probability, eta, low, high) }
+    }
+}
+
+impl Gene for f64 {
+    type MutationMode = RealMode;
+
+    fn mutate<R: Rng>(mode: RealMode, genes: &mut [f64], rng: &mut R) {
+        match mode {
+            RealMode::Gaussian(probability, sigma) => {
+                let normal = Normal::new(0.0, sigma);
+                for gene in genes {
+                    if rng.gen::<f64>() < probability {
+                        *gene += normal.ind_sample(rng);
+                    }
+                }
+            }
+            RealMode::Polynomial(probability, eta, low, high) => {
+                for gene in genes {
+                    if rng.gen::<f64>() < probability {
+                        *gene = polynomial(*gene, eta, low, high, rng.gen());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Deb's bounded polynomial mutation of `x`, given a uniform random number `u` in [0, 1).
+fn polynomial(x: f64, eta: f64, low: f64, high: f64, u: f64) -> f64 {
+    let x = x.max(low).min(high);
+    let range = high - low;
+    let power = 1.0 / (eta + 1.0);
+    let delta = if u < 0.5 {
+        let xy = 1.0 - (x - low) / range;
+        let value = 2.0 * u + (1.0 - 2.0 * u) * xy.powf(eta + 1.0);
+        value.powf(power) - 1.0
+    } else {
+        let xy = 1.0 - (high - x) / range;
+        let value = 2.0 * (1.0 - u) + 2.0 * (u - 0.5) * xy.powf(eta + 1.0);
+        1.0 - value.powf(power)
+    };
+    (x + delta * range).max(low).min(high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn bit_flip() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let bits = BitString::random(&mut rng, 40);
+        let mut flipped = bits.clone();
+        Mutation::bit_flip(1.0).mutate(&mut flipped, &mut rng);
+        assert!(bits.genes.iter().zip(&flipped.genes).all(|(a, b)| a != b));
+        Mutation::bit_flip(0.0).mutate(&mut flipped, &mut rng);
+        assert_eq!(flipped.count_ones(), 40 - bits.count_ones());
+    }
+
+    #[test]
+    fn random_reset_stays_in_range() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let mut vector = IntVector::random(&mut rng, 100, -50, 50);
+        Mutation::random_reset(1.0, 3, 5).mutate(&mut vector, &mut rng);
+        assert!(vector.genes.iter().all(|gene| (3..=5).contains(gene)));
+        assert!((3..6).all(|value| vector.genes.contains(&value)));
+    }
+
+    #[test]
+    fn real_mutations() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let vector = RealVector::random(&mut rng, 100, -1.0, 1.0);
+        let mut noisy = vector.clone();
+        Mutation::gaussian(1.0, 0.1).mutate(&mut noisy, &mut rng);
+        let mean_change = vector.genes
+            .iter()
+            .zip(&noisy.genes)
+            .map(|(a, b)| (a - b).abs())
+            .sum::<f64>() / 100.0;
+        assert!(0.0 < mean_change && mean_change < 0.2, "{}", mean_change);
+
+        let mut bounded = vector.clone();
+        for _ in 0..20 {
+            Mutation::polynomial(1.0, 20.0, -1.0, 1.0).mutate(&mut bounded, &mut rng);
+            assert!(bounded.genes.iter().all(|gene| (-1.0..=1.0).contains(gene)));
+        }
+        assert!(bounded != vector);
+        assert_eq!(polynomial(0.25, 20.0, -1.0, 1.0, 0.5), 0.25);
+    }
+}
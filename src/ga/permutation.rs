@@ -0,0 +1,377 @@
+use rand::Rng;
+use std::mem;
+
+/// A permutation of `0..n`, for ordering problems such as scheduling and routing.
+///
+/// Genes can only be changed by the permutation operators, which always leave a valid
+/// permutation.
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub struct Permutation {
+    genes: Vec<usize>,
+}
+
+impl Permutation {
+    /// Create a permutation from genes. Returns `None` unless each of `0..genes.len()`
+    /// appears exactly once.
+    pub fn new(genes: Vec<usize>) -> Option<Permutation> {
+        let mut seen = vec![false; genes.len()];
+        for &gene in &genes {
+            if gene >= genes.len() || seen[gene] {
+                return None;
+            }
+            seen[gene] = true;
+        }
+        Some(Permutation { genes })
+    }
+
+    /// Create the identity permutation of `0..length`.
+    pub fn identity(length: usize) -> Permutation {
+        Permutation { genes: (0..length).collect() }
+    }
+
+    /// Generate a random permutation of `0..length`.
+    pub fn random<R: Rng>(rng: &mut R, length: usize) -> Permutation {
+        let mut permutation = Self::identity(length);
+        rng.shuffle(&mut permutation.genes);
+        permutation
+    }
+
+    /// Get the genes.
+    pub fn genes(&self) -> &[usize] {
+        &self.genes
+    }
+
+    /// Number of genes.
+    pub fn len(&self) -> usize {
+        self.genes.len()
+    }
+
+    /// Whether there are no genes.
+    pub fn is_empty(&self) -> bool {
+        self.genes.is_empty()
+    }
+
+    /// Get the position of each gene, so that `positions()[genes()[i]] == i`.
+    pub fn positions(&self) -> Vec<usize> {
+        let mut positions = vec![0; self.genes.len()];
+        for (i, &gene) in self.genes.iter().enumerate() {
+            positions[gene] = i;
+        }
+        positions
+    }
+
+    /// Take the genes.
+    pub fn into_genes(self) -> Vec<usize> {
+        self.genes
+    }
+}
+
+/// Choose a random range of positions `[start, end)` in a permutation of `length` genes.
+fn random_range<R: Rng>(rng: &mut R, length: usize) -> (usize, usize) {
+    let mut start = rng.gen_range(0, length + 1);
+    let mut end = rng.gen_range(0, length + 1);
+    if start > end {
+        mem::swap(&mut start, &mut end);
+    }
+    (start, end)
+}
+
+/// The permutation crossover mode in use. See `PermutationCrossover`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum PermutationCrossoverMode {
+    /// Corresponds to `PermutationCrossover::partially_mapped`.
+    PartiallyMapped,
+    /// Corresponds to `PermutationCrossover::ordered`.
+    Ordered,
+    /// Corresponds to `PermutationCrossover::cycle`.
+    Cycle,
+}
+
+/// Configures crossover (mating) between permutations.
+///
+/// Children are always valid permutations.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct PermutationCrossover {
+    mode: PermutationCrossoverMode,
+}
+
+impl PermutationCrossover {
+    /// Get an operator to perform partially mapped crossover (PMX).
+    ///
+    /// Each child takes a random section from one parent. The other parent's genes fill
+    /// the rest, following the mapping between the sections where genes would repeat.
+    pub fn partially_mapped() -> PermutationCrossover {
+        PermutationCrossover { mode: PermutationCrossoverMode::PartiallyMapped }
+    }
+
+    /// Get an operator to perform ordered crossover (OX).
+    ///
+    /// Each child takes a random section from one parent, and the remaining genes in the
+    /// order they appear in the other parent starting after the section.
+    pub fn ordered() -> PermutationCrossover {
+        PermutationCrossover { mode: PermutationCrossoverMode::Ordered }
+    }
+
+    /// Get an operator to perform cycle crossover (CX).
+    ///
+    /// The positions are split into cycles between the parents. Children take alternate
+    /// cycles from each parent, so every gene keeps a position from one of the parents.
+    pub fn cycle() -> PermutationCrossover {
+        PermutationCrossover { mode: PermutationCrossoverMode::Cycle }
+    }
+
+    /// Crossover (mate) two permutations according to the configured crossover mode.
+    ///
+    /// Panics unless the permutations have the same length.
+    pub fn mate<R>(&self,
+                   permutation1: &mut Permutation,
+                   permutation2: &mut Permutation,
+                   mut rng: R)
+        where R: Rng
+    {
+        assert_eq!(permutation1.len(),
+                   permutation2.len(),
+                   "permutations must have the same length");
+        match self.mode {
+            PermutationCrossoverMode::PartiallyMapped => {
+                let (start, end) = random_range(&mut rng, permutation1.len());
+                mate_partially_mapped(permutation1, permutation2, start, end);
+            }
+            PermutationCrossoverMode::Ordered => {
+                let (start, end) = random_range(&mut rng, permutation1.len());
+                let child1 = ordered_child(permutation1, permutation2, start, end);
+                let child2 = ordered_child(permutation2, permutation1, start, end);
+                permutation1.genes = child1;
+                permutation2.genes = child2;
+            }
+            PermutationCrossoverMode::Cycle => mate_cycle(permutation1, permutation2),
+        }
+    }
+}
+
+/// PMX by swapping genes within each permutation, which keeps both valid throughout.
+fn mate_partially_mapped(permutation1: &mut Permutation,
+                         permutation2: &mut Permutation,
+                         start: usize,
+                         end: usize) {
+    // Swaps can move genes within the section, so take its genes from the parents.
+    let section1 = permutation1.genes[start..end].to_vec();
+    let section2 = permutation2.genes[start..end].to_vec();
+    let mut positions1 = permutation1.positions();
+    let mut positions2 = permutation2.positions();
+    for (i, (&gene1, &gene2)) in (start..end).zip(section1.iter().zip(&section2)) {
+        // Move gene2 to position i in the first permutation, and gene1 in the second.
+        let (current1, current2) = (permutation1.genes[i], permutation2.genes[i]);
+        permutation1.genes.swap(i, positions1[gene2]);
+        positions1.swap(current1, gene2);
+        permutation2.genes.swap(i, positions2[gene1]);
+        positions2.swap(current2, gene1);
+    }
+}
+
+/// Build an OX child keeping `[start, end)` from `keep` and filling from `fill`.
+fn ordered_child(keep: &Permutation, fill: &Permutation, start: usize, end: usize) -> Vec<usize> {
+    let length = keep.len();
+    let mut kept = vec![false; length];
+    for &gene in &keep.genes[start..end] {
+        kept[gene] = true;
+    }
+    let mut child = keep.genes.clone();
+    let mut position = end;
+    for offset in 0..length {
+        let gene = fill.genes[(end + offset) % length];
+        if !kept[gene] {
+            child[position % length] = gene;
+            position += 1;
+        }
+    }
+    child
+}
+
+fn mate_cycle(permutation1: &mut Permutation, permutation2: &mut Permutation) {
+    let positions1 = permutation1.positions();
+    let mut visited = vec![false; permutation1.len()];
+    let mut cycle = 0;
+    for start in 0..permutation1.len() {
+        if visited[start] {
+            continue;
+        }
+        let mut i = start;
+        while !visited[i] {
+            visited[i] = true;
+            let next = positions1[permutation2.genes[i]];
+            // Odd cycles are swapped between the children.
+            if cycle % 2 == 1 {
+                mem::swap(&mut permutation1.genes[i], &mut permutation2.genes[i]);
+            }
+            i = next;
+        }
+        cycle += 1;
+    }
+}
+
+/// The permutation mutation mode in use. See `PermutationMutation`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum PermutationMutationMode {
+    /// Corresponds to `PermutationMutation::swap`.
+    Swap,
+    /// Corresponds to `PermutationMutation::inversion`.
+    Inversion,
+    /// Corresponds to `PermutationMutation::scramble`.
+    Scramble,
+}
+
+/// Configures mutation of permutations.
+///
+/// Mutated permutations are always valid.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct PermutationMutation {
+    mode: PermutationMutationMode,
+}
+
+impl PermutationMutation {
+    /// Swap the genes at two random positions.
+    pub fn swap() -> PermutationMutation {
+        PermutationMutation { mode: PermutationMutationMode::Swap }
+    }
+
+    /// Reverse the genes in a random section.
+    pub fn inversion() -> PermutationMutation {
+        PermutationMutation { mode: PermutationMutationMode::Inversion }
+    }
+
+    /// Shuffle the genes in a random section.
+    pub fn scramble() -> PermutationMutation {
+        PermutationMutation { mode: PermutationMutationMode::Scramble }
+    }
+
+    /// Mutate a permutation according to the configured mutation mode.
+    pub fn mutate<R>(&self, permutation: &mut Permutation, mut rng: R)
+        where R: Rng
+    {
+        let length = permutation.len();
+        if length < 2 {
+            return;
+        }
+        match self.mode {
+            PermutationMutationMode::Swap => {
+                let i = rng.gen_range(0, length);
+                let j = rng.gen_range(0, length);
+                permutation.genes.swap(i, j);
+            }
+            PermutationMutationMode::Inversion => {
+                let (start, end) = random_range(&mut rng, length);
+                permutation.genes[start..end].reverse();
+            }
+            PermutationMutationMode::Scramble => {
+                let (start, end) = random_range(&mut rng, length);
+                rng.shuffle(&mut permutation.genes[start..end]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn permutation(genes: &[usize]) -> Permutation {
+        Permutation::new(genes.to_vec()).unwrap()
+    }
+
+    fn is_valid(permutation: &Permutation) -> bool {
+        Permutation::new(permutation.genes().to_vec()).is_some()
+    }
+
+    #[test]
+    fn new_rejects_non_permutations() {
+        assert!(Permutation::new(vec![2, 0, 1]).is_some());
+        assert!(Permutation::new(vec![0, 0, 1]).is_none());
+        assert!(Permutation::new(vec![0, 3, 1]).is_none());
+        let shuffled = permutation(&[3, 0, 2, 1]);
+        assert_eq!(shuffled.positions(), vec![1, 3, 2, 0]);
+        assert_eq!(Permutation::identity(3).into_genes(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn partially_mapped_copies_the_section() {
+        let mut child1 = permutation(&[8, 4, 7, 3, 6, 2, 5, 1, 9, 0]);
+        let mut child2 = Permutation::identity(10);
+        mate_partially_mapped(&mut child1, &mut child2, 3, 8);
+        assert_eq!(child1.genes(), &[8, 2, 1, 3, 4, 5, 6, 7, 9, 0][..]);
+        assert_eq!(child2.genes(), &[0, 7, 4, 3, 6, 2, 5, 1, 8, 9][..]);
+
+        let mut rng = StdRng::from_seed(&[42usize][..]);
+        for _ in 0..50 {
+            let parent1 = Permutation::random(&mut rng, 12);
+            let parent2 = Permutation::random(&mut rng, 12);
+            let (start, end) = random_range(&mut rng, 12);
+            let (mut child1, mut child2) = (parent1.clone(), parent2.clone());
+            mate_partially_mapped(&mut child1, &mut child2, start, end);
+            assert_eq!(child1.genes()[start..end], parent2.genes()[start..end]);
+            assert_eq!(child2.genes()[start..end], parent1.genes()[start..end]);
+        }
+    }
+
+    #[test]
+    fn ordered_fills_in_the_other_order() {
+        let keep = Permutation::identity(9);
+        let fill = permutation(&[8, 7, 6, 5, 4, 3, 2, 1, 0]);
+        assert_eq!(ordered_child(&keep, &fill, 3, 6), vec![8, 7, 6, 3, 4, 5, 2, 1, 0]);
+    }
+
+    #[test]
+    fn cycle_swaps_odd_cycles() {
+        let mut child1 = Permutation::identity(8);
+        let mut child2 = permutation(&[1, 2, 0, 4, 3, 6, 7, 5]);
+        let rng = StdRng::from_seed(&[42usize][..]);
+        PermutationCrossover::cycle().mate(&mut child1, &mut child2, rng);
+        assert_eq!(child1.genes(), &[0, 1, 2, 4, 3, 5, 6, 7][..]);
+        assert_eq!(child2.genes(), &[1, 2, 0, 3, 4, 6, 7, 5][..]);
+    }
+
+    #[test]
+    fn operators_keep_permutations_valid() {
+        let mut rng = StdRng::from_seed(&[42usize][..]);
+        let crossovers = [PermutationCrossover::partially_mapped(),
+                          PermutationCrossover::ordered(),
+                          PermutationCrossover::cycle()];
+        let mutations = [PermutationMutation::swap(),
+                         PermutationMutation::inversion(),
+                         PermutationMutation::scramble()];
+        for _ in 0..50 {
+            let mut permutation1 = Permutation::random(&mut rng, 12);
+            let mut permutation2 = Permutation::random(&mut rng, 12);
+            for crossover in &crossovers {
+                crossover.mate(&mut permutation1, &mut permutation2, &mut rng);
+                assert!(is_valid(&permutation1) && is_valid(&permutation2));
+            }
+            for mutation in &mutations {
+                mutation.mutate(&mut permutation1, &mut rng);
+                assert!(is_valid(&permutation1));
+            }
+        }
+    }
+
+    #[test]
+    fn mutations_change_one_section() {
+        let mut rng = StdRng::from_seed(&[42usize][..]);
+        for _ in 0..50 {
+            let mut swapped = Permutation::identity(10);
+            PermutationMutation::swap().mutate(&mut swapped, &mut rng);
+            assert!((0..10).filter(|&i| swapped.genes()[i] != i).count() <= 2);
+
+            let mut inverted = Permutation::identity(10);
+            PermutationMutation::inversion().mutate(&mut inverted, &mut rng);
+            let changed: Vec<usize> = (0..10).filter(|&i| inverted.genes()[i] != i).collect();
+            if let (Some(&first), Some(&last)) = (changed.first(), changed.last()) {
+                let section: Vec<usize> = (first..last + 1).rev().collect();
+                assert_eq!(inverted.genes()[first..last + 1], section[..]);
+            }
+        }
+        let mut single = Permutation::identity(1);
+        PermutationMutation::scramble().mutate(&mut single, &mut rng);
+        assert_eq!(single, Permutation::identity(1));
+    }
+}
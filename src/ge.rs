@@ -0,0 +1,352 @@
+use gp::grammar::*;
+use gp::tree::*;
+use rand::Rng;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+/// A Grammatical Evolution genome: a variable-length vector of integer codons.
+///
+/// Genomes are turned into programs by a `Mapper`.
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub struct Genome {
+    /// The codons, read from the start by the `Mapper`.
+    pub codons: Vec<u32>,
+}
+
+impl Genome {
+    /// Create a genome from codons.
+    pub fn new(codons: Vec<u32>) -> Genome {
+        Genome { codons }
+    }
+
+    /// Generate a random genome with `length` codons, each below `codon_size`.
+    pub fn random<R: Rng>(rng: &mut R, length: usize, codon_size: u32) -> Genome {
+        Genome { codons: (0..length).map(|_| rng.gen_range(0, codon_size)).collect() }
+    }
+
+    /// Number of codons.
+    pub fn len(&self) -> usize {
+        self.codons.len()
+    }
+
+    /// Whether there are no codons.
+    pub fn is_empty(&self) -> bool {
+        self.codons.is_empty()
+    }
+}
+
+/// Maps genomes to programs through a grammar.
+///
+/// The leftmost non-terminal is expanded repeatedly, starting from the grammar's start
+/// symbol. Rules with several productions read the next codon and use the production at
+/// the codon modulo their number of productions; rules with a single production read no
+/// codon. Reaching the end of the genome wraps back to its start, up to `max_wraps` times.
+#[derive(Clone, Debug)]
+pub struct Mapper {
+    grammar: Rc<Grammar>,
+    max_wraps: usize,
+}
+
+impl Mapper {
+    /// Create a mapper for a grammar, allowing no wrapping.
+    pub fn new(grammar: Rc<Grammar>) -> Mapper {
+        Mapper {
+            grammar,
+            max_wraps: 0,
+        }
+    }
+
+    /// Allow reading the genome up to `max_wraps` more times after reaching its end.
+    pub fn max_wraps(mut self, max_wraps: usize) -> Mapper {
+        self.max_wraps = max_wraps;
+        self
+    }
+
+    /// Get the grammar genomes are mapped through.
+    pub fn grammar(&self) -> &Rc<Grammar> {
+        &self.grammar
+    }
+
+    /// Map a genome to a derivation tree.
+    pub fn map(&self, genome: &Genome) -> Result<Mapping, MappingError> {
+        let mut state = MappingState {
+            codons: &genome.codons,
+            position: 0,
+            wraps: 0,
+            max_wraps: self.max_wraps,
+        };
+        let tree = self.expand(0, &mut state)?;
+        Ok(Mapping {
+            tree,
+            codons_used: state.wraps * genome.codons.len() + state.position,
+            wraps: state.wraps,
+        })
+    }
+
+    /// Map a genome straight to the program text it derives.
+    pub fn map_string(&self, genome: &Genome) -> Result<String, MappingError> {
+        self.map(genome).map(|mapping| mapping.tree.evaluate(&()))
+    }
+
+    fn expand(&self,
+              rule: usize,
+              state: &mut MappingState)
+              -> Result<BoxTree<Derivation>, MappingError> {
+        let productions = &self.grammar.rules()[rule].productions;
+        let production = if productions.len() > 1 {
+            state.next_codon()? as usize % productions.len()
+        } else {
+            0
+        };
+        let mut children = vec![];
+        for symbol in &productions[production] {
+            if let Symbol::NonTerminal(child_rule) = *symbol {
+                children.push(self.expand(child_rule, state)?);
+            }
+        }
+        Ok(Derivation::new(self.grammar.clone(), rule, production, children))
+    }
+}
+
+/// Where mapping has read up to in a genome.
+struct MappingState<'a> {
+    codons: &'a [u32],
+    position: usize,
+    wraps: usize,
+    max_wraps: usize,
+}
+
+impl<'a> MappingState<'a> {
+    fn next_codon(&mut self) -> Result<u32, MappingError> {
+        if self.position == self.codons.len() {
+            if self.codons.is_empty() || self.wraps == self.max_wraps {
+                return Err(MappingError::OutOfCodons);
+            }
+            self.wraps += 1;
+            self.position = 0;
+        }
+        self.position += 1;
+        Ok(self.codons[self.position - 1])
+    }
+}
+
+/// A genome mapped to a program.
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    /// The derivation tree of the program. Evaluate it to get the program text.
+    pub tree: BoxTree<Derivation>,
+    /// Number of codons read, counting codons read again after wrapping.
+    pub codons_used: usize,
+    /// Number of times the genome wrapped.
+    pub wraps: usize,
+}
+
+/// Errors from mapping a genome.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum MappingError {
+    /// The genome ran out of codons, after wrapping as many times as allowed, with
+    /// non-terminals still to expand.
+    OutOfCodons,
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MappingError::OutOfCodons => write!(f, "genome ran out of codons"),
+        }
+    }
+}
+
+impl Error for MappingError {}
+
+/// The genome crossover mode in use. See `GenomeCrossover`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum GenomeCrossoverMode {
+    /// Corresponds to `GenomeCrossover::one_point`.
+    OnePoint,
+    /// Corresponds to `GenomeCrossover::fixed_one_point`.
+    FixedOnePoint,
+}
+
+/// Configures crossover (mating) between genomes.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct GenomeCrossover {
+    mode: GenomeCrossoverMode,
+}
+
+impl GenomeCrossover {
+    /// Get an operator to perform variable-length one-point crossover.
+    ///
+    /// A cut point is chosen independently in each genome and the codons after the cut
+    /// points are swapped, so genome lengths can change.
+    pub fn one_point() -> GenomeCrossover {
+        GenomeCrossover { mode: GenomeCrossoverMode::OnePoint }
+    }
+
+    /// Get an operator to perform fixed one-point crossover.
+    ///
+    /// The same cut point is used in both genomes, so genomes of equal length keep it.
+    pub fn fixed_one_point() -> GenomeCrossover {
+        GenomeCrossover { mode: GenomeCrossoverMode::FixedOnePoint }
+    }
+
+    /// Crossover (mate) two genomes according to the configured crossover mode.
+    pub fn mate<R>(&self, genome1: &mut Genome, genome2: &mut Genome, mut rng: R)
+        where R: Rng
+    {
+        let (cut1, cut2) = match self.mode {
+            GenomeCrossoverMode::OnePoint => {
+                (rng.gen_range(0, genome1.len() + 1), rng.gen_range(0, genome2.len() + 1))
+            }
+            GenomeCrossoverMode::FixedOnePoint => {
+                let cut = rng.gen_range(0, genome1.len().min(genome2.len()) + 1);
+                (cut, cut)
+            }
+        };
+        let tail1 = genome1.codons.split_off(cut1);
+        let tail2 = genome2.codons.split_off(cut2);
+        genome1.codons.extend(tail2);
+        genome2.codons.extend(tail1);
+    }
+}
+
+/// The genome mutation mode in use. See `GenomeMutation`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum GenomeMutationMode {
+    /// Corresponds to `GenomeMutation::int_flip`.
+    IntFlip(f64, u32),
+    /// Corresponds to `GenomeMutation::duplicate`.
+    Duplicate,
+}
+
+/// Configures mutation of genomes.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct GenomeMutation {
+    mode: GenomeMutationMode,
+}
+
+impl GenomeMutation {
+    /// Replace each codon, with `probability`, by a random codon below `codon_size`.
+    pub fn int_flip(probability: f64, codon_size: u32) -> GenomeMutation {
+        GenomeMutation { mode: GenomeMutationMode::IntFlip(probability, codon_size) }
+    }
+
+    /// Copy a random run of codons onto the end of the genome.
+    pub fn duplicate() -> GenomeMutation {
+        GenomeMutation { mode: GenomeMutationMode::Duplicate }
+    }
+
+    /// Mutate a genome according to the configured mutation mode.
+    pub fn mutate<R>(&self, genome: &mut Genome, mut rng: R)
+        where R: Rng
+    {
+        match self.mode {
+            GenomeMutationMode::IntFlip(probability, codon_size) => {
+                for codon in &mut genome.codons {
+                    if rng.gen::<f64>() < probability {
+                        *codon = rng.gen_range(0, codon_size);
+                    }
+                }
+            }
+            GenomeMutationMode::Duplicate => {
+                if genome.is_empty() {
+                    return;
+                }
+                let start = rng.gen_range(0, genome.len());
+                let end = rng.gen_range(start + 1, genome.len() + 1);
+                let run = genome.codons[start..end].to_vec();
+                genome.codons.extend(run);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn mapper() -> Mapper {
+        let grammar = Grammar::parse("
+            <expr> ::= <expr> \"+\" <expr> | <var>
+            <var>  ::= \"x\" | \"y\"
+        ");
+        Mapper::new(Rc::new(grammar.unwrap()))
+    }
+
+    #[test]
+    fn maps_leftmost_non_terminal_first() {
+        let mapping = mapper().map(&Genome::new(vec![0, 1, 0, 1, 1])).unwrap();
+        assert_eq!(mapping.tree.evaluate(&()), "x+y");
+        assert_eq!((mapping.codons_used, mapping.wraps), (5, 0));
+        assert_eq!(mapper().map_string(&Genome::new(vec![2, 3, 4, 5, 7, 9])),
+                   Ok("x+y".to_string()));
+    }
+
+    #[test]
+    fn single_productions_read_no_codons() {
+        let grammar = Grammar::parse("<s> ::= <var> \"!\" \n <var> ::= \"x\" | \"y\"").unwrap();
+        let mapping = Mapper::new(Rc::new(grammar)).map(&Genome::new(vec![1])).unwrap();
+        assert_eq!(mapping.tree.evaluate(&()), "y!");
+        assert_eq!(mapping.codons_used, 1);
+    }
+
+    #[test]
+    fn wrapping() {
+        let genome = Genome::new(vec![1]);
+        assert_eq!(mapper().map(&genome).unwrap_err(), MappingError::OutOfCodons);
+        let mapping = mapper().max_wraps(1).map(&genome).unwrap();
+        assert_eq!(mapping.tree.evaluate(&()), "y");
+        assert_eq!((mapping.codons_used, mapping.wraps), (2, 1));
+        assert_eq!(mapper().max_wraps(3).map(&Genome::new(vec![0])).unwrap_err(),
+                   MappingError::OutOfCodons);
+        assert_eq!(mapper().max_wraps(3).map(&Genome::new(vec![])).unwrap_err(),
+                   MappingError::OutOfCodons);
+    }
+
+    #[test]
+    fn crossover_swaps_tails() {
+        let mut rng = StdRng::from_seed(&[38usize][..]);
+        for _ in 0..50 {
+            let mut genome1 = Genome::random(&mut rng, 6, 10);
+            let mut genome2 = Genome::random(&mut rng, 9, 10);
+            let mut before: Vec<u32> =
+                genome1.codons.iter().chain(&genome2.codons).cloned().collect();
+            GenomeCrossover::one_point().mate(&mut genome1, &mut genome2, &mut rng);
+            let mut after: Vec<u32> =
+                genome1.codons.iter().chain(&genome2.codons).cloned().collect();
+            before.sort();
+            after.sort();
+            assert_eq!(after, before);
+
+            let mut genome3 = Genome::random(&mut rng, 8, 10);
+            let mut genome4 = Genome::random(&mut rng, 8, 10);
+            GenomeCrossover::fixed_one_point().mate(&mut genome3, &mut genome4, &mut rng);
+            assert_eq!((genome3.len(), genome4.len()), (8, 8));
+        }
+    }
+
+    #[test]
+    fn mutation() {
+        let mut rng = StdRng::from_seed(&[38usize][..]);
+        let genome = Genome::random(&mut rng, 20, 100);
+        let mut unchanged = genome.clone();
+        GenomeMutation::int_flip(0.0, 4).mutate(&mut unchanged, &mut rng);
+        assert_eq!(unchanged, genome);
+        let mut flipped = genome.clone();
+        GenomeMutation::int_flip(1.0, 4).mutate(&mut flipped, &mut rng);
+        assert_eq!(flipped.len(), genome.len());
+        assert!(flipped.codons.iter().all(|&codon| codon < 4));
+
+        let mut duplicated = genome.clone();
+        GenomeMutation::duplicate().mutate(&mut duplicated, &mut rng);
+        let run = &duplicated.codons[genome.len()..];
+        assert_eq!(duplicated.codons[..genome.len()], genome.codons[..]);
+        assert!(!run.is_empty());
+        assert!(genome.codons.windows(run.len()).any(|window| window == run));
+        let mut empty = Genome::new(vec![]);
+        GenomeMutation::duplicate().mutate(&mut empty, &mut rng);
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+use gp::tree::*;
+use rand::Rng;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+/// The Santa Fe trail: 89 pieces of food on a 32 by 32 toroidal grid, starting at `S`.
+const SANTA_FE_TRAIL: &str = "\
+S###............................
+...#............................
+...#.....................###....
+...#....................#....#..
+...#....................#....#..
+...####.#####........##.........
+............#................#..
+............#.......#...........
+............#.......#...........
+............#.......#........#..
+....................#...........
+............#...................
+............#................#..
+............#.......#...........
+............#.......#.....###...
+.................#.....#........
+................................
+............#...................
+............#...#.......#.......
+............#...#..........#....
+............#...#...............
+............#...#...............
+............#.............#.....
+............#..........#........
+...##..#####....#...............
+.#..............#...............
+.#..............#...............
+.#......#######.................
+.#.....#........................
+.......#........................
+..####..........................
+................................";
+
+/// Program for an artificial ant, following a trail of food.
+///
+/// Evaluating the program runs it once against a `Trail`, moving the ant. Ant programs are
+/// run repeatedly until the trail runs out of steps; see `SantaFeAnt`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Ant {
+    /// Run the first program if there is food in the cell ahead, and otherwise the second.
+    IfFoodAhead(BoxTree<Ant>, BoxTree<Ant>),
+    /// Run two programs in turn.
+    Prog2(BoxTree<Ant>, BoxTree<Ant>),
+    /// Run three programs in turn.
+    Prog3(BoxTree<Ant>, BoxTree<Ant>, BoxTree<Ant>),
+    /// Move forward one cell, eating any food there.
+    Move,
+    /// Turn left.
+    Left,
+    /// Turn right.
+    Right,
+}
+
+impl Tree for Ant {
+    type Environment = Trail;
+    type Action = ();
+
+    fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Self> {
+        let kind = tg.choose_branch(3);
+        let mut child = || Self::child(tg, current_depth + 1);
+        match kind {
+                0 => Ant::IfFoodAhead(child(), child()),
+                1 => Ant::Prog2(child(), child()),
+                2 => Ant::Prog3(child(), child(), child()),
+                _ => unreachable!(),
+            }
+            .into()
+    }
+
+    fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Self> {
+        match tg.choose_leaf(3) {
+                0 => Ant::Move,
+                1 => Ant::Left,
+                2 => Ant::Right,
+                _ => unreachable!(),
+            }
+            .into()
+    }
+
+    fn count_children(&mut self) -> usize {
+        self.children().len()
+    }
+
+    fn children(&self) -> Vec<&BoxTree<Self>> {
+        match *self {
+            Ant::IfFoodAhead(ref a, ref b) |
+            Ant::Prog2(ref a, ref b) => vec![a, b],
+            Ant::Prog3(ref a, ref b, ref c) => vec![a, b, c],
+            Ant::Move | Ant::Left | Ant::Right => vec![],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut BoxTree<Self>> {
+        match *self {
+            Ant::IfFoodAhead(ref mut a, ref mut b) |
+            Ant::Prog2(ref mut a, ref mut b) => vec![a, b],
+            Ant::Prog3(ref mut a, ref mut b, ref mut c) => vec![a, b, c],
+            Ant::Move | Ant::Left | Ant::Right => vec![],
+        }
+    }
+
+    fn evaluate(&self, trail: &Trail) {
+        match *self {
+            Ant::IfFoodAhead(ref a, ref b) => {
+                if trail.food_ahead() {
+                    a.evaluate(trail)
+                } else {
+                    b.evaluate(trail)
+                }
+            }
+            Ant::Prog2(ref a, ref b) => {
+                a.evaluate(trail);
+                b.evaluate(trail);
+            }
+            Ant::Prog3(ref a, ref b, ref c) => {
+                a.evaluate(trail);
+                b.evaluate(trail);
+                c.evaluate(trail);
+            }
+            Ant::Move => trail.act(|heading, position| (heading, trail.ahead(heading, position))),
+            Ant::Left => trail.act(|heading, position| ((heading + 3) % 4, position)),
+            Ant::Right => trail.act(|heading, position| ((heading + 1) % 4, position)),
+        }
+    }
+}
+
+impl fmt::Display for Ant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Ant::IfFoodAhead(ref a, ref b) => write!(f, "if_food_ahead({}, {})", a, b),
+            Ant::Prog2(ref a, ref b) => write!(f, "prog2({}, {})", a, b),
+            Ant::Prog3(ref a, ref b, ref c) => write!(f, "prog3({}, {}, {})", a, b, c),
+            Ant::Move => write!(f, "move"),
+            Ant::Left => write!(f, "left"),
+            Ant::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// A toroidal grid of food for an `Ant` to eat, with the ant's position.
+///
+/// Ant programs move the ant while being evaluated, which only borrows the trail, so its
+/// state is kept in `Cell`s. Each move or turn takes a step; once the steps run out the ant
+/// stops.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trail {
+    width: usize,
+    height: usize,
+    food: RefCell<Vec<bool>>,
+    total_food: usize,
+    eaten: Cell<usize>,
+    /// 0 for east, then clockwise.
+    heading: Cell<usize>,
+    position: Cell<(usize, usize)>,
+    steps: Cell<usize>,
+    max_steps: usize,
+}
+
+impl Trail {
+    /// The Santa Fe trail, with the ant at the top left facing east and 600 steps.
+    pub fn santa_fe() -> Trail {
+        let rows: Vec<&str> = SANTA_FE_TRAIL.lines().collect();
+        let food: Vec<bool> = rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect();
+        Trail {
+            width: rows[0].len(),
+            height: rows.len(),
+            total_food: food.iter().filter(|&&food| food).count(),
+            food: RefCell::new(food),
+            eaten: Cell::new(0),
+            heading: Cell::new(0),
+            position: Cell::new((0, 0)),
+            steps: Cell::new(0),
+            max_steps: 600,
+        }
+    }
+
+    /// Check if there is food in the cell ahead of the ant.
+    pub fn food_ahead(&self) -> bool {
+        let (x, y) = self.ahead(self.heading.get(), self.position.get());
+        self.food.borrow()[y * self.width + x]
+    }
+
+    /// Get the amount of food eaten.
+    pub fn eaten(&self) -> usize {
+        self.eaten.get()
+    }
+
+    /// Get the amount of food on the trail at the start.
+    pub fn total_food(&self) -> usize {
+        self.total_food
+    }
+
+    /// Get the number of steps taken.
+    pub fn steps(&self) -> usize {
+        self.steps.get()
+    }
+
+    /// Check if the ant has run out of steps or eaten all the food.
+    pub fn is_finished(&self) -> bool {
+        self.steps.get() >= self.max_steps || self.eaten.get() == self.total_food
+    }
+
+    fn ahead(&self, heading: usize, (x, y): (usize, usize)) -> (usize, usize) {
+        match heading {
+            0 => ((x + 1) % self.width, y),
+            1 => (x, (y + 1) % self.height),
+            2 => ((x + self.width - 1) % self.width, y),
+            _ => (x, (y + self.height - 1) % self.height),
+        }
+    }
+
+    /// Take a step, changing the ant's heading and position, and eat any food it reaches.
+    fn act<F>(&self, f: F)
+        where F: FnOnce(usize, (usize, usize)) -> (usize, (usize, usize))
+    {
+        if self.steps.get() >= self.max_steps {
+            return;
+        }
+        self.steps.set(self.steps.get() + 1);
+        let (heading, (x, y)) = f(self.heading.get(), self.position.get());
+        self.heading.set(heading);
+        self.position.set((x, y));
+        let mut food = self.food.borrow_mut();
+        if food[y * self.width + x] {
+            food[y * self.width + x] = false;
+            self.eaten.set(self.eaten.get() + 1);
+        }
+    }
+}
+
+/// The Santa Fe artificial ant benchmark: eat as much of the Santa Fe trail's food as
+/// possible within 600 steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SantaFeAnt {
+    trail: Trail,
+}
+
+impl SantaFeAnt {
+    /// Create the benchmark.
+    pub fn new() -> SantaFeAnt {
+        SantaFeAnt { trail: Trail::santa_fe() }
+    }
+
+    /// Run an ant program repeatedly on a fresh trail until it finishes. Returns the trail
+    /// afterwards.
+    pub fn run(&self, tree: &BoxTree<Ant>) -> Trail {
+        let trail = self.trail.clone();
+        while !trail.is_finished() {
+            tree.evaluate(&trail);
+        }
+        trail
+    }
+
+    /// Amount of food an ant program leaves uneaten, to be minimised.
+    pub fn fitness(&self, tree: &BoxTree<Ant>) -> usize {
+        let trail = self.run(tree);
+        trail.total_food() - trail.eaten()
+    }
+}
+
+impl Default for SantaFeAnt {
+    fn default() -> SantaFeAnt {
+        SantaFeAnt::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn node(ant: Ant) -> BoxTree<Ant> {
+        ant.into()
+    }
+
+    #[test]
+    fn santa_fe_trail_has_89_food() {
+        let trail = Trail::santa_fe();
+        assert_eq!(trail.total_food(), 89);
+        assert_eq!((trail.width, trail.height), (32, 32));
+        assert!(trail.food_ahead());
+        assert!(!trail.is_finished());
+    }
+
+    #[test]
+    fn ants_are_limited_to_600_steps() {
+        let ant = SantaFeAnt::new();
+        // Turning on the spot eats nothing.
+        let trail = ant.run(&node(Ant::Left));
+        assert_eq!((trail.eaten(), trail.steps()), (0, 600));
+        assert_eq!(ant.fitness(&node(Ant::Right)), 89);
+        // Walking east around the top row eats its three pieces of food.
+        let trail = ant.run(&node(Ant::Move));
+        assert_eq!((trail.eaten(), trail.steps()), (3, 600));
+    }
+
+    #[test]
+    fn following_the_trail_eats_more() {
+        let ant = SantaFeAnt::new();
+        // Move while there is food ahead, otherwise turn to look for it.
+        let follower = node(Ant::IfFoodAhead(node(Ant::Move),
+                                             node(Ant::Prog3(node(Ant::Left),
+                                                             node(Ant::Right),
+                                                             node(Ant::Right)))));
+        assert_eq!(follower.to_string(), "if_food_ahead(move, prog3(left, right, right))");
+        assert!(ant.fitness(&follower) < ant.fitness(&node(Ant::Move)));
+
+        let mut rng = StdRng::from_seed(&[50usize][..]);
+        let mut tg = TreeGen::full(&mut rng, 1, 4);
+        for _ in 0..20 {
+            let trail = ant.run(&Ant::tree(&mut tg));
+            assert!(trail.eaten() <= 89 && trail.steps() <= 600);
+            assert!(trail.is_finished());
+        }
+    }
+}
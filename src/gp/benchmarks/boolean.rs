@@ -0,0 +1,296 @@
+use gp::tree::*;
+use rand::Rng;
+use std::fmt;
+
+/// A gate which can appear in a `Boolean` circuit.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum Gate {
+    /// `a && b`.
+    And,
+    /// `a || b`.
+    Or,
+    /// `!(a && b)`.
+    Nand,
+    /// `!(a || b)`.
+    Nor,
+    /// `!a`.
+    Not,
+    /// `if a { b } else { c }`.
+    If,
+}
+
+impl Gate {
+    /// Every gate, in the order used for branch weights.
+    pub const ALL: [Gate; 6] = [Gate::And, Gate::Or, Gate::Nand, Gate::Nor, Gate::Not, Gate::If];
+
+    /// Number of inputs the gate takes.
+    pub fn arity(self) -> usize {
+        match self {
+            Gate::Not => 1,
+            Gate::If => 3,
+            _ => 2,
+        }
+    }
+
+    /// Apply the gate to its inputs.
+    pub fn apply(self, args: &[bool]) -> bool {
+        match self {
+            Gate::And => args[0] && args[1],
+            Gate::Or => args[0] || args[1],
+            Gate::Nand => !(args[0] && args[1]),
+            Gate::Nor => !(args[0] || args[1]),
+            Gate::Not => !args[0],
+            Gate::If => if args[0] { args[1] } else { args[2] },
+        }
+    }
+
+    /// Lowercase name of the gate, such as `and` or `if`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Gate::And => "and",
+            Gate::Or => "or",
+            Gate::Nand => "nand",
+            Gate::Nor => "nor",
+            Gate::Not => "not",
+            Gate::If => "if",
+        }
+    }
+}
+
+/// A Boolean circuit over numbered inputs, for the multiplexer and parity benchmarks.
+///
+/// Generate circuits with the weights of a `BooleanBenchmark`. Leaf weights are one per
+/// input; without weights every gate is used, with one input.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Boolean {
+    /// A gate applied to its inputs.
+    Apply(Gate, Vec<BoxTree<Boolean>>),
+    /// An input.
+    Input(usize),
+}
+
+impl Tree for Boolean {
+    type Environment = Vec<bool>;
+    type Action = bool;
+
+    fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Self> {
+        let gate = Gate::ALL[tg.choose_branch(Gate::ALL.len())];
+        let args = (0..gate.arity()).map(|_| Self::child(tg, current_depth + 1)).collect();
+        Boolean::Apply(gate, args).into()
+    }
+
+    fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Self> {
+        let inputs = tg.leaf_weights().len().max(1);
+        Boolean::Input(tg.choose_leaf(inputs)).into()
+    }
+
+    fn count_children(&mut self) -> usize {
+        match *self {
+            Boolean::Apply(_, ref args) => args.len(),
+            Boolean::Input(_) => 0,
+        }
+    }
+
+    fn children(&self) -> Vec<&BoxTree<Self>> {
+        match *self {
+            Boolean::Apply(_, ref args) => args.iter().collect(),
+            Boolean::Input(_) => vec![],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut BoxTree<Self>> {
+        match *self {
+            Boolean::Apply(_, ref mut args) => args.iter_mut().collect(),
+            Boolean::Input(_) => vec![],
+        }
+    }
+
+    fn evaluate(&self, env: &Vec<bool>) -> bool {
+        match *self {
+            // Evaluated lazily, so `if` only evaluates the branch it takes.
+            Boolean::Apply(Gate::If, ref args) => {
+                if args[0].evaluate(env) {
+                    args[1].evaluate(env)
+                } else {
+                    args[2].evaluate(env)
+                }
+            }
+            Boolean::Apply(gate, ref args) => {
+                let values: Vec<bool> = args.iter().map(|arg| arg.evaluate(env)).collect();
+                gate.apply(&values)
+            }
+            Boolean::Input(index) => env[index],
+        }
+    }
+}
+
+impl fmt::Display for Boolean {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Boolean::Apply(gate, ref args) => {
+                write!(f, "{}(", gate.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Boolean::Input(index) => write!(f, "x{}", index),
+        }
+    }
+}
+
+/// A Boolean benchmark: every combination of inputs with the expected output, and the
+/// gates it is usually solved with.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct BooleanBenchmark {
+    name: String,
+    inputs: usize,
+    gates: Vec<Gate>,
+    cases: Vec<(Vec<bool>, bool)>,
+}
+
+impl BooleanBenchmark {
+    /// The multiplexer with `address_bits` address inputs, such as 2 for the 6-bit and 3 for
+    /// the 11-bit multiplexer. Uses and, or, not and if.
+    ///
+    /// Inputs `x0` upwards are the address, least significant first, then the data. The
+    /// output is the addressed data input.
+    pub fn multiplexer(address_bits: usize) -> BooleanBenchmark {
+        let data_bits = 1 << address_bits;
+        Self::new(format!("{}-multiplexer", address_bits + data_bits),
+                  address_bits + data_bits,
+                  vec![Gate::And, Gate::Or, Gate::Not, Gate::If],
+                  |inputs| {
+                      let address = (0..address_bits)
+                          .filter(|&bit| inputs[bit])
+                          .fold(0, |address, bit| address | 1 << bit);
+                      inputs[address_bits + address]
+                  })
+    }
+
+    /// Even-`n`-parity: whether an even number of the `n` inputs are true. Uses and, or,
+    /// nand and nor.
+    pub fn even_parity(n: usize) -> BooleanBenchmark {
+        Self::new(format!("even-{}-parity", n),
+                  n,
+                  vec![Gate::And, Gate::Or, Gate::Nand, Gate::Nor],
+                  |inputs| inputs.iter().filter(|&&input| input).count() % 2 == 0)
+    }
+
+    fn new<F>(name: String, inputs: usize, gates: Vec<Gate>, target: F) -> BooleanBenchmark
+        where F: Fn(&[bool]) -> bool
+    {
+        let cases = (0..1usize << inputs)
+            .map(|case| {
+                let inputs: Vec<bool> = (0..inputs).map(|bit| case & (1 << bit) != 0).collect();
+                let output = target(&inputs);
+                (inputs, output)
+            })
+            .collect();
+        BooleanBenchmark {
+            name,
+            inputs,
+            gates,
+            cases,
+        }
+    }
+
+    /// Get the benchmark's name, such as `6-multiplexer`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the number of inputs.
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// Get every combination of inputs with its expected output.
+    pub fn cases(&self) -> &[(Vec<bool>, bool)] {
+        &self.cases
+    }
+
+    /// Get the weight of each gate, in the order of `Gate::ALL`, for
+    /// `TreeGen::with_weights`.
+    pub fn branch_weights(&self) -> Vec<u32> {
+        Gate::ALL.iter().map(|gate| if self.gates.contains(gate) { 1 } else { 0 }).collect()
+    }
+
+    /// Get the weight of each input, for `TreeGen::with_weights`.
+    pub fn leaf_weights(&self) -> Vec<u32> {
+        vec![1; self.inputs]
+    }
+
+    /// Number of cases a circuit gets wrong, to be minimised.
+    pub fn fitness(&self, tree: &BoxTree<Boolean>) -> usize {
+        self.cases.iter().filter(|&&(ref inputs, output)| tree.evaluate(inputs) != output).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn input(index: usize) -> BoxTree<Boolean> {
+        Boolean::Input(index).into()
+    }
+
+    fn apply(gate: Gate, args: Vec<BoxTree<Boolean>>) -> BoxTree<Boolean> {
+        Boolean::Apply(gate, args).into()
+    }
+
+    #[test]
+    fn multiplexer_is_solved_by_its_definition() {
+        let multiplexer = BooleanBenchmark::multiplexer(2);
+        assert_eq!(multiplexer.name(), "6-multiplexer");
+        assert_eq!((multiplexer.inputs(), multiplexer.cases().len()), (6, 64));
+        // Address bit 1 picks a half of the data inputs and bit 0 an input from that half.
+        let solution = apply(Gate::If,
+                             vec![input(1),
+                                  apply(Gate::If, vec![input(0), input(5), input(4)]),
+                                  apply(Gate::If, vec![input(0), input(3), input(2)])]);
+        assert_eq!(solution.to_string(), "if(x1, if(x0, x5, x4), if(x0, x3, x2))");
+        assert_eq!(multiplexer.fitness(&solution), 0);
+        assert_eq!(multiplexer.fitness(&input(2)), 24);
+        assert_eq!(BooleanBenchmark::multiplexer(3).cases().len(), 2048);
+    }
+
+    #[test]
+    fn even_parity_cases() {
+        let parity = BooleanBenchmark::even_parity(3);
+        assert_eq!(parity.name(), "even-3-parity");
+        assert_eq!(parity.cases().iter().filter(|&&(_, output)| output).count(), 4);
+        assert!(parity.cases().contains(&(vec![false; 3], true)));
+        assert!(parity.cases().contains(&(vec![true, true, false], true)));
+        assert!(parity.cases().contains(&(vec![true, true, true], false)));
+        // `nor(nor(a, b), and(a, b))` is odd parity, so gets every case wrong.
+        let even_2 = apply(Gate::Nor,
+                           vec![apply(Gate::Nor, vec![input(0), input(1)]),
+                                apply(Gate::And, vec![input(0), input(1)])]);
+        assert_eq!(BooleanBenchmark::even_parity(2).fitness(&even_2), 4);
+        assert_eq!(parity.branch_weights(), vec![1, 1, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn circuits_follow_the_weights() {
+        let parity = BooleanBenchmark::even_parity(4);
+        let mut rng = StdRng::from_seed(&[50usize][..]);
+        let mut tg = TreeGen::full(&mut rng, 1, 4)
+            .with_weights(parity.branch_weights(), parity.leaf_weights());
+        for _ in 0..20 {
+            for node in Boolean::tree(&mut tg).pre_order() {
+                match **node.tree {
+                    Boolean::Apply(gate, ref args) => {
+                        assert!(gate != Gate::Not && gate != Gate::If);
+                        assert_eq!(args.len(), 2);
+                    }
+                    Boolean::Input(index) => assert!(index < 4),
+                }
+            }
+        }
+    }
+}
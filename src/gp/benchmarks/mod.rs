@@ -0,0 +1,10 @@
+mod ant;
+mod boolean;
+mod symbolic;
+
+/// The Santa Fe artificial ant.
+pub use self::ant::*;
+/// Boolean multiplexer and even parity benchmarks.
+pub use self::boolean::*;
+/// Koza, Nguyen, Keijzer and Pagie symbolic regression benchmarks.
+pub use self::symbolic::*;
@@ -0,0 +1,340 @@
+use gp::*;
+use gp::regression::*;
+use rand::Rng;
+use std::f64;
+use std::f64::consts::PI;
+
+/// A symbolic regression benchmark: a target function sampled into training and test sets,
+/// with the primitives it is usually solved with.
+///
+/// Sampling follows McDermott et al., "Genetic Programming Needs Better Benchmarks" (2012).
+/// `U[a, b, n]` draws `n` samples uniformly from `[a, b)` and `E[a, b, s]` takes every `s`
+/// from `a` to `b` inclusive, over a grid for several variables. Where a benchmark defines
+/// no test set, one sampled by `U` tests on a fresh draw from the training distribution, and
+/// one sampled by `E` tests on the training grid itself.
+#[derive(PartialEq, Clone, Debug)]
+pub struct RegressionBenchmark {
+    name: String,
+    primitives: PrimitiveSet,
+    train: Dataset,
+    test: Dataset,
+}
+
+impl RegressionBenchmark {
+    /// Koza-1, `x^4 + x^3 + x^2 + x` on U[-1, 1, 20].
+    pub fn koza_1<R: Rng>(rng: &mut R) -> RegressionBenchmark {
+        Self::koza("Koza-1", |x| x[0].powi(4) + x[0].powi(3) + x[0].powi(2) + x[0], rng)
+    }
+
+    /// Koza-2, `x^5 - 2x^3 + x` on U[-1, 1, 20].
+    pub fn koza_2<R: Rng>(rng: &mut R) -> RegressionBenchmark {
+        Self::koza("Koza-2", |x| x[0].powi(5) - 2.0 * x[0].powi(3) + x[0], rng)
+    }
+
+    /// Koza-3, `x^6 - 2x^4 + x^2` on U[-1, 1, 20].
+    pub fn koza_3<R: Rng>(rng: &mut R) -> RegressionBenchmark {
+        Self::koza("Koza-3", |x| x[0].powi(6) - 2.0 * x[0].powi(4) + x[0].powi(2), rng)
+    }
+
+    fn koza<F, R>(name: &str, target: F, rng: &mut R) -> RegressionBenchmark
+        where F: Fn(&[f64]) -> f64,
+              R: Rng
+    {
+        let range = [(-1.0, 1.0)];
+        Self::new(name,
+                  koza_primitives(1),
+                  Dataset::from_function(uniform(rng, &range, 20), &target),
+                  Dataset::from_function(uniform(rng, &range, 20), &target))
+    }
+
+    /// Nguyen-`n`, for `n` from 1 to 12.
+    ///
+    /// Nguyen-1 to 6 are `x^3 + x^2 + x`, `x^4 + ... + x`, `x^5 + ... + x`, `x^6 + ... + x`,
+    /// `sin(x^2)cos(x) - 1` and `sin(x) + sin(x + x^2)`, on U[-1, 1, 20]. Nguyen-7 is
+    /// `log(x + 1) + log(x^2 + 1)` on U[0, 2, 20] and Nguyen-8 is `sqrt(x)` on U[0, 4, 20].
+    /// Nguyen-9 to 12 have two variables: `sin(x) + sin(y^2)`, `2sin(x)cos(y)`, `x^y` and
+    /// `x^4 - x^3 + y^2/2 - y`, on U[-1, 1, 100] except Nguyen-11 on U[0, 1, 100].
+    pub fn nguyen<R: Rng>(n: usize, rng: &mut R) -> RegressionBenchmark {
+        fn polynomial(x: f64, degree: i32) -> f64 {
+            (1..degree + 1).map(|power| x.powi(power)).sum()
+        }
+        let target: fn(&[f64]) -> f64 = match n {
+            1 => |x| polynomial(x[0], 3),
+            2 => |x| polynomial(x[0], 4),
+            3 => |x| polynomial(x[0], 5),
+            4 => |x| polynomial(x[0], 6),
+            5 => |x| x[0].powi(2).sin() * x[0].cos() - 1.0,
+            6 => |x| x[0].sin() + (x[0] + x[0].powi(2)).sin(),
+            7 => |x| (x[0] + 1.0).ln() + (x[0].powi(2) + 1.0).ln(),
+            8 => |x| x[0].sqrt(),
+            9 => |x| x[0].sin() + x[1].powi(2).sin(),
+            10 => |x| 2.0 * x[0].sin() * x[1].cos(),
+            11 => |x| x[0].powf(x[1]),
+            12 => |x| x[0].powi(4) - x[0].powi(3) + x[1].powi(2) / 2.0 - x[1],
+            _ => panic!("Nguyen benchmarks are numbered 1 to 12"),
+        };
+        let (ranges, count) = match n {
+            7 => (vec![(0.0, 2.0)], 20),
+            8 => (vec![(0.0, 4.0)], 20),
+            9 | 10 | 12 => (vec![(-1.0, 1.0); 2], 100),
+            11 => (vec![(0.0, 1.0); 2], 100),
+            _ => (vec![(-1.0, 1.0)], 20),
+        };
+        Self::new(&format!("Nguyen-{}", n),
+                  koza_primitives(ranges.len()),
+                  Dataset::from_function(uniform(rng, &ranges, count), target),
+                  Dataset::from_function(uniform(rng, &ranges, count), target))
+    }
+
+    /// Keijzer-`n`, for `n` from 1 to 15.
+    ///
+    /// Keijzer-1 to 3 are `0.3x sin(2πx)` on E[-1, 1, 0.1], E[-2, 2, 0.1] and E[-3, 3, 0.1].
+    /// Keijzer-4 is `x^3 e^-x cos(x) sin(x) (sin(x)^2 cos(x) - 1)` on E[0, 10, 0.05].
+    /// Keijzer-5 is `30xz / ((x - 10)y^2)` with 1000 samples. Keijzer-6 to 9 are the
+    /// harmonic sum to `x`, `ln(x)`, `sqrt(x)` and `arcsinh(x)`. Keijzer-10 is `x^y` and
+    /// Keijzer-11 to 15 are `xy + sin((x - 1)(y - 1))`, `x^4 - x^3 + y^2/2 - y`,
+    /// `6sin(x)cos(y)`, `8 / (2 + x^2 + y^2)` and `x^3/5 + y^3/2 - y - x`, on U[-3, 3, 20]
+    /// and tested on E[-3, 3, 0.01].
+    ///
+    /// Keijzer's primitives `inv` and `neg` are stood in for by `div` and `sub`.
+    pub fn keijzer<R: Rng>(n: usize, rng: &mut R) -> RegressionBenchmark {
+        let target: fn(&[f64]) -> f64 = match n {
+            1..=3 => |x| 0.3 * x[0] * (2.0 * PI * x[0]).sin(),
+            4 => {
+                |x| {
+                    let (sin, cos) = x[0].sin_cos();
+                    x[0].powi(3) * (-x[0]).exp() * cos * sin * (sin.powi(2) * cos - 1.0)
+                }
+            }
+            5 => |x| 30.0 * x[0] * x[2] / ((x[0] - 10.0) * x[1].powi(2)),
+            6 => |x| (1..x[0] as usize + 1).map(|i| 1.0 / i as f64).sum(),
+            7 => |x| x[0].ln(),
+            8 => |x| x[0].sqrt(),
+            9 => |x| x[0].asinh(),
+            10 => |x| x[0].powf(x[1]),
+            11 => |x| x[0] * x[1] + ((x[0] - 1.0) * (x[1] - 1.0)).sin(),
+            12 => |x| x[0].powi(4) - x[0].powi(3) + x[1].powi(2) / 2.0 - x[1],
+            13 => |x| 6.0 * x[0].sin() * x[1].cos(),
+            14 => |x| 8.0 / (2.0 + x[0].powi(2) + x[1].powi(2)),
+            15 => |x| x[0].powi(3) / 5.0 + x[1].powi(3) / 2.0 - x[1] - x[0],
+            _ => panic!("Keijzer benchmarks are numbered 1 to 15"),
+        };
+        let (train, test) = match n {
+            1..=3 => {
+                let bound = n as f64;
+                (mesh(&[(-bound, bound, 0.1)]), mesh(&[(-bound, bound, 0.001)]))
+            }
+            4 => (mesh(&[(0.0, 10.0, 0.05)]), mesh(&[(0.05, 10.05, 0.05)])),
+            5 => {
+                let ranges = [(-1.0, 1.0), (1.0, 2.0), (-1.0, 1.0)];
+                (uniform(rng, &ranges, 1000), uniform(rng, &ranges, 10000))
+            }
+            6 => (mesh(&[(1.0, 50.0, 1.0)]), mesh(&[(1.0, 120.0, 1.0)])),
+            7 => (mesh(&[(1.0, 100.0, 1.0)]), mesh(&[(1.0, 100.0, 0.1)])),
+            8 | 9 => (mesh(&[(0.0, 100.0, 1.0)]), mesh(&[(0.0, 100.0, 0.1)])),
+            10 => (uniform(rng, &[(0.0, 1.0); 2], 100), mesh(&[(0.0, 1.0, 0.01); 2])),
+            _ => (uniform(rng, &[(-3.0, 3.0); 2], 20), mesh(&[(-3.0, 3.0, 0.01); 2])),
+        };
+        let variables = train[0].len();
+        let primitives = PrimitiveSet::arithmetic(variables).function(Function::Sqrt, 1);
+        Self::new(&format!("Keijzer-{}", n),
+                  primitives,
+                  Dataset::from_function(train, target),
+                  Dataset::from_function(test, target))
+    }
+
+    /// Pagie-1, `1 / (1 + x^-4) + 1 / (1 + y^-4)` on E[-5, 5, 0.4], also tested on that grid.
+    pub fn pagie_1() -> RegressionBenchmark {
+        // Written to avoid dividing by zero when x or y is 0.
+        let target = |x: &[f64]| {
+            x[0].powi(4) / (x[0].powi(4) + 1.0) + x[1].powi(4) / (x[1].powi(4) + 1.0)
+        };
+        let dataset = Dataset::from_function(mesh(&[(-5.0, 5.0, 0.4); 2]), target);
+        Self::new("Pagie-1", koza_primitives(2), dataset.clone(), dataset)
+    }
+
+    fn new(name: &str,
+           primitives: PrimitiveSet,
+           train: Dataset,
+           test: Dataset)
+           -> RegressionBenchmark {
+        RegressionBenchmark {
+            name: name.to_string(),
+            primitives,
+            train,
+            test,
+        }
+    }
+
+    /// Get the benchmark's name, such as `Nguyen-7`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the primitives the benchmark is usually solved with.
+    pub fn primitives(&self) -> &PrimitiveSet {
+        &self.primitives
+    }
+
+    /// Get the training samples.
+    pub fn train(&self) -> &Dataset {
+        &self.train
+    }
+
+    /// Get the test samples.
+    pub fn test(&self) -> &Dataset {
+        &self.test
+    }
+
+    /// Mean squared error of an equation on the training samples, to be minimised.
+    /// Non-finite errors count as infinitely bad.
+    pub fn fitness(&self, tree: &BoxTree<Equation>) -> f64 {
+        error(&self.train, tree)
+    }
+
+    /// Mean squared error of an equation on the test samples.
+    pub fn test_fitness(&self, tree: &BoxTree<Equation>) -> f64 {
+        error(&self.test, tree)
+    }
+}
+
+fn error(dataset: &Dataset, tree: &BoxTree<Equation>) -> f64 {
+    let error = mse(&dataset.outputs(tree), dataset.targets());
+    if error.is_finite() { error } else { f64::INFINITY }
+}
+
+/// Koza's primitives: add, sub, mul, div, sin, cos, exp and log, without constants.
+fn koza_primitives(variables: usize) -> PrimitiveSet {
+    PrimitiveSet::all(variables).function(Function::Sqrt, 0).constant(0)
+}
+
+/// `count` samples with each variable uniform in its `[low, high)` range.
+fn uniform<R: Rng>(rng: &mut R, ranges: &[(f64, f64)], count: usize) -> Vec<Vec<f64>> {
+    (0..count)
+        .map(|_| ranges.iter().map(|&(low, high)| rng.gen_range(low, high)).collect())
+        .collect()
+}
+
+/// Every combination of each variable stepping from `start` to `stop` inclusive.
+fn mesh(ranges: &[(f64, f64, f64)]) -> Vec<Vec<f64>> {
+    ranges.iter().fold(vec![vec![]], |samples, &(start, stop, step)| {
+        let steps = ((stop - start) / step).round() as usize;
+        samples.iter()
+            .flat_map(|sample| {
+                (0..steps + 1).map(move |i| {
+                    let mut sample = sample.clone();
+                    sample.push(start + i as f64 * step);
+                    sample
+                })
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn x(index: usize) -> BoxTree<Equation> {
+        Equation::Variable(index).into()
+    }
+
+    fn apply(function: Function, args: Vec<BoxTree<Equation>>) -> BoxTree<Equation> {
+        Equation::Apply(function, args).into()
+    }
+
+    /// `x^1 + ... + x^degree`, built from multiplications.
+    fn polynomial(degree: usize) -> BoxTree<Equation> {
+        let power = |n| (1..n).fold(x(0), |power, _| apply(Function::Mul, vec![power, x(0)]));
+        (2..degree + 1).fold(x(0), |sum, n| apply(Function::Add, vec![sum, power(n)]))
+    }
+
+    fn assert_targets<F>(dataset: &Dataset, target: F)
+        where F: Fn(&[f64]) -> f64
+    {
+        for (inputs, &actual) in dataset.inputs().iter().zip(dataset.targets()) {
+            let expected = target(inputs);
+            assert!((actual - expected).abs() < 1e-12, "{:?}: {} != {}", inputs, actual, expected);
+        }
+    }
+
+    #[test]
+    fn koza_targets() {
+        let mut rng = StdRng::from_seed(&[50usize][..]);
+        let koza_1 = RegressionBenchmark::koza_1(&mut rng);
+        assert_eq!(koza_1.name(), "Koza-1");
+        assert_eq!((koza_1.train().len(), koza_1.test().len()), (20, 20));
+        assert!(koza_1.train().inputs().iter().all(|x| x.len() == 1 && x[0].abs() <= 1.0));
+        assert_targets(koza_1.train(), |x| x[0] * (1.0 + x[0] * (1.0 + x[0] * (1.0 + x[0]))));
+        assert!(koza_1.fitness(&polynomial(4)) < 1e-20);
+        assert!(koza_1.test_fitness(&polynomial(4)) < 1e-20);
+
+        let koza_2 = RegressionBenchmark::koza_2(&mut rng);
+        assert_targets(koza_2.train(), |x| x[0] * (x[0] * x[0] - 1.0).powi(2));
+        let koza_3 = RegressionBenchmark::koza_3(&mut rng);
+        assert_targets(koza_3.train(), |x| (x[0] * (x[0] * x[0] - 1.0)).powi(2));
+        assert_eq!(koza_3.primitives().leaf_weights(), vec![1, 0]);
+    }
+
+    #[test]
+    fn nguyen_targets() {
+        let mut rng = StdRng::from_seed(&[50usize][..]);
+        for n in 1..5 {
+            let nguyen = RegressionBenchmark::nguyen(n, &mut rng);
+            assert_eq!(nguyen.name(), format!("Nguyen-{}", n));
+            assert!(nguyen.fitness(&polynomial(n + 2)) < 1e-20);
+        }
+
+        let nguyen_5 = RegressionBenchmark::nguyen(5, &mut rng);
+        let x2 = || apply(Function::Mul, vec![x(0), x(0)]);
+        let sin_x2_cos_x = apply(Function::Mul,
+                                 vec![apply(Function::Sin, vec![x2()]),
+                                      apply(Function::Cos, vec![x(0)])]);
+        let one = apply(Function::Div, vec![x(0), x(0)]);
+        assert!(nguyen_5.fitness(&apply(Function::Sub, vec![sin_x2_cos_x, one])) < 1e-20);
+
+        let nguyen_7 = RegressionBenchmark::nguyen(7, &mut rng);
+        assert!(nguyen_7.train().inputs().iter().all(|x| (0.0..2.0).contains(&x[0])));
+        assert_targets(nguyen_7.train(), |x| ((x[0] + 1.0) * (x[0] * x[0] + 1.0)).ln());
+
+        let nguyen_8 = RegressionBenchmark::nguyen(8, &mut rng);
+        assert!(nguyen_8.fitness(&apply(Function::Sqrt, vec![x(0)])) < 1e-20);
+
+        let nguyen_11 = RegressionBenchmark::nguyen(11, &mut rng);
+        assert_eq!((nguyen_11.train().len(), nguyen_11.train().variables()), (100, 2));
+        assert_targets(nguyen_11.train(), |x| (x[1] * x[0].ln()).exp());
+
+        let nguyen_12 = RegressionBenchmark::nguyen(12, &mut rng);
+        assert_eq!(nguyen_12.primitives().variables(), 2);
+        assert_targets(nguyen_12.train(),
+                       |x| x[0].powi(3) * (x[0] - 1.0) + x[1] * (x[1] - 2.0) / 2.0);
+    }
+
+    #[test]
+    fn grid_targets() {
+        let mut rng = StdRng::from_seed(&[50usize][..]);
+        let keijzer_1 = RegressionBenchmark::keijzer(1, &mut rng);
+        assert_eq!((keijzer_1.train().len(), keijzer_1.test().len()), (21, 2001));
+        let half = keijzer_1.train().inputs().iter().position(|x| (x[0] - 0.5).abs() < 1e-9);
+        assert!(keijzer_1.train().targets()[half.unwrap()].abs() < 1e-12);
+        // The test grid steps by 0.001 from -1, so sample 1250 is at 0.25.
+        assert!((keijzer_1.test().targets()[1250] - 0.075).abs() < 1e-12);
+
+        let keijzer_6 = RegressionBenchmark::keijzer(6, &mut rng);
+        assert_eq!(keijzer_6.train().targets()[..3], [1.0, 1.5, 1.5 + 1.0 / 3.0]);
+
+        let pagie_1 = RegressionBenchmark::pagie_1();
+        assert_eq!(pagie_1.train().len(), 26 * 26);
+        // Pagie-1 has no test set of its own, so is tested on its training grid.
+        assert_eq!(pagie_1.train(), pagie_1.test());
+        let at = |x: f64, y: f64| {
+            let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+            let inputs = pagie_1.train().inputs();
+            let index = inputs.iter().position(|s| close(s[0], x) && close(s[1], y));
+            pagie_1.train().targets()[index.unwrap()]
+        };
+        assert_eq!(at(-5.0, -5.0), 2.0 * 625.0 / 626.0);
+        assert!((at(-0.2, 1.0) - (0.0016 / 1.0016 + 0.5)).abs() < 1e-12);
+    }
+}
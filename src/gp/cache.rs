@@ -0,0 +1,406 @@
+use gp::tree::*;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Caches the results of evaluating whole trees.
+///
+/// Populations contain many identical trees, e.g. individuals copied unchanged into the
+/// next generation. Individuals identical to one already evaluated skip evaluation.
+#[derive(Clone, Debug)]
+pub struct EvaluationCache<T, V>
+    where T: Tree + Hash + Eq
+{
+    results: HashMap<BoxTree<T>, V>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<T, V> EvaluationCache<T, V>
+    where T: Tree + Hash + Eq
+{
+    /// Create an empty cache.
+    pub fn new() -> EvaluationCache<T, V> {
+        EvaluationCache {
+            results: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Get the cached result for a tree, if any.
+    pub fn get(&self, tree: &BoxTree<T>) -> Option<&V> {
+        self.results.get(tree)
+    }
+
+    /// Get the cached result for a tree, or evaluate it with `f` and cache the result.
+    pub fn get_or_insert_with<F>(&mut self, tree: &BoxTree<T>, f: F) -> V
+        where F: FnOnce(&BoxTree<T>) -> V,
+              V: Clone
+    {
+        if let Some(result) = self.results.get(tree) {
+            self.hits += 1;
+            return result.clone();
+        }
+        self.misses += 1;
+        let result = f(tree);
+        self.results.insert(tree.clone(), result.clone());
+        result
+    }
+
+    /// Get the number of cached results.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Whether no results are cached.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Get the number of lookups answered from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Get the number of lookups which needed evaluation.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Forget all cached results, e.g. because the fitness function has changed.
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+}
+
+impl<T, V> Default for EvaluationCache<T, V>
+    where T: Tree + Hash + Eq
+{
+    fn default() -> EvaluationCache<T, V> {
+        EvaluationCache::new()
+    }
+}
+
+/// Subtrees whose instructions share a hash, each with its cached output column.
+type Bucket<O, A> = Vec<(Vec<Instruction<O>>, Vec<A>)>;
+
+/// Memoises batch evaluation of subtrees over one dataset.
+///
+/// Every subtree's output column is cached under the structural hash of its opcodes, so
+/// subtrees shared between individuals are only evaluated once. Each column is stored with
+/// the subtree's instructions, which are compared on a hit so that colliding hashes are
+/// never confused. A cache is only valid for the batch of environments it was filled with;
+/// call `SubtreeCache::clear` before using it with another.
+///
+/// Columns are cached without limit by default. Use `SubtreeCache::with_limit` to bound
+/// memory use, or clear the cache regularly, e.g. once per generation.
+#[derive(Clone, Debug)]
+pub struct SubtreeCache<T>
+    where T: Compile,
+          T::Op: Hash + PartialEq,
+          T::Action: Clone
+{
+    columns: HashMap<u64, Bucket<T::Op, T::Action>>,
+    len: usize,
+    limit: Option<usize>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<T> SubtreeCache<T>
+    where T: Compile,
+          T::Op: Hash + PartialEq,
+          T::Action: Clone
+{
+    /// Create an empty cache.
+    pub fn new() -> SubtreeCache<T> {
+        SubtreeCache {
+            columns: HashMap::new(),
+            len: 0,
+            limit: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Create an empty cache holding at most `limit` columns. Once full, further subtrees
+    /// are evaluated without being cached until `SubtreeCache::clear` is called.
+    pub fn with_limit(limit: usize) -> SubtreeCache<T> {
+        SubtreeCache { limit: Some(limit), ..SubtreeCache::new() }
+    }
+
+    /// Evaluate a tree over a batch of environments, reusing cached subtree results.
+    /// Returns one value per environment. See `Program::evaluate_batch`.
+    ///
+    /// Panics if the tree has a `Compile::lazy` node.
+    pub fn evaluate_batch(&mut self,
+                          tree: &BoxTree<T>,
+                          envs: &[T::Environment])
+                          -> Vec<T::Action> {
+        let mut nodes = vec![];
+        let mut hashes = vec![];
+        hash_subtrees(tree, &mut nodes, &mut hashes);
+        let mut index = 0;
+        self.evaluate_node(tree, &nodes, &hashes, &mut index, envs)
+    }
+
+    /// Evaluate the node at pre-order `index`, advancing `index` past its subtree.
+    fn evaluate_node(&mut self,
+                     node: &BoxTree<T>,
+                     nodes: &[Instruction<T::Op>],
+                     hashes: &[(u64, usize)],
+                     index: &mut usize,
+                     envs: &[T::Environment])
+                     -> Vec<T::Action> {
+        let (hash, size) = hashes[*index];
+        let subtree = &nodes[*index..*index + size];
+        let cached = self.columns
+            .get(&hash)
+            .and_then(|bucket| bucket.iter().find(|cached| cached.0[..] == *subtree));
+        if let Some(cached) = cached {
+            self.hits += 1;
+            *index += size;
+            return cached.1.clone();
+        }
+        self.misses += 1;
+
+        *index += 1;
+        let args: Vec<Vec<T::Action>> = node.children()
+            .into_iter()
+            .map(|child| self.evaluate_node(child, nodes, hashes, index, envs))
+            .collect();
+        let mut column = Vec::with_capacity(envs.len());
+        T::execute_batch(&node.op(), envs, &args, &mut column);
+        let full = match self.limit {
+            Some(limit) => self.len >= limit,
+            None => false,
+        };
+        if !full {
+            let bucket = self.columns.entry(hash).or_default();
+            bucket.push((subtree.to_vec(), column.clone()));
+            self.len += 1;
+        }
+        column
+    }
+
+    /// Get the number of cached subtree results.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no subtree results are cached.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of subtrees answered from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Get the number of subtrees which needed evaluation.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Forget all cached results, e.g. before evaluating over a different dataset.
+    pub fn clear(&mut self) {
+        self.columns.clear();
+        self.len = 0;
+    }
+}
+
+impl<T> Default for SubtreeCache<T>
+    where T: Compile,
+          T::Op: Hash + PartialEq,
+          T::Action: Clone
+{
+    fn default() -> SubtreeCache<T> {
+        SubtreeCache::new()
+    }
+}
+
+/// Push the instruction of every node onto `nodes` and the structural hash and size of
+/// every subtree onto `hashes`, both in pre-order. Returns the hash of `node`.
+fn hash_subtrees<T>(node: &BoxTree<T>,
+                    nodes: &mut Vec<Instruction<T::Op>>,
+                    hashes: &mut Vec<(u64, usize)>)
+                    -> u64
+    where T: Compile,
+          T::Op: Hash
+{
+    assert!(!node.lazy(), "cannot cache a lazy node, as batches are strict");
+    let index = hashes.len();
+    let children = node.children();
+    let op = node.op();
+    let mut hasher = DefaultHasher::new();
+    op.hash(&mut hasher);
+    nodes.push(Instruction {
+        op,
+        arity: children.len(),
+    });
+    hashes.push((0, 0));
+    for child in children {
+        hash_subtrees(child, nodes, hashes).hash(&mut hasher);
+    }
+    let hash = hasher.finish();
+    hashes[index] = (hash, hashes.len() - index);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    /// Wrapping integer sums and products of an input and twos.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    enum Expr {
+        X,
+        Two,
+        Plus(BoxTree<Expr>, BoxTree<Expr>),
+        Times(BoxTree<Expr>, BoxTree<Expr>),
+    }
+
+    #[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+    enum ExprOp {
+        X,
+        Two,
+        Plus,
+        Times,
+    }
+
+    impl Tree for Expr {
+        type Environment = i64;
+        type Action = i64;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Expr> {
+            let (a, b) = (Self::child(tg, current_depth + 1), Self::child(tg, current_depth + 1));
+            if tg.gen() { Expr::Plus(a, b) } else { Expr::Times(a, b) }.into()
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Expr> {
+            if tg.gen() { Expr::X } else { Expr::Two }.into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Expr>> {
+            match *self {
+                Expr::Plus(ref a, ref b) | Expr::Times(ref a, ref b) => vec![a, b],
+                Expr::X | Expr::Two => vec![],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Expr>> {
+            match *self {
+                Expr::Plus(ref mut a, ref mut b) | Expr::Times(ref mut a, ref mut b) => vec![a, b],
+                Expr::X | Expr::Two => vec![],
+            }
+        }
+
+        fn evaluate(&self, env: &i64) -> i64 {
+            let args: Vec<i64> = self.children().iter().map(|child| child.evaluate(env)).collect();
+            Expr::execute(&self.op(), env, &args)
+        }
+    }
+
+    impl Compile for Expr {
+        type Op = ExprOp;
+
+        fn op(&self) -> ExprOp {
+            match *self {
+                Expr::X => ExprOp::X,
+                Expr::Two => ExprOp::Two,
+                Expr::Plus(_, _) => ExprOp::Plus,
+                Expr::Times(_, _) => ExprOp::Times,
+            }
+        }
+
+        fn execute(op: &ExprOp, env: &i64, args: &[i64]) -> i64 {
+            match *op {
+                ExprOp::X => *env,
+                ExprOp::Two => 2,
+                ExprOp::Plus => args[0].wrapping_add(args[1]),
+                ExprOp::Times => args[0].wrapping_mul(args[1]),
+            }
+        }
+    }
+
+    fn envs() -> Vec<i64> {
+        (-4..6).collect()
+    }
+
+    fn x() -> BoxTree<Expr> {
+        Expr::X.into()
+    }
+
+    #[test]
+    fn evaluation_cache_counts_hits_and_misses() {
+        let mut cache = EvaluationCache::new();
+        let two: BoxTree<Expr> = Expr::Two.into();
+        assert_eq!(cache.get_or_insert_with(&x(), |_| 1), 1);
+        assert_eq!(cache.get_or_insert_with(&x(), |_| 2), 1);
+        assert_eq!(cache.get_or_insert_with(&two, |_| 3), 3);
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 2, 2));
+        assert_eq!(cache.get(&two), Some(&3));
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&two), None);
+    }
+
+    #[test]
+    fn subtree_cache_matches_batch_evaluation() {
+        let mut rng = StdRng::from_seed(&[29usize][..]);
+        let envs = envs();
+        let mut cache = SubtreeCache::new();
+        for _ in 0..100 {
+            let tree = Expr::tree(&mut TreeGen::full(&mut rng, 1, 5));
+            let expected: Vec<i64> = envs.iter().map(|env| tree.evaluate(env)).collect();
+            assert_eq!(cache.evaluate_batch(&tree, &envs), expected);
+        }
+        assert!(cache.hits() > 0);
+    }
+
+    #[test]
+    fn subtree_cache_reuses_shared_subtrees() {
+        let envs = envs();
+        let square: BoxTree<Expr> = Expr::Times(x(), x()).into();
+        let tree = Expr::Plus(square.clone(), square).into();
+        let mut cache = SubtreeCache::new();
+        cache.evaluate_batch(&tree, &envs);
+        // The second `x` and the second `x * x` are answered from the cache.
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (2, 3, 3));
+        let outputs = cache.evaluate_batch(&tree, &envs);
+        assert_eq!(cache.hits(), 3);
+        assert_eq!(outputs[0], 2 * 4 * 4);
+    }
+
+    #[test]
+    fn subtree_cache_tells_apart_different_subtrees() {
+        let envs = envs();
+        let mut cache = SubtreeCache::new();
+        let sum: BoxTree<Expr> = Expr::Plus(x(), Expr::Two.into()).into();
+        let product: BoxTree<Expr> = Expr::Times(x(), Expr::Two.into()).into();
+        assert_eq!(cache.evaluate_batch(&sum, &envs)[0], -2);
+        assert_eq!(cache.evaluate_batch(&product, &envs)[0], -8);
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn subtree_cache_limit_and_clear() {
+        let envs = envs();
+        let tree: BoxTree<Expr> = Expr::Times(x(), Expr::Two.into()).into();
+        let mut cache = SubtreeCache::with_limit(1);
+        cache.evaluate_batch(&tree, &envs);
+        assert_eq!(cache.len(), 1);
+        cache.evaluate_batch(&tree, &envs);
+        assert_eq!(cache.len(), 1);
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.evaluate_batch(&tree, &envs), tree.evaluate_batch(&envs));
+    }
+}
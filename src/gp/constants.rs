@@ -0,0 +1,579 @@
+use gp::*;
+use rand::Rng;
+use rand::distributions::{IndependentSample, Normal};
+use std::hash::Hash;
+
+/// Trait for `Tree`s with numeric constants which `ConstantOptimisation` can tune.
+pub trait Constants: Tree {
+    /// Get this node's constant, or `None` if it has none.
+    fn constant(&self) -> Option<f64>;
+
+    /// Set this node's constant. Only called on nodes whose `constant` is not `None`.
+    fn set_constant(&mut self, value: f64);
+}
+
+impl<T> BoxTree<T>
+    where T: Constants
+{
+    /// Get the tree's constants, in pre-order.
+    pub fn constants(&self) -> Vec<f64> {
+        self.pre_order().filter_map(|node| node.tree.constant()).collect()
+    }
+
+    /// Set the tree's constants, in pre-order, as returned by `BoxTree::constants`.
+    ///
+    /// Panics unless there is one value per constant.
+    pub fn set_constants(&mut self, values: &[f64]) {
+        let mut values = values.iter();
+        let mut nodes = self.pre_order_mut();
+        while let Some(node) = nodes.next_node() {
+            if node.tree.constant().is_some() {
+                node.tree.set_constant(*values.next().expect("one value per constant"));
+            }
+        }
+        assert!(values.next().is_none(), "one value per constant");
+    }
+}
+
+/// Storage for a tree whose constants `ConstantOptimisation` can tune. Implemented for
+/// `BoxTree` and `FlatTree` of `Constants` trees.
+pub trait TreeConstants: Representation {
+    /// Get the tree's constants, in pre-order.
+    fn constants(&self) -> Vec<f64>;
+
+    /// Set the tree's constants, in pre-order. Panics unless there is one value per
+    /// constant.
+    fn set_constants(&mut self, values: &[f64]);
+}
+
+impl<T> TreeConstants for BoxTree<T>
+    where T: Constants + Hash
+{
+    fn constants(&self) -> Vec<f64> {
+        BoxTree::constants(self)
+    }
+
+    fn set_constants(&mut self, values: &[f64]) {
+        BoxTree::set_constants(self, values)
+    }
+}
+
+/// Constants are read and written by rebuilding the tree as a `BoxTree`.
+impl<T> TreeConstants for FlatTree<T>
+    where T: Constants + Flatten,
+          T::Op: Hash
+{
+    fn constants(&self) -> Vec<f64> {
+        self.to_tree().constants()
+    }
+
+    fn set_constants(&mut self, values: &[f64]) {
+        let mut tree = self.to_tree();
+        tree.set_constants(values);
+        *self = FlatTree::from_tree(&tree);
+    }
+}
+
+/// The constant optimisation mode in use. See `ConstantOptimisation`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum ConstantOptimisationMode {
+    /// Corresponds to `ConstantOptimisation::nelder_mead`.
+    NelderMead,
+    /// Corresponds to `ConstantOptimisation::one_plus_one`.
+    OnePlusOne,
+}
+
+/// Configures optimisation of the constants in a tree, keeping its structure.
+///
+/// Evolution finds the shape of a tree but rarely exact constants. Optimising the constants
+/// of each individual and keeping the result (a Lamarckian step) lets evolution concentrate
+/// on structure. Costs are minimised; NaN costs count as infinitely bad.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ConstantOptimisation {
+    mode: ConstantOptimisationMode,
+    max_evaluations: usize,
+    initial_step: f64,
+}
+
+impl ConstantOptimisation {
+    /// Optimise with the Nelder-Mead simplex method, using at most `max_evaluations`
+    /// evaluations of the cost.
+    pub fn nelder_mead(max_evaluations: usize) -> ConstantOptimisation {
+        ConstantOptimisation {
+            mode: ConstantOptimisationMode::NelderMead,
+            max_evaluations,
+            initial_step: 1.0,
+        }
+    }
+
+    /// Optimise with a (1+1) evolution strategy using the 1/5 success rule, using at most
+    /// `max_evaluations` evaluations of the cost.
+    pub fn one_plus_one(max_evaluations: usize) -> ConstantOptimisation {
+        ConstantOptimisation {
+            mode: ConstantOptimisationMode::OnePlusOne,
+            max_evaluations,
+            initial_step: 1.0,
+        }
+    }
+
+    /// Set how far from their current values constants are first tried. Defaults to 1.
+    pub fn initial_step(mut self, initial_step: f64) -> ConstantOptimisation {
+        self.initial_step = initial_step;
+        self
+    }
+
+    /// Optimise the constants of a tree against `cost`, writing the best values found back
+    /// into the tree. Returns the tree's cost afterwards.
+    ///
+    /// The tree is only changed if its cost improves.
+    pub fn optimise<G, F, R>(&self, tree: &mut G, mut cost: F, mut rng: R) -> f64
+        where G: TreeConstants,
+              F: FnMut(&G) -> f64,
+              R: Rng
+    {
+        let initial = tree.constants();
+        let initial_cost = nan_worst(cost(tree));
+        if initial.is_empty() {
+            return initial_cost;
+        }
+        let (best, best_cost) = {
+            let mut working = tree.clone();
+            let mut objective = |values: &[f64]| {
+                working.set_constants(values);
+                nan_worst(cost(&working))
+            };
+            match self.mode {
+                ConstantOptimisationMode::NelderMead => {
+                    nelder_mead(&mut objective,
+                                initial,
+                                initial_cost,
+                                self.initial_step,
+                                self.max_evaluations)
+                }
+                ConstantOptimisationMode::OnePlusOne => {
+                    one_plus_one(&mut objective,
+                                 initial,
+                                 initial_cost,
+                                 self.initial_step,
+                                 self.max_evaluations,
+                                 &mut rng)
+                }
+            }
+        };
+        if best_cost < initial_cost {
+            tree.set_constants(&best);
+            best_cost
+        } else {
+            initial_cost
+        }
+    }
+
+    /// Optimise the constants of an individual's tree, as `ConstantOptimisation::optimise`,
+    /// and update its cached metadata.
+    pub fn optimise_individual<T, G, F, R>(&self,
+                                           indv: &mut Individual<T, G>,
+                                           cost: F,
+                                           rng: R)
+                                           -> f64
+        where T: Constants,
+              G: TreeConstants + Representation<Tree = T>,
+              F: FnMut(&G) -> f64,
+              R: Rng
+    {
+        let cost = self.optimise(&mut indv.tree, cost, rng);
+        indv.recalculate_metadata();
+        cost
+    }
+}
+
+fn nan_worst(cost: f64) -> f64 {
+    if cost.is_nan() { f64::INFINITY } else { cost }
+}
+
+/// Minimise `objective` from `start` with the Nelder-Mead simplex method. Returns the best
+/// point and its cost.
+fn nelder_mead<F>(objective: &mut F,
+                  start: Vec<f64>,
+                  start_cost: f64,
+                  step: f64,
+                  max_evaluations: usize)
+                  -> (Vec<f64>, f64)
+    where F: FnMut(&[f64]) -> f64
+{
+    let mut budget = Budget {
+        objective,
+        remaining: max_evaluations,
+    };
+    let mut simplex = vec![(start.clone(), start_cost)];
+    for i in 0..start.len() {
+        let mut vertex = start.clone();
+        vertex[i] += step;
+        match budget.evaluate(&vertex) {
+            Some(cost) => simplex.push((vertex, cost)),
+            None => break,
+        }
+    }
+    if simplex.len() == start.len() + 1 {
+        while nelder_mead_step(&mut simplex, &mut budget).is_some() {}
+    }
+    simplex.into_iter()
+        .min_by(|a, b| a.1.partial_cmp(&b.1).expect("costs are never NaN"))
+        .expect("a simplex always has vertices")
+}
+
+/// An objective which can be evaluated a limited number of times.
+struct Budget<'a, F: 'a> {
+    objective: &'a mut F,
+    remaining: usize,
+}
+
+impl<'a, F> Budget<'a, F>
+    where F: FnMut(&[f64]) -> f64
+{
+    /// Evaluate a point, or return `None` if the budget is spent.
+    fn evaluate(&mut self, point: &[f64]) -> Option<f64> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some((self.objective)(point))
+    }
+}
+
+/// Replace the worst vertex of a simplex of `n + 1` vertices, or shrink the simplex. Returns
+/// `None` once the simplex has converged or the budget is spent.
+fn nelder_mead_step<F>(simplex: &mut [(Vec<f64>, f64)], budget: &mut Budget<F>) -> Option<()>
+    where F: FnMut(&[f64]) -> f64
+{
+    let n = simplex.len() - 1;
+    simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("costs are never NaN"));
+    if simplex[n].1 - simplex[0].1 <= 1e-12 * simplex[0].1.abs().max(1e-12) {
+        return None;
+    }
+    let centroid: Vec<f64> = (0..n)
+        .map(|j| simplex[..n].iter().map(|vertex| vertex.0[j]).sum::<f64>() / n as f64)
+        .collect();
+    let along = |t: f64, worst: &[f64]| -> Vec<f64> {
+        centroid.iter().zip(worst).map(|(c, w)| c + t * (c - w)).collect()
+    };
+
+    let reflected = along(1.0, &simplex[n].0);
+    let reflected_cost = budget.evaluate(&reflected)?;
+    if reflected_cost < simplex[0].1 {
+        let expanded = along(2.0, &simplex[n].0);
+        simplex[n] = match budget.evaluate(&expanded) {
+            Some(expanded_cost) if expanded_cost < reflected_cost => (expanded, expanded_cost),
+            _ => (reflected, reflected_cost),
+        };
+    } else if reflected_cost < simplex[n - 1].1 {
+        simplex[n] = (reflected, reflected_cost);
+    } else {
+        // Contract towards the better of the worst and reflected points.
+        let (t, bound) = if reflected_cost < simplex[n].1 {
+            (0.5, reflected_cost)
+        } else {
+            (-0.5, simplex[n].1)
+        };
+        let contracted = along(t, &simplex[n].0);
+        let contracted_cost = budget.evaluate(&contracted)?;
+        if contracted_cost < bound {
+            simplex[n] = (contracted, contracted_cost);
+        } else {
+            // Shrink every vertex towards the best.
+            let best = simplex[0].0.clone();
+            for vertex in simplex.iter_mut().skip(1) {
+                let shrunk: Vec<f64> =
+                    best.iter().zip(&vertex.0).map(|(b, v)| b + 0.5 * (v - b)).collect();
+                let cost = budget.evaluate(&shrunk)?;
+                *vertex = (shrunk, cost);
+            }
+        }
+    }
+    Some(())
+}
+
+/// Minimise `objective` from `start` with a (1+1) evolution strategy. Returns the best
+/// point and its cost.
+fn one_plus_one<F, R>(objective: &mut F,
+                      start: Vec<f64>,
+                      start_cost: f64,
+                      step: f64,
+                      max_evaluations: usize,
+                      rng: &mut R)
+                      -> (Vec<f64>, f64)
+    where F: FnMut(&[f64]) -> f64,
+          R: Rng
+{
+    let normal = Normal::new(0.0, 1.0);
+    let (mut best, mut best_cost) = (start, start_cost);
+    let mut sigma = step;
+    for _ in 0..max_evaluations {
+        let candidate: Vec<f64> =
+            best.iter().map(|value| value + sigma * normal.ind_sample(rng)).collect();
+        let cost = objective(&candidate);
+        // Growing on success and shrinking on failure balances at a 1/5 success rate.
+        if cost <= best_cost {
+            best = candidate;
+            best_cost = cost;
+            sigma *= (1.0f64 / 3.0).exp();
+        } else {
+            sigma *= (-1.0f64 / 12.0).exp();
+        }
+    }
+    (best, best_cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use std::hash::Hasher;
+
+    /// A signal from a single input, passed through gains and summed with the settings of
+    /// knobs. Gains and knobs are the constants.
+    #[derive(Clone, Debug, PartialEq)]
+    enum Dial {
+        Input,
+        Knob(f64),
+        Gain(f64, BoxTree<Dial>),
+        Sum(BoxTree<Dial>, BoxTree<Dial>),
+    }
+
+    /// Opcodes hold constants by their bits, so that they can be hashed.
+    #[derive(Clone, Copy, Debug, PartialEq, Hash)]
+    enum DialOp {
+        Input,
+        Knob(u64),
+        Gain(u64),
+        Sum,
+    }
+
+    impl Tree for Dial {
+        type Environment = f64;
+        type Action = f64;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Dial> {
+            let child = |tg: &mut TreeGen<R>| Self::child(tg, current_depth + 1);
+            match tg.gen_range(0, 2) {
+                0 => Dial::Gain(tg.gen_range(-1.0, 1.0), child(tg)),
+                _ => Dial::Sum(child(tg), child(tg)),
+            }
+            .into()
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Dial> {
+            match tg.gen_range(0, 2) {
+                0 => Dial::Input,
+                _ => Dial::Knob(tg.gen_range(-1.0, 1.0)),
+            }
+            .into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Dial>> {
+            match *self {
+                Dial::Input | Dial::Knob(_) => vec![],
+                Dial::Gain(_, ref a) => vec![a],
+                Dial::Sum(ref a, ref b) => vec![a, b],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Dial>> {
+            match *self {
+                Dial::Input | Dial::Knob(_) => vec![],
+                Dial::Gain(_, ref mut a) => vec![a],
+                Dial::Sum(ref mut a, ref mut b) => vec![a, b],
+            }
+        }
+
+        fn evaluate(&self, x: &f64) -> f64 {
+            match *self {
+                Dial::Input => *x,
+                Dial::Knob(setting) => setting,
+                Dial::Gain(gain, ref a) => gain * a.evaluate(x),
+                Dial::Sum(ref a, ref b) => a.evaluate(x) + b.evaluate(x),
+            }
+        }
+    }
+
+    impl Hash for Dial {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.op().hash(state);
+            for child in self.children() {
+                child.hash(state);
+            }
+        }
+    }
+
+    impl Compile for Dial {
+        type Op = DialOp;
+
+        fn op(&self) -> DialOp {
+            match *self {
+                Dial::Input => DialOp::Input,
+                Dial::Knob(setting) => DialOp::Knob(setting.to_bits()),
+                Dial::Gain(gain, _) => DialOp::Gain(gain.to_bits()),
+                Dial::Sum(_, _) => DialOp::Sum,
+            }
+        }
+
+        fn execute(op: &DialOp, x: &f64, args: &[f64]) -> f64 {
+            match *op {
+                DialOp::Input => *x,
+                DialOp::Knob(setting) => f64::from_bits(setting),
+                DialOp::Gain(gain) => f64::from_bits(gain) * args[0],
+                DialOp::Sum => args[0] + args[1],
+            }
+        }
+    }
+
+    impl Flatten for Dial {
+        fn unflatten(op: DialOp, children: Vec<BoxTree<Dial>>) -> BoxTree<Dial> {
+            let mut children = children.into_iter();
+            let mut child = || children.next().unwrap();
+            match op {
+                DialOp::Input => Dial::Input,
+                DialOp::Knob(setting) => Dial::Knob(f64::from_bits(setting)),
+                DialOp::Gain(gain) => Dial::Gain(f64::from_bits(gain), child()),
+                DialOp::Sum => Dial::Sum(child(), child()),
+            }
+            .into()
+        }
+    }
+
+    impl Constants for Dial {
+        fn constant(&self) -> Option<f64> {
+            match *self {
+                Dial::Knob(value) | Dial::Gain(value, _) => Some(value),
+                _ => None,
+            }
+        }
+
+        fn set_constant(&mut self, value: f64) {
+            match *self {
+                Dial::Knob(ref mut old) | Dial::Gain(ref mut old, _) => *old = value,
+                _ => unreachable!("only called on nodes with a constant"),
+            }
+        }
+    }
+
+    /// `gain(1, x) + knob(1)`, whose constants can fit any line.
+    fn line() -> BoxTree<Dial> {
+        let gain = Dial::Gain(1.0, Dial::Input.into());
+        Dial::Sum(gain.into(), Dial::Knob(1.0).into()).into()
+    }
+
+    /// Squared error of a tree from `3x - 2`.
+    fn error<G: Representation<Tree = Dial>>(tree: &G) -> f64 {
+        (-3..4)
+            .map(|x| {
+                let x = f64::from(x);
+                (tree.evaluate(&x) - (3.0 * x - 2.0)).powi(2)
+            })
+            .sum()
+    }
+
+    fn rosenbrock(x: &[f64]) -> f64 {
+        (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2)
+    }
+
+    #[test]
+    fn constants_round_trip() {
+        let mut tree = line();
+        assert_eq!(tree.constants(), vec![1.0, 1.0]);
+        tree.set_constants(&[3.0, -2.0]);
+        assert_eq!(tree.constants(), vec![3.0, -2.0]);
+        assert_eq!(error(&tree), 0.0);
+
+        let mut flat = FlatTree::from(&line());
+        assert_eq!(TreeConstants::constants(&flat), vec![1.0, 1.0]);
+        TreeConstants::set_constants(&mut flat, &[3.0, -2.0]);
+        assert_eq!(flat.to_tree(), tree);
+    }
+
+    #[test]
+    #[should_panic(expected = "one value per constant")]
+    fn set_constants_needs_every_value() {
+        line().set_constants(&[1.0]);
+    }
+
+    #[test]
+    fn nelder_mead_minimises_rosenbrock() {
+        let mut objective = rosenbrock;
+        let (best, cost) = nelder_mead(&mut objective, vec![-1.0, 2.0], 104.0, 0.5, 1000);
+        assert!(cost < 1e-8, "{}", cost);
+        assert!((best[0] - 1.0).abs() < 1e-3 && (best[1] - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn nelder_mead_keeps_to_the_budget() {
+        let cost_of = |x: &[f64]| x.iter().map(|xi| (xi - 0.3).powi(2)).sum::<f64>();
+        let start = vec![1.0; 5];
+        for max_evaluations in 0..30 {
+            let mut calls = 0;
+            let mut objective = |x: &[f64]| {
+                calls += 1;
+                cost_of(x)
+            };
+            let start_cost = cost_of(&start);
+            let (best, cost) =
+                nelder_mead(&mut objective, start.clone(), start_cost, 1.0, max_evaluations);
+            assert!(calls <= max_evaluations);
+            assert!(cost <= start_cost);
+            assert_eq!(cost, cost_of(&best));
+        }
+    }
+
+    #[test]
+    fn one_plus_one_improves() {
+        let mut rng = StdRng::from_seed(&[45usize][..]);
+        let mut calls = 0;
+        let mut objective = |x: &[f64]| {
+            calls += 1;
+            rosenbrock(x)
+        };
+        let (best, cost) =
+            one_plus_one(&mut objective, vec![-1.0, 2.0], 104.0, 0.5, 2000, &mut rng);
+        assert_eq!(calls, 2000);
+        assert!(cost < 1e-2, "{}", cost);
+        assert_eq!(cost, rosenbrock(&best));
+    }
+
+    #[test]
+    fn optimise_fits_constants() {
+        let mut rng = StdRng::from_seed(&[45usize][..]);
+        for optimisation in &[ConstantOptimisation::nelder_mead(200),
+                              ConstantOptimisation::one_plus_one(2000).initial_step(0.5)] {
+            let mut tree = line();
+            let cost = optimisation.optimise(&mut tree, error, &mut rng);
+            assert!(cost < 1e-4, "{:?} {}", optimisation, cost);
+            assert_eq!(cost, error(&tree));
+        }
+        // A tree without constants, or whose constants are already best, is unchanged.
+        let mut x: BoxTree<Dial> = Dial::Input.into();
+        assert_eq!(ConstantOptimisation::nelder_mead(10).optimise(&mut x, error, &mut rng),
+                   error(&x));
+        let mut fitted = line();
+        fitted.set_constants(&[3.0, -2.0]);
+        assert_eq!(ConstantOptimisation::one_plus_one(10).optimise(&mut fitted, error, &mut rng),
+                   0.0);
+        assert_eq!(fitted.constants(), vec![3.0, -2.0]);
+    }
+
+    #[test]
+    fn optimise_individuals_of_any_representation() {
+        let mut rng = StdRng::from_seed(&[45usize][..]);
+        let optimisation = ConstantOptimisation::nelder_mead(200);
+        let mut boxed: Individual<Dial> = Individual::new_from_tree(line());
+        let mut flat: Individual<Dial, FlatTree<Dial>> =
+            Individual::new_from_tree(FlatTree::from(&line()));
+        let boxed_cost = optimisation.optimise_individual(&mut boxed, error, &mut rng);
+        let flat_cost = optimisation.optimise_individual(&mut flat, error, &mut rng);
+        assert_eq!(flat_cost, boxed_cost);
+        assert_eq!(flat.tree.to_tree(), boxed.tree);
+        assert_eq!(flat.nodes_count(), 4);
+    }
+}
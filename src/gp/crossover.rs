@@ -1,5 +1,4 @@
 use gp::*;
-use std::mem;
 use rand::Rng;
 
 /// The crossover mode in use. See `Crossover`.
@@ -21,24 +20,32 @@ impl Crossover {
     /// Get an operator to perform one-point crossover between two individuals.
     ///
     /// The subtree at a random position in one individual will be swapped with a random
-    /// position in a second individual.
+    /// position in a second individual. Only positions accepted by `Tree::compatible` are
+    /// chosen in the second individual; if there are none the individuals are unchanged.
     pub fn one_point() -> Crossover {
         Crossover { mode: CrossoverMode::OnePoint }
     }
 
-    /// Get an operator to perform one-point crossover between two individuals.
+    /// Get an operator to perform one-point crossover between two individuals, biased
+    /// towards or away from swapping leaves.
     ///
-    /// The subtree at a random position in one individual will be swapped with a random
-    /// position in a second individual. Each swap points will be a terminal with `termpb`
-    /// probability.
-    #[doc(hidden)]
+    /// As for `Crossover::one_point`, but each swap point is a leaf with `termpb`
+    /// probability and otherwise a branch, unless the tree has only one kind. Koza's 90/10
+    /// rule is a `termpb` of 0.1.
+    ///
+    /// Panics unless `termpb` is between 0 and 1.
     pub fn one_point_leaf_biased(termpb: f32) -> Crossover {
+        assert!((0.0..=1.0).contains(&termpb), "need 0 <= termpb <= 1");
         Crossover { mode: CrossoverMode::OnePointLeafBiased(termpb) }
     }
 
     /// Crossover (mate) two individuals according to the configured crossover mode.
-    pub fn mate<T, R>(&self, indv1: &mut Individual<T>, indv2: &mut Individual<T>, rng: R)
+    pub fn mate<T, G, R>(&self,
+                         indv1: &mut Individual<T, G>,
+                         indv2: &mut Individual<T, G>,
+                         rng: R)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         match self.mode {
@@ -49,40 +56,187 @@ impl Crossover {
         }
     }
 
-    fn mate_one_point<T: Tree, R: Rng>(&self,
-                                       indv1: &mut Individual<T>,
-                                       indv2: &mut Individual<T>,
-                                       mut rng: R)
+    fn mate_one_point<T, G, R>(&self,
+                               indv1: &mut Individual<T, G>,
+                               indv2: &mut Individual<T, G>,
+                               mut rng: R)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         let target_index1 = rng.gen_range(0, indv1.nodes_count());
-        let target_index2 = rng.gen_range(0, indv2.nodes_count());
-
-        indv1.tree.map_while(|node1, index1, _| if index1 == target_index1 {
-            indv2.tree.map_while(|node2, index2, _| if index2 == target_index2 {
-                mem::swap(node1, node2);
-                false
-            } else {
-                true
-            });
-            false
+        let target_index2 = if T::constrained() {
+            let candidates = indv1.tree.compatible_indices(target_index1, &indv2.tree);
+            if candidates.is_empty() {
+                indv1.last_changed = None;
+                indv2.last_changed = None;
+                return;
+            }
+            candidates[rng.gen_range(0, candidates.len())]
         } else {
-            true
-        });
-
-        indv1.recalculate_metadata();
-        indv2.recalculate_metadata();
+            rng.gen_range(0, indv2.nodes_count())
+        };
+        swap(indv1, indv2, target_index1, target_index2);
     }
 
-    fn mate_one_point_leaf_biased<T, R>(&self,
-                                        _: &mut Individual<T>,
-                                        _: &mut Individual<T>,
-                                        _: f32,
-                                        _: R)
+    fn mate_one_point_leaf_biased<T, G, R>(&self,
+                                           indv1: &mut Individual<T, G>,
+                                           indv2: &mut Individual<T, G>,
+                                           termpb: f32,
+                                           mut rng: R)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
-        unimplemented!();
+        let all1: Vec<usize> = (0..indv1.nodes_count()).collect();
+        let target_index1 = choose_leaf_biased(&indv1.tree, &all1, termpb, &mut rng);
+        let candidates = if T::constrained() {
+            indv1.tree.compatible_indices(target_index1, &indv2.tree)
+        } else {
+            (0..indv2.nodes_count()).collect()
+        };
+        if candidates.is_empty() {
+            indv1.last_changed = None;
+            indv2.last_changed = None;
+            return;
+        }
+        let target_index2 = choose_leaf_biased(&indv2.tree, &candidates, termpb, &mut rng);
+        swap(indv1, indv2, target_index1, target_index2);
+    }
+}
+
+/// Swap the subtrees at two pre-order indices and update the individuals' metadata.
+fn swap<T, G>(indv1: &mut Individual<T, G>,
+              indv2: &mut Individual<T, G>,
+              target_index1: usize,
+              target_index2: usize)
+    where T: Tree,
+          G: Representation<Tree = T>
+{
+    indv1.tree.swap_subtrees(target_index1, &mut indv2.tree, target_index2);
+
+    indv1.last_changed = Some(target_index1);
+    indv2.last_changed = Some(target_index2);
+    indv1.recalculate_metadata();
+    indv2.recalculate_metadata();
+}
+
+/// Choose one of the non-empty `candidates`, a leaf with `termpb` probability and otherwise
+/// a branch. If the candidates are all leaves or all branches, any of them may be chosen.
+fn choose_leaf_biased<G, R>(tree: &G, candidates: &[usize], termpb: f32, rng: &mut R) -> usize
+    where G: Representation,
+          R: Rng
+{
+    let mut is_leaf = vec![false; tree.count_nodes()];
+    tree.visit(|index, _, children_count| is_leaf[index] = children_count == 0);
+    let (leaves, branches): (Vec<usize>, Vec<usize>) =
+        candidates.iter().partition(|&&index| is_leaf[index]);
+    let pool = if branches.is_empty() || (!leaves.is_empty() && rng.gen::<f32>() < termpb) {
+        leaves
+    } else {
+        branches
+    };
+    pool[rng.gen_range(0, pool.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    /// A hanging mobile of weights on bars. Evaluates to its total weight.
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    enum Mobile {
+        Weight(u32),
+        Bar(BoxTree<Mobile>, BoxTree<Mobile>),
+    }
+
+    impl Tree for Mobile {
+        type Environment = ();
+        type Action = u32;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Mobile> {
+            let left = Self::child(tg, current_depth + 1);
+            Mobile::Bar(left, Self::child(tg, current_depth + 1)).into()
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Mobile> {
+            Mobile::Weight(tg.gen_range(1, 10)).into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Mobile>> {
+            match *self {
+                Mobile::Weight(_) => vec![],
+                Mobile::Bar(ref left, ref right) => vec![left, right],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Mobile>> {
+            match *self {
+                Mobile::Weight(_) => vec![],
+                Mobile::Bar(ref mut left, ref mut right) => vec![left, right],
+            }
+        }
+
+        fn evaluate(&self, _: &()) -> u32 {
+            match *self {
+                Mobile::Weight(weight) => weight,
+                Mobile::Bar(ref left, ref right) => left.evaluate(&()) + right.evaluate(&()),
+            }
+        }
+    }
+
+    #[test]
+    fn one_point_swaps_subtrees() {
+        let mut rng = StdRng::from_seed(&[37usize][..]);
+        let mut tree_rng = StdRng::from_seed(&[38usize][..]);
+        let mut tg = TreeGen::full(&mut tree_rng, 1, 4);
+        let crossover = Crossover::one_point();
+        for _ in 0..50 {
+            let mut indv1: Individual<Mobile> = Individual::new(&mut tg);
+            let mut indv2: Individual<Mobile> = Individual::new(&mut tg);
+            let (before1, before2) = (indv1.clone(), indv2.clone());
+            crossover.mate(&mut indv1, &mut indv2, &mut rng);
+            let (index1, index2) = (indv1.last_changed().unwrap(), indv2.last_changed().unwrap());
+            assert_eq!(indv1.tree.subtree_at(index1), before2.tree.subtree_at(index2));
+            assert_eq!(indv2.tree.subtree_at(index2), before1.tree.subtree_at(index1));
+            assert_eq!(indv1.nodes_count() + indv2.nodes_count(),
+                       before1.nodes_count() + before2.nodes_count());
+            assert_eq!(indv1.tree.evaluate(&()) + indv2.tree.evaluate(&()),
+                       before1.tree.evaluate(&()) + before2.tree.evaluate(&()));
+        }
+    }
+
+    #[test]
+    fn leaf_biased_chooses_leaves_or_branches() {
+        let mut rng = StdRng::from_seed(&[49usize][..]);
+        let mut tree_rng = StdRng::from_seed(&[50usize][..]);
+        let mut tg = TreeGen::full(&mut tree_rng, 1, 4);
+        for &termpb in &[0.0, 1.0] {
+            let crossover = Crossover::one_point_leaf_biased(termpb);
+            for _ in 0..20 {
+                let mut indv1: Individual<Mobile> = Individual::new(&mut tg);
+                let mut indv2: Individual<Mobile> = Individual::new(&mut tg);
+                let before2 = indv2.clone();
+                crossover.mate(&mut indv1, &mut indv2, &mut rng);
+                let (index1, index2) =
+                    (indv1.last_changed().unwrap(), indv2.last_changed().unwrap());
+                assert_eq!(indv1.tree.subtree_at(index1), before2.tree.subtree_at(index2));
+                // Trees of depth at least 1 have both leaves and branches.
+                for subtree in &[indv1.tree.subtree_at(index1), indv2.tree.subtree_at(index2)] {
+                    assert_eq!(subtree.unwrap().children().is_empty(), termpb == 1.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "need 0 <= termpb <= 1")]
+    fn leaf_biased_needs_a_probability() {
+        Crossover::one_point_leaf_biased(1.5);
     }
 }
@@ -0,0 +1,807 @@
+use gp::tree::*;
+use rand::Rng;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::str::Chars;
+
+/// A symbol in a grammar production.
+#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+pub enum Symbol {
+    /// Literal text.
+    Terminal(String),
+    /// Index of a rule in the grammar.
+    NonTerminal(usize),
+}
+
+/// A grammar rule: a non-terminal and the alternative productions it expands to.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Rule {
+    /// Name of the non-terminal, without angle brackets.
+    pub name: String,
+    /// Alternative sequences of symbols the non-terminal can expand to.
+    pub productions: Vec<Vec<Symbol>>,
+}
+
+/// A context-free grammar, defining the valid programs for grammar-guided GP.
+///
+/// Grammars are written in BNF, with the first rule defining the start symbol:
+///
+/// ```text
+/// <expr> ::= <expr> <op> <expr> | "(" <expr> ")" | <var>
+/// <op>   ::= " + " | " - " | " * "
+/// <var>  ::= "x" | "y" | "1"
+/// ```
+///
+/// Terminals are quoted with `"` or `'` and are joined without separators. The EBNF forms
+/// `[ ... ]` (optional), `{ ... }` (zero or more) and `( ... )` (grouping) are rewritten
+/// into extra rules. `#` starts a comment.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Grammar {
+    rules: Vec<Rule>,
+    /// Minimum height of a derivation tree from each rule, counting the rule's own node.
+    min_depths: Vec<usize>,
+    /// Minimum height of a derivation tree from each production of each rule.
+    production_min_depths: Vec<Vec<usize>>,
+}
+
+impl Grammar {
+    /// Parse a grammar from BNF.
+    pub fn parse(bnf: &str) -> Result<Grammar, GrammarError> {
+        let tokens = tokenise(bnf)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            names: vec![],
+            rules: vec![],
+        };
+        parser.parse_rules()?;
+        Grammar::new(parser.rules)
+    }
+
+    /// Create a grammar from rules. The first rule defines the start symbol.
+    ///
+    /// Fails if a non-terminal refers to a missing rule, or a rule can never expand to
+    /// terminals alone.
+    pub fn new(rules: Vec<Rule>) -> Result<Grammar, GrammarError> {
+        if rules.is_empty() {
+            return Err(GrammarError::Empty);
+        }
+        for rule in &rules {
+            if rule.productions.is_empty() {
+                return Err(GrammarError::NonTerminating(rule.name.clone()));
+            }
+            for production in &rule.productions {
+                for symbol in production {
+                    if let Symbol::NonTerminal(index) = *symbol {
+                        if index >= rules.len() {
+                            return Err(GrammarError::Undefined(format!("#{}", index)));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Iterate to a fixpoint. Rules which never get a finite depth cannot terminate.
+        let mut min_depths: Vec<Option<usize>> = vec![None; rules.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, rule) in rules.iter().enumerate() {
+                let depth = rule.productions
+                    .iter()
+                    .filter_map(|production| production_min_depth(production, &min_depths))
+                    .min();
+                if depth.is_some() && depth != min_depths[index] {
+                    min_depths[index] = depth;
+                    changed = true;
+                }
+            }
+        }
+        if let Some(index) = min_depths.iter().position(Option::is_none) {
+            return Err(GrammarError::NonTerminating(rules[index].name.clone()));
+        }
+
+        let production_min_depths = rules.iter()
+            .map(|rule| {
+                rule.productions
+                    .iter()
+                    .map(|production| {
+                        production_min_depth(production, &min_depths)
+                            .expect("every rule terminates")
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Grammar {
+            rules,
+            min_depths: min_depths.into_iter().map(|depth| depth.unwrap()).collect(),
+            production_min_depths,
+        })
+    }
+
+    /// Get the rules. The first rule defines the start symbol.
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Find a rule by name.
+    pub fn rule_index(&self, name: &str) -> Option<usize> {
+        self.rules.iter().position(|rule| rule.name == name)
+    }
+
+    /// Get the minimum height of a derivation tree for a rule, counting the rule's node.
+    pub fn min_depth(&self, rule: usize) -> usize {
+        self.min_depths[rule]
+    }
+
+    /// Get the minimum height of a derivation tree for a production of a rule.
+    pub fn production_min_depth(&self, rule: usize, production: usize) -> usize {
+        self.production_min_depths[rule][production]
+    }
+}
+
+/// Writes the grammar as BNF which `Grammar::parse` reads back into an equal grammar.
+///
+/// Rules added for EBNF forms are written out as ordinary rules.
+impl fmt::Display for Grammar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rule in &self.rules {
+            write!(f, "<{}> ::=", rule.name)?;
+            for (i, production) in rule.productions.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " |")?;
+                }
+                for symbol in production {
+                    match *symbol {
+                        Symbol::Terminal(ref text) => write!(f, " {}", quote(text))?,
+                        Symbol::NonTerminal(index) => write!(f, " <{}>", self.rules[index].name)?,
+                    }
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quote a terminal for BNF, escaping as read by `take_until`.
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Minimum height of a derivation tree for a production, if known.
+fn production_min_depth(production: &[Symbol], min_depths: &[Option<usize>]) -> Option<usize> {
+    let mut depth = 1;
+    for symbol in production {
+        if let Symbol::NonTerminal(index) = *symbol {
+            depth = depth.max(1 + min_depths[index]?);
+        }
+    }
+    Some(depth)
+}
+
+/// Errors from building a `Grammar`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum GrammarError {
+    /// The grammar has no rules.
+    Empty,
+    /// Parsing failed at a byte offset into the BNF, with a description.
+    Syntax(usize, String),
+    /// A non-terminal is used but has no rule.
+    Undefined(String),
+    /// A rule is defined more than once.
+    Duplicate(String),
+    /// A rule can never expand into terminals alone.
+    NonTerminating(String),
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GrammarError::Empty => write!(f, "grammar has no rules"),
+            GrammarError::Syntax(offset, ref message) => {
+                write!(f, "syntax error at byte {}: {}", offset, message)
+            }
+            GrammarError::Undefined(ref name) => write!(f, "no rule for <{}>", name),
+            GrammarError::Duplicate(ref name) => write!(f, "<{}> defined more than once", name),
+            GrammarError::NonTerminating(ref name) => {
+                write!(f, "<{}> never expands into terminals alone", name)
+            }
+        }
+    }
+}
+
+impl Error for GrammarError {}
+
+/// A token of BNF, with its byte offset.
+#[derive(PartialEq, Eq, Clone, Debug)]
+enum Token {
+    NonTerminal(String),
+    Terminal(String),
+    Define,
+    Bar,
+    Open(char),
+    Close(char),
+}
+
+fn tokenise(bnf: &str) -> Result<Vec<(Token, usize)>, GrammarError> {
+    let mut tokens = vec![];
+    let mut chars = bnf.chars().peekable();
+    let mut offset = 0;
+    while let Some(c) = chars.next() {
+        let start = offset;
+        offset += c.len_utf8();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '#' => {
+                for c in chars.by_ref() {
+                    offset += c.len_utf8();
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '<' => {
+                let name = take_until(&mut chars, &mut offset, '>', false)
+                    .ok_or_else(|| GrammarError::Syntax(start, "unterminated <".to_string()))?;
+                Token::NonTerminal(name)
+            }
+            '"' | '\'' => {
+                let text = take_until(&mut chars, &mut offset, c, true).ok_or_else(|| {
+                        GrammarError::Syntax(start, "unterminated string".to_string())
+                    })?;
+                Token::Terminal(text)
+            }
+            ':' => {
+                if chars.next() != Some(':') || chars.next() != Some('=') {
+                    return Err(GrammarError::Syntax(start, "expected ::=".to_string()));
+                }
+                offset += 2;
+                Token::Define
+            }
+            '|' => Token::Bar,
+            '[' | '{' | '(' => Token::Open(c),
+            ']' | '}' | ')' => Token::Close(c),
+            _ => return Err(GrammarError::Syntax(start, format!("unexpected {:?}", c))),
+        };
+        tokens.push((token, start));
+    }
+    Ok(tokens)
+}
+
+/// Take characters up to (and consuming) `end`, processing backslash escapes if `escapes`.
+fn take_until(chars: &mut Peekable<Chars>,
+              offset: &mut usize,
+              end: char,
+              escapes: bool)
+              -> Option<String> {
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        *offset += c.len_utf8();
+        if c == end {
+            return Some(text);
+        }
+        if escapes && c == '\\' {
+            let escaped = chars.next()?;
+            *offset += escaped.len_utf8();
+            text.push(match escaped {
+                'n' => '\n',
+                't' => '\t',
+                other => other,
+            });
+        } else {
+            text.push(c);
+        }
+    }
+    None
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// Names of rules, by index. Rules for EBNF forms are named after their parent rule.
+    names: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+impl Parser {
+    fn parse_rules(&mut self) -> Result<(), GrammarError> {
+        // Rules may be used before they are defined, so find every name first.
+        for window in self.tokens.windows(2) {
+            if let (&(Token::NonTerminal(ref name), _), &(Token::Define, _)) =
+                (&window[0], &window[1]) {
+                if self.names.contains(name) {
+                    return Err(GrammarError::Duplicate(name.clone()));
+                }
+                self.names.push(name.clone());
+            }
+        }
+        self.rules = self.names
+            .iter()
+            .map(|name| {
+                Rule {
+                    name: name.clone(),
+                    productions: vec![],
+                }
+            })
+            .collect();
+
+        let mut rule_index = 0;
+        while self.position < self.tokens.len() {
+            match self.tokens[self.position] {
+                (Token::NonTerminal(_), _) if self.at_definition() => self.position += 2,
+                (_, offset) => {
+                    return Err(GrammarError::Syntax(offset, "expected <rule> ::=".to_string()))
+                }
+            }
+            let productions = self.parse_alternatives(rule_index)?;
+            self.rules[rule_index].productions = productions;
+            rule_index += 1;
+        }
+        Ok(())
+    }
+
+    /// Whether the current token starts a rule definition.
+    fn at_definition(&self) -> bool {
+        self.tokens.get(self.position + 1).map(|token| &token.0) == Some(&Token::Define)
+    }
+
+    fn parse_alternatives(&mut self, parent: usize) -> Result<Vec<Vec<Symbol>>, GrammarError> {
+        let mut productions = vec![self.parse_sequence(parent)?];
+        while let Some(&(Token::Bar, _)) = self.tokens.get(self.position) {
+            self.position += 1;
+            productions.push(self.parse_sequence(parent)?);
+        }
+        Ok(productions)
+    }
+
+    fn parse_sequence(&mut self, parent: usize) -> Result<Vec<Symbol>, GrammarError> {
+        let mut symbols = vec![];
+        while let Some((token, offset)) = self.tokens.get(self.position).cloned() {
+            match token {
+                Token::NonTerminal(_) if self.at_definition() => break,
+                Token::NonTerminal(name) => {
+                    let index = self.names
+                        .iter()
+                        .position(|other| *other == name)
+                        .ok_or(GrammarError::Undefined(name))?;
+                    symbols.push(Symbol::NonTerminal(index));
+                }
+                Token::Terminal(text) => symbols.push(Symbol::Terminal(text)),
+                Token::Open(open) => {
+                    self.position += 1;
+                    let alternatives = self.parse_alternatives(parent)?;
+                    let close = match open {
+                        '[' => ']',
+                        '{' => '}',
+                        _ => ')',
+                    };
+                    match self.tokens.get(self.position) {
+                        Some(&(Token::Close(c), _)) if c == close => {}
+                        _ => {
+                            return Err(GrammarError::Syntax(offset, format!("unclosed {}", open)))
+                        }
+                    }
+                    symbols.push(self.add_ebnf_rule(parent, open, alternatives));
+                }
+                Token::Bar | Token::Close(_) => break,
+                Token::Define => {
+                    return Err(GrammarError::Syntax(offset, "unexpected ::=".to_string()))
+                }
+            }
+            self.position += 1;
+        }
+        Ok(symbols)
+    }
+
+    /// Add a rule for an EBNF form, returning the non-terminal referring to it.
+    fn add_ebnf_rule(&mut self,
+                     parent: usize,
+                     open: char,
+                     mut productions: Vec<Vec<Symbol>>)
+                     -> Symbol {
+        let index = self.rules.len();
+        match open {
+            '[' => productions.push(vec![]),
+            '{' => {
+                for production in &mut productions {
+                    production.push(Symbol::NonTerminal(index));
+                }
+                productions.push(vec![]);
+            }
+            _ => {}
+        }
+        let name = format!("{}#{}", self.names[parent], index);
+        self.names.push(name.clone());
+        self.rules.push(Rule {
+            name,
+            productions,
+        });
+        Symbol::NonTerminal(index)
+    }
+}
+
+/// A node of a derivation tree: a rule expanded by one of its productions.
+///
+/// Children are the expansions of the production's non-terminals, in order. Evaluating a
+/// derivation tree gives the program text it derives.
+///
+/// Derivation trees are generated from the grammar set by `TreeGen::with_grammar` or
+/// `Initialisation::with_grammar`, or directly with `Derivation::generate`.
+/// `Crossover` only swaps subtrees rooted at the same non-terminal, and `Mutation`
+/// regenerates subtrees from the same non-terminal, so programs stay valid.
+#[derive(Clone)]
+pub struct Derivation {
+    grammar: Rc<Grammar>,
+    rule: usize,
+    production: usize,
+    children: Vec<BoxTree<Derivation>>,
+}
+
+impl Derivation {
+    /// Create a node expanding `rule` by `production`.
+    ///
+    /// Panics unless there is one child for each non-terminal in the production.
+    pub fn new(grammar: Rc<Grammar>,
+               rule: usize,
+               production: usize,
+               children: Vec<BoxTree<Derivation>>)
+               -> BoxTree<Derivation> {
+        let nonterminals = grammar.rules[rule].productions[production]
+            .iter()
+            .filter(|symbol| matches!(**symbol, Symbol::NonTerminal(_)))
+            .count();
+        assert_eq!(children.len(), nonterminals, "need one child per non-terminal");
+        Derivation {
+                grammar,
+                rule,
+                production,
+                children,
+            }
+            .into()
+    }
+
+    /// Generate a random derivation tree from the grammar's start symbol.
+    pub fn generate<R: Rng>(grammar: &Rc<Grammar>, tg: &mut TreeGen<R>) -> BoxTree<Derivation> {
+        Self::generate_rule(grammar, 0, tg, 0)
+    }
+
+    /// Generate a random derivation tree from a rule.
+    ///
+    /// Once TreeGen chooses to place a leaf, the shortest productions are used so the tree
+    /// terminates as soon as possible. Otherwise productions which fit within TreeGen's
+    /// maximum depth are chosen between, preferring ones with non-terminals.
+    pub fn generate_rule<R: Rng>(grammar: &Rc<Grammar>,
+                                 rule: usize,
+                                 tg: &mut TreeGen<R>,
+                                 current_depth: usize)
+                                 -> BoxTree<Derivation> {
+        let leaf = tg.have_reached_a_leaf(current_depth);
+        Self::expand(grammar, rule, leaf, tg, current_depth)
+    }
+
+    /// Expand a rule by one of its shortest productions if `leaf`, and otherwise by one
+    /// which fits within TreeGen's maximum depth. See `Derivation::generate_rule`.
+    fn expand<R: Rng>(grammar: &Rc<Grammar>,
+                      rule: usize,
+                      leaf: bool,
+                      tg: &mut TreeGen<R>,
+                      current_depth: usize)
+                      -> BoxTree<Derivation> {
+        let productions = grammar.production_min_depths[rule].len();
+        let shortest: Vec<usize> = (0..productions)
+            .filter(|&p| grammar.production_min_depths[rule][p] == grammar.min_depths[rule])
+            .collect();
+        let candidates = if leaf {
+            shortest
+        } else {
+            let fitting: Vec<usize> = (0..productions)
+                .filter(|&p| {
+                    current_depth + grammar.production_min_depths[rule][p] <= tg.max_depth() + 1
+                })
+                .collect();
+            let branching: Vec<usize> = fitting.iter()
+                .cloned()
+                .filter(|&p| grammar.production_min_depths[rule][p] > 1)
+                .collect();
+            if !branching.is_empty() {
+                branching
+            } else if !fitting.is_empty() {
+                fitting
+            } else {
+                shortest
+            }
+        };
+        let production = candidates[tg.gen_range(0, candidates.len())];
+
+        let children = grammar.rules[rule].productions[production]
+            .iter()
+            .filter_map(|symbol| match *symbol {
+                Symbol::NonTerminal(child_rule) => {
+                    Some(Self::generate_rule(grammar, child_rule, tg, current_depth + 1))
+                }
+                Symbol::Terminal(_) => None,
+            })
+            .collect();
+        Self::new(grammar.clone(), rule, production, children)
+    }
+
+    /// Get the grammar this tree derives from.
+    pub fn grammar(&self) -> &Rc<Grammar> {
+        &self.grammar
+    }
+
+    /// Get the index of the rule this node expands.
+    pub fn rule(&self) -> usize {
+        self.rule
+    }
+
+    /// Get the index of the production used to expand the rule.
+    pub fn production(&self) -> usize {
+        self.production
+    }
+
+    /// Get the grammar set on a TreeGen, to generate from its start symbol.
+    fn start_grammar<R: Rng>(tg: &TreeGen<R>) -> Rc<Grammar> {
+        tg.grammar()
+            .cloned()
+            .expect("derivation trees need a grammar; see TreeGen::with_grammar")
+    }
+
+    /// Append the program text derived from this node onto `out`.
+    fn write_text(&self, out: &mut String) {
+        let mut children = self.children.iter();
+        for symbol in &self.grammar.rules[self.rule].productions[self.production] {
+            match *symbol {
+                Symbol::Terminal(ref text) => out.push_str(text),
+                Symbol::NonTerminal(_) => {
+                    children.next().expect("one child per non-terminal").write_text(out)
+                }
+            }
+        }
+    }
+}
+
+impl Tree for Derivation {
+    type Environment = ();
+    type Action = String;
+
+    /// Generate from the start symbol of the grammar set by `TreeGen::with_grammar`.
+    ///
+    /// Panics if the TreeGen has no grammar.
+    fn child<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Self> {
+        Self::generate_rule(&Self::start_grammar(tg), 0, tg, current_depth)
+    }
+
+    /// Expand the start symbol of the grammar set by `TreeGen::with_grammar`, preferring
+    /// productions with non-terminals.
+    fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Self> {
+        Self::expand(&Self::start_grammar(tg), 0, false, tg, current_depth)
+    }
+
+    /// Expand the start symbol of the grammar set by `TreeGen::with_grammar` by one of its
+    /// shortest productions.
+    fn leaf<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Self> {
+        Self::expand(&Self::start_grammar(tg), 0, true, tg, current_depth)
+    }
+
+    fn count_children(&mut self) -> usize {
+        self.children.len()
+    }
+
+    fn children(&self) -> Vec<&BoxTree<Self>> {
+        self.children.iter().collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut BoxTree<Self>> {
+        self.children.iter_mut().collect()
+    }
+
+    /// Only subtrees rooted at the same non-terminal may be swapped.
+    fn compatible(&self, other: &Self) -> bool {
+        self.rule == other.rule
+    }
+
+    fn constrained() -> bool {
+        true
+    }
+
+    /// Regenerate from the same non-terminal.
+    fn regenerate<R: Rng>(&self, tg: &mut TreeGen<R>) -> BoxTree<Self> {
+        Self::generate_rule(&self.grammar, self.rule, tg, 0)
+    }
+
+    /// Get the program text derived from this node.
+    fn evaluate(&self, _: &()) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text);
+        text
+    }
+}
+
+/// Derivation trees from different grammars are never compared, so only the derivation is
+/// compared rather than the grammar.
+impl PartialEq for Derivation {
+    fn eq(&self, other: &Derivation) -> bool {
+        self.rule == other.rule && self.production == other.production &&
+        self.children == other.children
+    }
+}
+
+impl Eq for Derivation {}
+
+impl Hash for Derivation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rule.hash(state);
+        self.production.hash(state);
+        self.children.hash(state);
+    }
+}
+
+impl fmt::Debug for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "<{}>#{}",
+               self.grammar.rules[self.rule].name,
+               self.production)?;
+        if !self.children.is_empty() {
+            f.debug_list().entries(&self.children).finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Derivation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.evaluate(&()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gp::*;
+    use rand::{SeedableRng, StdRng};
+
+    const EXPRESSIONS: &str = "
+        <expr> ::= <expr> <op> <expr> | \"(\" <expr> \")\" | <var>
+        <op>   ::= \" + \" | \" - \" | \" * \"
+        <var>  ::= \"x\" | \"y\" | \"1\"
+    ";
+
+    fn expressions() -> Rc<Grammar> {
+        Rc::new(Grammar::parse(EXPRESSIONS).unwrap())
+    }
+
+    /// Whether every node's children expand the non-terminals of its production.
+    fn valid(tree: &BoxTree<Derivation>) -> bool {
+        let rules: Vec<usize> = tree.grammar().rules()[tree.rule()].productions[tree.production()]
+            .iter()
+            .filter_map(|symbol| match *symbol {
+                Symbol::NonTerminal(rule) => Some(rule),
+                Symbol::Terminal(_) => None,
+            })
+            .collect();
+        let children = tree.children();
+        rules.len() == children.len() &&
+        rules.iter().zip(&children).all(|(&rule, child)| child.rule() == rule && valid(child))
+    }
+
+    #[test]
+    fn parse_bnf() {
+        let grammar = expressions();
+        let names: Vec<&str> = grammar.rules().iter().map(|rule| &rule.name[..]).collect();
+        assert_eq!(names, vec!["expr", "op", "var"]);
+        assert_eq!(grammar.rules()[0].productions[1],
+                   vec![Symbol::Terminal("(".to_string()),
+                        Symbol::NonTerminal(0),
+                        Symbol::Terminal(")".to_string())]);
+        assert_eq!(grammar.rule_index("var"), Some(2));
+        assert_eq!((grammar.min_depth(0), grammar.min_depth(2)), (2, 1));
+        assert_eq!(grammar.production_min_depth(0, 0), 3);
+    }
+
+    #[test]
+    fn bnf_round_trips() {
+        let bnf = "
+            # Lists of quoted items, using every EBNF form.
+            <list> ::= '[' [<item> {\", \" <item>}] \"]\"
+            <item> ::= \"a\" | \"\\\"q\\\"\" | \"tab\\t\\\\\" | (\"b\" | \"c\") <item> |
+        ";
+        let grammar = Grammar::parse(bnf).unwrap();
+        assert_eq!(grammar.rules().len(), 5);
+        assert_eq!(grammar.rules()[1].productions[1],
+                   vec![Symbol::Terminal("\"q\"".to_string())]);
+        assert_eq!(grammar.rules()[1].productions[4], vec![]);
+        let written = grammar.to_string();
+        assert_eq!(Grammar::parse(&written), Ok(grammar.clone()));
+        assert_eq!(Grammar::parse(&written).unwrap().to_string(), written);
+        assert_eq!(Grammar::parse(&expressions().to_string()).unwrap(), *expressions());
+    }
+
+    #[test]
+    fn bnf_errors() {
+        assert_eq!(Grammar::parse(""), Err(GrammarError::Empty));
+        assert_eq!(Grammar::parse("<a> ::= <b>"),
+                   Err(GrammarError::Undefined("b".to_string())));
+        assert_eq!(Grammar::parse("<a> ::= \"x\" <a> ::= \"y\""),
+                   Err(GrammarError::Duplicate("a".to_string())));
+        assert_eq!(Grammar::parse("<a> ::= <a> \"x\""),
+                   Err(GrammarError::NonTerminating("a".to_string())));
+        assert_eq!(Grammar::parse("<a> ::= \"x"),
+                   Err(GrammarError::Syntax(8, "unterminated string".to_string())));
+        assert_eq!(Grammar::parse("<a> : \"x\""),
+                   Err(GrammarError::Syntax(4, "expected ::=".to_string())));
+        assert_eq!(Grammar::parse("<a> ::= [\"x\""),
+                   Err(GrammarError::Syntax(8, "unclosed [".to_string())));
+    }
+
+    #[test]
+    fn generate_from_tree_gen_grammar() {
+        let grammar = expressions();
+        let mut rng = StdRng::from_seed(&[37usize][..]);
+        let mut tg = TreeGen::full(&mut rng, 1, 6).with_grammar(grammar.clone());
+        for _ in 0..50 {
+            let indv: Individual<Derivation> = Individual::new(&mut tg);
+            assert!(valid(&indv.tree));
+            assert!(indv.height() <= 6);
+            let text = indv.tree.evaluate(&());
+            assert!(text.chars().all(|c| "xy1()+-* ".contains(c)), "{}", text);
+            assert_eq!(text, indv.to_string());
+        }
+
+        let population: Vec<Individual<Derivation>> = Initialisation::ramped_half_and_half(2, 4)
+            .with_grammar(grammar)
+            .population(20, &mut rng);
+        assert!(population.iter().all(|indv| valid(&indv.tree)));
+    }
+
+    #[test]
+    fn operators_keep_derivations_valid() {
+        let grammar = expressions();
+        let mut rng = StdRng::from_seed(&[37usize][..]);
+        let mut population: Vec<Individual<Derivation>> = Initialisation::ramped_half_and_half(2,
+                                                                                            5)
+            .with_grammar(grammar)
+            .population(20, &mut rng);
+        let crossover = Crossover::one_point();
+        let mutation = Mutation::uniform();
+        let mut tg = TreeGen::full(&mut rng, 1, 3);
+        for _ in 0..10 {
+            for pair in population.chunks_mut(2) {
+                let (first, second) = pair.split_at_mut(1);
+                crossover.mate(&mut first[0], &mut second[0], &mut tg);
+                if let Some(index) = first[0].last_changed() {
+                    let swapped = first[0].tree.subtree_at(index).unwrap().rule();
+                    let other = second[0].tree.subtree_at(second[0].last_changed().unwrap());
+                    assert_eq!(swapped, other.unwrap().rule());
+                }
+                mutation.mutate(&mut first[0], &mut tg);
+            }
+            assert!(population.iter().all(|indv| valid(&indv.tree)));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "need a grammar")]
+    fn generating_needs_a_grammar() {
+        let mut rng = StdRng::from_seed(&[37usize][..]);
+        Derivation::tree(&mut TreeGen::full(&mut rng, 1, 3));
+    }
+}
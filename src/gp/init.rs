@@ -0,0 +1,230 @@
+use gp::*;
+use gp::grammar::Grammar;
+use rand::Rng;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// The initialisation mode in use. See `Initialisation`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum InitialisationMode {
+    /// Corresponds to `Initialisation::ramped_half_and_half`.
+    RampedHalfAndHalf,
+}
+
+/// Configures generation of an initial population of GP individuals.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Initialisation {
+    mode: InitialisationMode,
+    /// The minimum depth of trees to generate.
+    min_depth: usize,
+    /// The maximum depth of trees to generate.
+    max_depth: usize,
+    /// Weights of each kind of branch node. See `TreeGen::with_weights`.
+    branch_weights: Vec<u32>,
+    /// Weights of each kind of leaf node. See `TreeGen::with_weights`.
+    leaf_weights: Vec<u32>,
+    /// Grammar for derivation trees. See `TreeGen::with_grammar`.
+    grammar: Option<Rc<Grammar>>,
+}
+
+impl Initialisation {
+    /// Generate a population by ramped half-and-half.
+    ///
+    /// Individuals are spread evenly over every depth in [min_depth, max_depth], and at
+    /// each depth alternate between perfect trees (`TreeGen::perfect`) and trees with
+    /// leaves at varying depths (`TreeGen::full`).
+    ///
+    /// **This is the equivalent of DEAP's `genHalfAndHalf` in its standard ramped use.**
+    ///
+    /// Panics if `min_depth` is greater than `max_depth`.
+    pub fn ramped_half_and_half(min_depth: usize, max_depth: usize) -> Initialisation {
+        assert!(min_depth <= max_depth, "need min_depth <= max_depth");
+        Initialisation {
+            mode: InitialisationMode::RampedHalfAndHalf,
+            min_depth,
+            max_depth,
+            branch_weights: vec![],
+            leaf_weights: vec![],
+            grammar: None,
+        }
+    }
+
+    /// Set the relative probabilities of choosing each kind of branch and leaf node. See
+    /// `TreeGen::with_weights`.
+    pub fn with_weights(mut self, branch_weights: Vec<u32>, leaf_weights: Vec<u32>) -> Self {
+        self.branch_weights = branch_weights;
+        self.leaf_weights = leaf_weights;
+        self
+    }
+
+    /// Set the grammar to generate `Derivation` trees from. See `TreeGen::with_grammar`.
+    pub fn with_grammar(mut self, grammar: Rc<Grammar>) -> Self {
+        self.grammar = Some(grammar);
+        self
+    }
+
+    /// Generate a population of `size` individuals.
+    pub fn population<T, G, R>(&self, size: usize, rng: &mut R) -> Vec<Individual<T, G>>
+        where T: Tree,
+              G: Representation<Tree = T>,
+              R: Rng
+    {
+        (0..size).map(|i| self.individual(i, rng)).collect()
+    }
+
+    /// Generate a population of `size` individuals, without structurally identical trees.
+    ///
+    /// Duplicates are regenerated up to `max_attempts` times each, after which they are
+    /// accepted. This bounds the time taken when there are few distinct small trees.
+    pub fn unique_population<T, G, R>(&self,
+                                      size: usize,
+                                      max_attempts: usize,
+                                      rng: &mut R)
+                                      -> Vec<Individual<T, G>>
+        where T: Tree,
+              G: Representation<Tree = T>,
+              R: Rng
+    {
+        let mut seen = HashSet::new();
+        (0..size)
+            .map(|i| {
+                let mut indv = self.individual(i, rng);
+                let mut hash = indv.structural_hash();
+                for _ in 0..max_attempts {
+                    if !seen.contains(&hash) {
+                        break;
+                    }
+                    indv = self.individual(i, rng);
+                    hash = indv.structural_hash();
+                }
+                seen.insert(hash);
+                indv
+            })
+            .collect()
+    }
+
+    /// Generate the `i`th individual of a population.
+    fn individual<T, G, R>(&self, i: usize, rng: &mut R) -> Individual<T, G>
+        where T: Tree,
+              G: Representation<Tree = T>,
+              R: Rng
+    {
+        match self.mode {
+            InitialisationMode::RampedHalfAndHalf => {
+                let depths = self.max_depth - self.min_depth + 1;
+                let depth = self.min_depth + (i / 2) % depths;
+                let tg = match i % 2 {
+                    0 => TreeGen::perfect(rng, depth, depth),
+                    _ => TreeGen::full(rng, self.min_depth, depth),
+                };
+                let mut tg = tg.with_weights(self.branch_weights.clone(),
+                                             self.leaf_weights.clone());
+                if let Some(ref grammar) = self.grammar {
+                    tg = tg.with_grammar(grammar.clone());
+                }
+                Individual::new(&mut tg)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    /// Binary digits read left to right, such as `0110`.
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    enum Digits {
+        Zero,
+        One,
+        Then(BoxTree<Digits>, BoxTree<Digits>),
+    }
+
+    impl Tree for Digits {
+        type Environment = ();
+        type Action = String;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Digits> {
+            let first = Self::child(tg, current_depth + 1);
+            Digits::Then(first, Self::child(tg, current_depth + 1)).into()
+        }
+
+        /// Leaf weights are for `Zero` then `One`.
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Digits> {
+            match tg.choose_leaf(2) {
+                0 => Digits::Zero,
+                _ => Digits::One,
+            }
+            .into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Digits>> {
+            match *self {
+                Digits::Then(ref a, ref b) => vec![a, b],
+                _ => vec![],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Digits>> {
+            match *self {
+                Digits::Then(ref mut a, ref mut b) => vec![a, b],
+                _ => vec![],
+            }
+        }
+
+        fn evaluate(&self, _: &()) -> String {
+            match *self {
+                Digits::Zero => "0".to_string(),
+                Digits::One => "1".to_string(),
+                Digits::Then(ref a, ref b) => a.evaluate(&()) + &b.evaluate(&()),
+            }
+        }
+    }
+
+    #[test]
+    fn ramped_half_and_half_spreads_depths() {
+        let mut rng = StdRng::from_seed(&[34usize][..]);
+        let population: Vec<Individual<Digits>> =
+            Initialisation::ramped_half_and_half(1, 3).population(60, &mut rng);
+        assert_eq!(population.len(), 60);
+        for (i, indv) in population.iter().enumerate() {
+            let depth = 1 + (i / 2) % 3;
+            if i % 2 == 0 {
+                assert_eq!(indv.height(), depth);
+                assert_eq!(indv.depth_counts().len(), depth + 1);
+            } else {
+                assert!(indv.height() >= 1 && indv.height() <= depth);
+            }
+        }
+    }
+
+    #[test]
+    fn unique_population_has_no_duplicates() {
+        let mut rng = StdRng::from_seed(&[34usize][..]);
+        let population: Vec<Individual<Digits>> =
+            Initialisation::ramped_half_and_half(3, 5).unique_population(100, 50, &mut rng);
+        let hashes: HashSet<u64> = population.iter().map(|indv| indv.structural_hash()).collect();
+        assert_eq!(hashes.len(), 100);
+    }
+
+    #[test]
+    fn unique_population_gives_up_after_max_attempts() {
+        // With only `Zero` leaves and depth 0, `0` is the only possible tree.
+        let init = Initialisation::ramped_half_and_half(0, 0).with_weights(vec![], vec![1, 0]);
+        let mut rng = StdRng::from_seed(&[34usize][..]);
+        let population: Vec<Individual<Digits>> = init.unique_population(5, 3, &mut rng);
+        assert_eq!(population.len(), 5);
+        assert!(population.iter().all(|indv| indv.tree.evaluate(&()) == "0"));
+    }
+
+    #[test]
+    #[should_panic(expected = "need min_depth <= max_depth")]
+    fn ramped_half_and_half_rejects_inverted_depths() {
+        Initialisation::ramped_half_and_half(3, 2);
+    }
+}
@@ -1,42 +1,73 @@
 /// Types and utilities for Genetic Program trees.
 pub mod tree;
+/// Grammar-guided Genetic Programming with BNF grammars.
+pub mod grammar;
+/// Symbolic regression.
+pub mod regression;
+/// Standard benchmark problems, for measuring search performance.
+pub mod benchmarks;
 mod crossover;
 mod mutation;
+mod cache;
+mod init;
+mod constants;
 
 /// Genetic Program crossover (mating).
 pub use self::crossover::*;
 /// Genetic Program mutation.
 pub use self::mutation::*;
+/// Caching of tree evaluation results.
+pub use self::cache::*;
+/// Genetic Program population initialisation.
+pub use self::init::*;
+/// Optimisation of numeric constants in trees.
+pub use self::constants::*;
 
 use rand::Rng;
 use std::fmt;
+use std::marker::PhantomData;
 use self::tree::*;
 
 /// A genetic individual to mate and mutate in a Genetic Program.
 ///
-/// Wraps around a `BoxTree` and caches useful data.
+/// Wraps around a tree and caches useful data. Trees are stored as a `BoxTree` unless
+/// another `Representation` such as `FlatTree` is chosen.
 #[derive(Debug, Clone)]
-pub struct Individual<T>
-    where T: Tree
+pub struct Individual<T, G = BoxTree<T>>
+    where T: Tree,
+          G: Representation<Tree = T>
 {
     /// The contained GP tree, starting at the head.
-    pub tree: BoxTree<T>,
+    pub tree: G,
     nodes_count: usize,
+    height: usize,
+    leaves_count: usize,
+    depth_counts: Vec<usize>,
+    structural_hash: u64,
+    last_changed: Option<usize>,
+    tree_type: PhantomData<T>,
 }
 
-impl<T> Individual<T>
-    where T: Tree
+impl<T, G> Individual<T, G>
+    where T: Tree,
+          G: Representation<Tree = T>
 {
     /// Generate a new Tree and individual.
-    pub fn new<R: Rng>(tg: &mut TreeGen<R>) -> Individual<T> {
-        Self::new_from_tree(T::tree(tg))
+    pub fn new<R: Rng>(tg: &mut TreeGen<R>) -> Individual<T, G> {
+        Self::new_from_tree(G::generate(tg))
     }
 
     /// Create from a Tree.
-    pub fn new_from_tree(boxtree: BoxTree<T>) -> Individual<T> {
+    pub fn new_from_tree(tree: G) -> Individual<T, G> {
         let mut indv = Individual {
-            tree: boxtree,
+            tree,
             nodes_count: 0,
+            height: 0,
+            leaves_count: 0,
+            depth_counts: vec![],
+            structural_hash: 0,
+            last_changed: None,
+            tree_type: PhantomData,
         };
         indv.recalculate_metadata();
         indv
@@ -47,16 +78,167 @@ impl<T> Individual<T>
         self.nodes_count
     }
 
+    /// Get cached height of the tree, the 0-based depth of its deepest node.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get cached number of leaves (nodes without children) in tree.
+    pub fn leaves_count(&self) -> usize {
+        self.leaves_count
+    }
+
+    /// Get cached number of nodes at each depth of the tree, starting from the root.
+    pub fn depth_counts(&self) -> &[usize] {
+        &self.depth_counts
+    }
+
+    /// Get cached structural hash of the tree. See `Representation::structural_hash`.
+    pub fn structural_hash(&self) -> u64 {
+        self.structural_hash
+    }
+
+    /// Get the pre-order index of the subtree replaced by the latest crossover or mutation.
+    pub fn last_changed(&self) -> Option<usize> {
+        self.last_changed
+    }
+
     /// Update cached metadata such at the number of nodes in the tree.
+    ///
+    /// Call this after changing `tree` directly. `Crossover` and `Mutation` call it for you.
     pub fn recalculate_metadata(&mut self) {
-        self.nodes_count = self.tree.count_nodes();
+        let mut nodes_count = 0;
+        let mut leaves_count = 0;
+        let mut depth_counts = vec![];
+        self.tree.visit(|_, depth, children_count| {
+            nodes_count += 1;
+            if children_count == 0 {
+                leaves_count += 1;
+            }
+            if depth_counts.len() <= depth {
+                depth_counts.resize(depth + 1, 0);
+            }
+            depth_counts[depth] += 1;
+        });
+        self.nodes_count = nodes_count;
+        self.leaves_count = leaves_count;
+        self.height = depth_counts.len() - 1;
+        self.depth_counts = depth_counts;
+        self.structural_hash = self.tree.structural_hash();
     }
 }
 
-impl<T> fmt::Display for Individual<T>
-    where T: Tree + fmt::Display
+impl<T, G> fmt::Display for Individual<T, G>
+    where T: Tree,
+          G: Representation<Tree = T> + fmt::Display
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.tree)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Nodes numbered in pre-order. Evaluates to the largest number in the tree.
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    struct Numbered(u8, Vec<BoxTree<Numbered>>);
+
+    impl Tree for Numbered {
+        type Environment = ();
+        type Action = u8;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Numbered> {
+            Numbered(0, vec![Self::child(tg, current_depth + 1)]).into()
+        }
+
+        fn leaf<R: Rng>(_: &mut TreeGen<R>, _: usize) -> BoxTree<Numbered> {
+            Numbered(0, vec![]).into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.1.len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Numbered>> {
+            self.1.iter().collect()
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Numbered>> {
+            self.1.iter_mut().collect()
+        }
+
+        fn evaluate(&self, _: &()) -> u8 {
+            self.1.iter().map(|child| child.evaluate(&())).fold(self.0, u8::max)
+        }
+    }
+
+    impl Compile for Numbered {
+        type Op = u8;
+
+        fn op(&self) -> u8 {
+            self.0
+        }
+
+        fn execute(op: &u8, _: &(), args: &[u8]) -> u8 {
+            args.iter().cloned().fold(*op, u8::max)
+        }
+    }
+
+    impl Flatten for Numbered {
+        fn unflatten(op: u8, children: Vec<BoxTree<Numbered>>) -> BoxTree<Numbered> {
+            Numbered(op, children).into()
+        }
+    }
+
+    fn numbered(number: u8, children: Vec<BoxTree<Numbered>>) -> BoxTree<Numbered> {
+        Numbered(number, children).into()
+    }
+
+    /// `0(1(2, 3(4)), 5)`.
+    fn tree() -> BoxTree<Numbered> {
+        let one = numbered(1, vec![numbered(2, vec![]), numbered(3, vec![numbered(4, vec![])])]);
+        numbered(0, vec![one, numbered(5, vec![])])
+    }
+
+    #[test]
+    fn metadata() {
+        let indv: Individual<Numbered> = Individual::new_from_tree(tree());
+        assert_eq!(indv.nodes_count(), 6);
+        assert_eq!(indv.height(), 3);
+        assert_eq!(indv.leaves_count(), 3);
+        assert_eq!(indv.depth_counts(), &[1, 2, 2, 1]);
+        assert_eq!(indv.structural_hash(), tree().structural_hash());
+
+        let flat: Individual<Numbered, FlatTree<Numbered>> =
+            Individual::new_from_tree(FlatTree::from(&tree()));
+        assert_eq!(flat.nodes_count(), indv.nodes_count());
+        assert_eq!(flat.height(), indv.height());
+        assert_eq!(flat.leaves_count(), indv.leaves_count());
+        assert_eq!(flat.depth_counts(), indv.depth_counts());
+        assert_eq!(flat.structural_hash(), flat.tree.structural_hash());
+    }
+
+    #[test]
+    fn metadata_follows_changes() {
+        let mut indv: Individual<Numbered> = Individual::new_from_tree(tree());
+        let hash = indv.structural_hash();
+        indv.tree.replace_subtree(&[0], numbered(1, vec![]));
+        // Metadata is cached until recalculated.
+        assert_eq!((indv.nodes_count(), indv.structural_hash()), (6, hash));
+        indv.recalculate_metadata();
+        assert_eq!(indv.nodes_count(), 3);
+        assert_eq!(indv.height(), 1);
+        assert_eq!(indv.depth_counts(), &[1, 2]);
+        assert!(indv.structural_hash() != hash);
+        assert_eq!(Individual::<Numbered>::new_from_tree(tree()).structural_hash(), hash);
+    }
+
+    #[test]
+    fn individuals_are_send_and_sync() {
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<Individual<Numbered>>();
+        assert_send_sync::<Individual<Numbered, FlatTree<Numbered>>>();
+    }
+}
@@ -30,13 +30,14 @@ pub struct Mutation {
 }
 
 impl Mutation {
-    /// Perform mutation by randomly replacing a node with one of its children.
-    #[doc(hidden)]
+    /// Perform mutation by randomly replacing a branch with one of its children, making
+    /// the tree smaller. A tree without branches is unchanged.
     pub fn shrink() -> Mutation {
         Mutation { mode: MutationMode::Shrink }
     }
 
-    /// Perform mutation by randomly replacing a node with a new subtree.
+    /// Perform mutation by randomly replacing a node with a new subtree, generated by
+    /// `Tree::regenerate`.
     pub fn uniform() -> Mutation {
         Mutation { mode: MutationMode::Uniform }
     }
@@ -68,8 +69,9 @@ impl Mutation {
     }
 
     /// Mutate an individual according to the configured mutation mode.
-    pub fn mutate<T, R>(&self, indv: &mut Individual<T>, tg: &mut TreeGen<R>)
+    pub fn mutate<T, G, R>(&self, indv: &mut Individual<T, G>, tg: &mut TreeGen<R>)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         match self.mode {
@@ -82,51 +84,169 @@ impl Mutation {
         }
     }
 
-    fn mutate_shrink<T, R>(&self, _: &mut Individual<T>, _: &mut TreeGen<R>)
+    fn mutate_shrink<T, G, R>(&self, indv: &mut Individual<T, G>, tg: &mut TreeGen<R>)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
-        unimplemented!();
+        // The pre-order indices of each node's children. The ancestors of the node being
+        // visited are on the stack with their depths.
+        let mut children = vec![vec![]; indv.nodes_count()];
+        let mut ancestors: Vec<(usize, usize)> = vec![];
+        indv.tree.visit(|index, depth, _| {
+            while let Some(&(_, ancestor_depth)) = ancestors.last() {
+                if ancestor_depth < depth {
+                    break;
+                }
+                ancestors.pop();
+            }
+            if let Some(&(parent, _)) = ancestors.last() {
+                children[parent].push(index);
+            }
+            ancestors.push((index, depth));
+        });
+        let branches: Vec<usize> =
+            (0..children.len()).filter(|&index| !children[index].is_empty()).collect();
+        if branches.is_empty() {
+            indv.last_changed = None;
+            return;
+        }
+
+        let target_index = branches[tg.gen_range(0, branches.len())];
+        let child_index = children[target_index][tg.gen_range(0, children[target_index].len())];
+        // Swapping with a copy of the tree moves the child's subtree up into the target's
+        // place.
+        let mut copy = indv.tree.clone();
+        indv.tree.swap_subtrees(target_index, &mut copy, child_index);
+        indv.last_changed = Some(target_index);
+        indv.recalculate_metadata();
     }
 
-    fn mutate_uniform<T, R>(&self, indv: &mut Individual<T>, tg: &mut TreeGen<R>)
+    fn mutate_uniform<T, G, R>(&self, indv: &mut Individual<T, G>, tg: &mut TreeGen<R>)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         let target_index = tg.gen_range(0, indv.nodes_count());
-        indv.tree.map_while(|node, index, _| if index == target_index {
-            *node = T::tree(tg).inner();
-            false
-        } else {
-            true
-        });
+        let subtree = indv.tree.regenerate(target_index, tg);
+        indv.tree.replace_subtree(target_index, subtree);
+        indv.last_changed = Some(target_index);
+        indv.recalculate_metadata();
     }
 
-    fn mutate_node_replacement<T, R>(&self, _: &mut Individual<T>, _: &mut TreeGen<R>)
+    fn mutate_node_replacement<T, G, R>(&self, _: &mut Individual<T, G>, _: &mut TreeGen<R>)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         unimplemented!();
     }
 
-    fn mutate_ephemeral_one<T, R>(&self, _: &mut Individual<T>, _: &mut TreeGen<R>)
+    fn mutate_ephemeral_one<T, G, R>(&self, _: &mut Individual<T, G>, _: &mut TreeGen<R>)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         unimplemented!();
     }
 
-    fn mutate_ephemeral_all<T, R>(&self, _: &mut Individual<T>, _: &mut TreeGen<R>)
+    fn mutate_ephemeral_all<T, G, R>(&self, _: &mut Individual<T, G>, _: &mut TreeGen<R>)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         unimplemented!();
     }
 
-    fn mutate_insert<T, R>(&self, _: &mut Individual<T>, _: &mut TreeGen<R>)
+    fn mutate_insert<T, G, R>(&self, _: &mut Individual<T, G>, _: &mut TreeGen<R>)
         where T: Tree,
+              G: Representation<Tree = T>,
               R: Rng
     {
         unimplemented!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    /// Natural numbers built from zero by successors and sums.
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    enum Peano {
+        Zero,
+        Succ(BoxTree<Peano>),
+        Plus(BoxTree<Peano>, BoxTree<Peano>),
+    }
+
+    impl Tree for Peano {
+        type Environment = ();
+        type Action = usize;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Peano> {
+            let first = Self::child(tg, current_depth + 1);
+            match tg.gen_range(0, 2) {
+                0 => Peano::Succ(first),
+                _ => Peano::Plus(first, Self::child(tg, current_depth + 1)),
+            }
+            .into()
+        }
+
+        fn leaf<R: Rng>(_: &mut TreeGen<R>, _: usize) -> BoxTree<Peano> {
+            Peano::Zero.into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Peano>> {
+            match *self {
+                Peano::Zero => vec![],
+                Peano::Succ(ref a) => vec![a],
+                Peano::Plus(ref a, ref b) => vec![a, b],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Peano>> {
+            match *self {
+                Peano::Zero => vec![],
+                Peano::Succ(ref mut a) => vec![a],
+                Peano::Plus(ref mut a, ref mut b) => vec![a, b],
+            }
+        }
+
+        fn evaluate(&self, _: &()) -> usize {
+            match *self {
+                Peano::Zero => 0,
+                Peano::Succ(ref a) => a.evaluate(&()) + 1,
+                Peano::Plus(ref a, ref b) => a.evaluate(&()) + b.evaluate(&()),
+            }
+        }
+    }
+
+    #[test]
+    fn shrink_replaces_a_branch_with_a_child() {
+        let mut rng = StdRng::from_seed(&[49usize][..]);
+        let mut tg = TreeGen::full(&mut rng, 1, 4);
+        let mutation = Mutation::shrink();
+        for _ in 0..50 {
+            let mut indv: Individual<Peano> = Individual::new(&mut tg);
+            let before = indv.clone();
+            mutation.mutate(&mut indv, &mut tg);
+            let index = indv.last_changed().unwrap();
+            let replaced = before.tree.subtree_at(index).unwrap();
+            let replacement = indv.tree.subtree_at(index).unwrap();
+            assert!(replaced.children().contains(&replacement));
+            assert!(indv.nodes_count() < before.nodes_count());
+            // Dropping successors and summands never makes the number larger.
+            assert!(indv.tree.evaluate(&()) <= before.tree.evaluate(&()));
+        }
+
+        let mut zero: Individual<Peano> = Individual::new_from_tree(Peano::Zero.into());
+        mutation.mutate(&mut zero, &mut tg);
+        assert_eq!(zero.last_changed(), None);
+        assert_eq!(zero.tree, Peano::Zero.into());
+    }
+}
@@ -0,0 +1,440 @@
+use gp::*;
+use rand::Rng;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Numeric samples of input variables with a target for each.
+///
+/// Each sample's inputs are an `Equation` environment, so `inputs` can be passed straight
+/// to `Tree::evaluate` or `SubtreeCache::evaluate_batch`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Dataset {
+    names: Vec<String>,
+    inputs: Vec<Vec<f64>>,
+    targets: Vec<f64>,
+}
+
+impl Dataset {
+    /// Create a dataset from samples of inputs and their targets. Variables are named `x0`
+    /// upwards.
+    ///
+    /// Panics unless there is one target per sample and every sample has the same number of
+    /// inputs.
+    pub fn new(inputs: Vec<Vec<f64>>, targets: Vec<f64>) -> Dataset {
+        assert_eq!(inputs.len(), targets.len(), "need one target per sample");
+        let variables = inputs.first().map_or(0, |sample| sample.len());
+        assert!(inputs.iter().all(|sample| sample.len() == variables),
+                "every sample needs the same number of inputs");
+        Dataset {
+            names: (0..variables).map(|i| format!("x{}", i)).collect(),
+            inputs,
+            targets,
+        }
+    }
+
+    /// Create a dataset by applying `target` to each sample of inputs.
+    pub fn from_function<F>(inputs: Vec<Vec<f64>>, target: F) -> Dataset
+        where F: Fn(&[f64]) -> f64
+    {
+        let targets = inputs.iter().map(|sample| target(sample)).collect();
+        Dataset::new(inputs, targets)
+    }
+
+    /// Set the names of the input variables.
+    pub fn with_names(mut self, names: Vec<String>) -> Dataset {
+        assert_eq!(names.len(), self.names.len(), "need one name per variable");
+        self.names = names;
+        self
+    }
+
+    /// Get the names of the input variables.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Get the inputs of each sample.
+    pub fn inputs(&self) -> &[Vec<f64>] {
+        &self.inputs
+    }
+
+    /// Get the target of each sample.
+    pub fn targets(&self) -> &[f64] {
+        &self.targets
+    }
+
+    /// Get the number of input variables.
+    pub fn variables(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Get the number of samples.
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Check if there are no samples.
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Evaluate a tree on every sample.
+    pub fn outputs<T>(&self, tree: &BoxTree<T>) -> Vec<f64>
+        where T: Tree<Environment = Vec<f64>, Action = f64>
+    {
+        self.inputs.iter().map(|sample| tree.evaluate(sample)).collect()
+    }
+
+    /// Create a dataset of the samples at `indices`, in that order.
+    pub fn subset(&self, indices: &[usize]) -> Dataset {
+        Dataset {
+            names: self.names.clone(),
+            inputs: indices.iter().map(|&i| self.inputs[i].clone()).collect(),
+            targets: indices.iter().map(|&i| self.targets[i]).collect(),
+        }
+    }
+
+    /// Randomly split the samples into training, validation and test sets, holding out the
+    /// given fractions for validation and test. Use a seeded `rng` for repeatable splits.
+    pub fn split<R: Rng>(&self, validation: f64, test: f64, rng: &mut R) -> Split {
+        assert!(validation >= 0.0 && test >= 0.0 && validation + test <= 1.0,
+                "split fractions must be non-negative and sum to at most 1");
+        let indices = self.shuffled_indices(rng);
+        let test_len = (test * self.len() as f64).round() as usize;
+        let validation_len = ((validation * self.len() as f64).round() as usize)
+            .min(self.len() - test_len);
+        let (test_indices, rest) = indices.split_at(test_len);
+        let (validation_indices, train_indices) = rest.split_at(validation_len);
+        Split {
+            train: self.subset(train_indices),
+            validation: self.subset(validation_indices),
+            test: self.subset(test_indices),
+        }
+    }
+
+    /// Randomly partition the samples into `k` folds of near-equal size. Each returned
+    /// `Fold` validates on one of them and trains on the rest.
+    ///
+    /// Hold out a test set with `Dataset::split` first. Use a seeded `rng` for repeatable
+    /// folds.
+    pub fn k_fold<R: Rng>(&self, k: usize, rng: &mut R) -> Vec<Fold> {
+        assert!(k >= 2 && k <= self.len(), "need 2 <= k <= number of samples");
+        let indices = self.shuffled_indices(rng);
+        (0..k)
+            .map(|fold| {
+                let start = fold * self.len() / k;
+                let end = (fold + 1) * self.len() / k;
+                let train: Vec<usize> =
+                    indices[..start].iter().chain(&indices[end..]).cloned().collect();
+                Fold {
+                    train: self.subset(&train),
+                    validation: self.subset(&indices[start..end]),
+                }
+            })
+            .collect()
+    }
+
+    fn shuffled_indices<R: Rng>(&self, rng: &mut R) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.len()).collect();
+        rng.shuffle(&mut indices);
+        indices
+    }
+}
+
+/// A dataset split into training, validation and test sets. See `Dataset::split`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Split {
+    /// Samples to evolve against.
+    pub train: Dataset,
+    /// Samples to choose between models with, or to stop evolution when they stop
+    /// improving.
+    pub validation: Dataset,
+    /// Samples to report the final model's error on.
+    pub test: Dataset,
+}
+
+/// One fold of k-fold cross-validation. See `Dataset::k_fold`.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Fold {
+    /// Samples to evolve against.
+    pub train: Dataset,
+    /// Held-out samples to measure the evolved model on.
+    pub validation: Dataset,
+}
+
+/// Configures reading of datasets from CSV.
+///
+/// Every field must be numeric, except in an optional header row, which is detected by
+/// having a non-numeric field. Fields may be quoted with `"`; blank lines and lines
+/// starting with `#` are skipped.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Csv {
+    delimiter: char,
+    target: Option<String>,
+}
+
+impl Csv {
+    /// Read comma-separated values, using the last column as the target.
+    pub fn new() -> Csv {
+        Csv {
+            delimiter: ',',
+            target: None,
+        }
+    }
+
+    /// Set the field delimiter. Defaults to `,`.
+    pub fn delimiter(mut self, delimiter: char) -> Csv {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Use the column with this header name as the target, rather than the last.
+    pub fn target(mut self, target: &str) -> Csv {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Read a dataset from a CSV file.
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<Dataset, DatasetError> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        self.parse(&text)
+    }
+
+    /// Read a dataset from CSV text.
+    pub fn parse(&self, text: &str) -> Result<Dataset, DatasetError> {
+        let mut header: Option<Vec<String>> = None;
+        let mut rows: Vec<Vec<f64>> = vec![];
+        let mut columns = None;
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = self.split_fields(line, number)?;
+            if *columns.get_or_insert(fields.len()) != fields.len() {
+                return Err(DatasetError::Ragged(number));
+            }
+            let values: Result<Vec<f64>, usize> = fields.iter()
+                .enumerate()
+                .map(|(column, field)| field.trim().parse().map_err(|_| column + 1))
+                .collect();
+            match values {
+                Ok(values) => rows.push(values),
+                Err(_) if header.is_none() && rows.is_empty() => {
+                    header = Some(fields.iter().map(|field| field.trim().to_string()).collect())
+                }
+                Err(column) => return Err(DatasetError::NotNumeric(number, column)),
+            }
+        }
+
+        let columns = match columns {
+            Some(columns) if columns >= 2 && !rows.is_empty() => columns,
+            _ => return Err(DatasetError::Empty),
+        };
+        let names: Vec<String> =
+            header.unwrap_or_else(|| (0..columns).map(|i| format!("x{}", i)).collect());
+        let target = match self.target {
+            Some(ref target) => {
+                names.iter()
+                    .position(|name| name == target)
+                    .ok_or_else(|| DatasetError::UnknownTarget(target.clone()))?
+            }
+            None => columns - 1,
+        };
+
+        let targets = rows.iter().map(|row| row[target]).collect();
+        let inputs = rows.into_iter()
+            .map(|mut row| {
+                row.remove(target);
+                row
+            })
+            .collect();
+        let mut names = names;
+        names.remove(target);
+        Ok(Dataset::new(inputs, targets).with_names(names))
+    }
+
+    fn split_fields(&self, line: &str, number: usize) -> Result<Vec<String>, DatasetError> {
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = !quoted,
+                c if c == self.delimiter && !quoted => {
+                    fields.push(field);
+                    field = String::new();
+                }
+                c => field.push(c),
+            }
+        }
+        if quoted {
+            return Err(DatasetError::UnclosedQuote(number));
+        }
+        fields.push(field);
+        Ok(fields)
+    }
+}
+
+impl Default for Csv {
+    fn default() -> Csv {
+        Csv::new()
+    }
+}
+
+/// An error reading a `Dataset`.
+#[derive(Debug)]
+pub enum DatasetError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// There were no samples, or no columns besides the target.
+    Empty,
+    /// The line with this number has a different number of fields to the first.
+    Ragged(usize),
+    /// The line with this number has an unclosed quote.
+    UnclosedQuote(usize),
+    /// The field at this line and column number is not numeric.
+    NotNumeric(usize, usize),
+    /// There is no column with the target's name.
+    UnknownTarget(String),
+}
+
+impl From<io::Error> for DatasetError {
+    fn from(err: io::Error) -> DatasetError {
+        DatasetError::Io(err)
+    }
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DatasetError::Io(ref err) => write!(f, "could not read dataset: {}", err),
+            DatasetError::Empty => write!(f, "dataset has no samples or no input columns"),
+            DatasetError::Ragged(line) => write!(f, "line {}: wrong number of fields", line),
+            DatasetError::UnclosedQuote(line) => write!(f, "line {}: unclosed quote", line),
+            DatasetError::NotNumeric(line, column) => {
+                write!(f, "line {}, column {}: field is not numeric", line, column)
+            }
+            DatasetError::UnknownTarget(ref target) => {
+                write!(f, "no column named {:?} for the target", target)
+            }
+        }
+    }
+}
+
+impl Error for DatasetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            DatasetError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn dataset() -> Dataset {
+        let inputs = (0..10).map(|i| vec![i as f64 * 0.5, -(i as f64) / 3.0]).collect();
+        Dataset::from_function(inputs, |x| x[0] * x[1] + 1e-3)
+    }
+
+    fn to_csv(dataset: &Dataset, delimiter: char) -> String {
+        let mut text = "# written by a test\n".to_string();
+        for name in dataset.names() {
+            text += &format!("\"{}\"{}", name, delimiter);
+        }
+        text += "y\n";
+        for (inputs, target) in dataset.inputs().iter().zip(dataset.targets()) {
+            for input in inputs {
+                text += &format!("{}{}", input, delimiter);
+            }
+            text += &format!("{}\n\n", target);
+        }
+        text
+    }
+
+    #[test]
+    fn csv_round_trips() {
+        let dataset = dataset();
+        for &delimiter in &[',', ';', '\t'] {
+            let parsed = Csv::new().delimiter(delimiter).parse(&to_csv(&dataset, delimiter));
+            let parsed = parsed.unwrap();
+            assert_eq!(parsed.names(), ["x0", "x1"]);
+            assert_eq!(parsed, dataset);
+        }
+
+        let headerless = Csv::new().parse("1,2,3\n4,5,6\n").unwrap();
+        assert_eq!(headerless, Dataset::new(vec![vec![1.0, 2.0], vec![4.0, 5.0]], vec![3.0, 6.0]));
+    }
+
+    #[test]
+    fn csv_target_is_chosen_by_name() {
+        let parsed = Csv::new().target("y").parse("y,\"a,b\"\n1,2\n3,4\n").unwrap();
+        assert_eq!(parsed.names(), ["a,b"]);
+        assert_eq!(parsed.inputs(), [vec![2.0], vec![4.0]]);
+        assert_eq!(parsed.targets(), [1.0, 3.0]);
+    }
+
+    #[test]
+    fn csv_errors() {
+        let csv = Csv::new();
+        let error = |text: &str| csv.parse(text).unwrap_err().to_string();
+        assert_eq!(error("# nothing\n"), DatasetError::Empty.to_string());
+        assert_eq!(error("1\n2\n"), DatasetError::Empty.to_string());
+        assert_eq!(error("1,2\n3,4,5\n"), DatasetError::Ragged(2).to_string());
+        assert_eq!(error("1,\"2\n"), DatasetError::UnclosedQuote(1).to_string());
+        assert_eq!(error("a,b\n1,2\n3,c\n"), DatasetError::NotNumeric(3, 2).to_string());
+        assert_eq!(Csv::new().target("z").parse("a,b\n1,2\n").unwrap_err().to_string(),
+                   DatasetError::UnknownTarget("z".to_string()).to_string());
+        match Csv::new().load("/nonexistent/dataset.csv") {
+            Err(DatasetError::Io(_)) => {}
+            other => panic!("expected an IO error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn splits_partition_the_samples() {
+        let dataset = dataset();
+        let mut rng = StdRng::from_seed(&[48usize][..]);
+        let split = dataset.split(0.2, 0.3, &mut rng);
+        assert_eq!((split.train.len(), split.validation.len(), split.test.len()), (5, 2, 3));
+        let mut targets: Vec<f64> = split.train
+            .targets()
+            .iter()
+            .chain(split.validation.targets())
+            .chain(split.test.targets())
+            .cloned()
+            .collect();
+        targets.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        assert_eq!(targets, dataset.targets());
+
+        let mut rng = StdRng::from_seed(&[48usize][..]);
+        assert_eq!(dataset.split(0.2, 0.3, &mut rng), split);
+    }
+
+    #[test]
+    fn folds_validate_on_every_sample_once() {
+        let dataset = dataset();
+        let mut rng = StdRng::from_seed(&[48usize][..]);
+        let folds = dataset.k_fold(3, &mut rng);
+        assert_eq!(folds.iter().map(|fold| fold.validation.len()).collect::<Vec<_>>(),
+                   vec![3, 3, 4]);
+        let mut validated: Vec<f64> =
+            folds.iter().flat_map(|fold| fold.validation.targets().to_vec()).collect();
+        validated.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        assert_eq!(validated, dataset.targets());
+        assert!(folds.iter()
+            .all(|fold| fold.train.len() + fold.validation.len() == dataset.len()));
+    }
+}
@@ -0,0 +1,543 @@
+use gp::*;
+use gp::tree::*;
+use rand::Rng;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A function which can appear in an `Equation`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub enum Function {
+    /// `a + b`.
+    Add,
+    /// `a - b`.
+    Sub,
+    /// `a * b`.
+    Mul,
+    /// `a / b`, protected by `protected_div`.
+    Div,
+    /// `sin(a)`.
+    Sin,
+    /// `cos(a)`.
+    Cos,
+    /// `exp(a)`.
+    Exp,
+    /// `log(a)`, protected by `protected_log`.
+    Log,
+    /// `sqrt(a)`, protected by `protected_sqrt`.
+    Sqrt,
+}
+
+impl Function {
+    /// Every function, in the order used for branch weights.
+    pub const ALL: [Function; 9] = [Function::Add,
+                                    Function::Sub,
+                                    Function::Mul,
+                                    Function::Div,
+                                    Function::Sin,
+                                    Function::Cos,
+                                    Function::Exp,
+                                    Function::Log,
+                                    Function::Sqrt];
+
+    /// Number of arguments the function takes.
+    pub fn arity(self) -> usize {
+        match self {
+            Function::Add | Function::Sub | Function::Mul | Function::Div => 2,
+            _ => 1,
+        }
+    }
+
+    /// Apply the function to its arguments.
+    pub fn apply(self, args: &[f64]) -> f64 {
+        match self {
+            Function::Add => args[0] + args[1],
+            Function::Sub => args[0] - args[1],
+            Function::Mul => args[0] * args[1],
+            Function::Div => protected_div(args[0], args[1]),
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Exp => args[0].exp(),
+            Function::Log => protected_log(args[0]),
+            Function::Sqrt => protected_sqrt(args[0]),
+        }
+    }
+
+    /// Lowercase name of the function, such as `add` or `sqrt`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Add => "add",
+            Function::Sub => "sub",
+            Function::Mul => "mul",
+            Function::Div => "div",
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Exp => "exp",
+            Function::Log => "log",
+            Function::Sqrt => "sqrt",
+        }
+    }
+
+    /// Find the function with a name as given by `Function::name`.
+    pub fn from_name(name: &str) -> Option<Function> {
+        Function::ALL.iter().cloned().find(|function| function.name() == name)
+    }
+
+    fn index(self) -> usize {
+        Function::ALL.iter().position(|&function| function == self).unwrap()
+    }
+}
+
+/// Division which gives 1 rather than an infinite or NaN result.
+pub fn protected_div(numerator: f64, denominator: f64) -> f64 {
+    let div = numerator / denominator;
+    if div.is_finite() { div } else { 1.0 }
+}
+
+/// Natural logarithm of the magnitude, giving 0 for 0.
+pub fn protected_log(value: f64) -> f64 {
+    if value == 0.0 { 0.0 } else { value.abs().ln() }
+}
+
+/// Square root of the magnitude.
+pub fn protected_sqrt(value: f64) -> f64 {
+    value.abs().sqrt()
+}
+
+/// The functions, variables and constants `Equation`s are generated from, with the weight
+/// of each.
+///
+/// Pass `branch_weights` and `leaf_weights` to `TreeGen::with_weights` or
+/// `Initialisation::with_weights`. A weight of 0 leaves a primitive out.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PrimitiveSet {
+    function_weights: Vec<u32>,
+    variable_weights: Vec<u32>,
+    constant_weight: u32,
+}
+
+impl PrimitiveSet {
+    /// Add, subtract, multiply and divide, with `variables` input variables and constants.
+    pub fn arithmetic(variables: usize) -> PrimitiveSet {
+        PrimitiveSet {
+            function_weights: vec![1, 1, 1, 1, 0, 0, 0, 0, 0],
+            variable_weights: vec![1; variables],
+            constant_weight: 1,
+        }
+    }
+
+    /// Every function, with `variables` input variables and constants.
+    pub fn all(variables: usize) -> PrimitiveSet {
+        PrimitiveSet {
+            function_weights: vec![1; Function::ALL.len()],
+            variable_weights: vec![1; variables],
+            constant_weight: 1,
+        }
+    }
+
+    /// Set the weight of a function.
+    pub fn function(mut self, function: Function, weight: u32) -> PrimitiveSet {
+        self.function_weights[function.index()] = weight;
+        self
+    }
+
+    /// Set the weight of an input variable.
+    pub fn variable(mut self, index: usize, weight: u32) -> PrimitiveSet {
+        self.variable_weights[index] = weight;
+        self
+    }
+
+    /// Set the weight of ephemeral random constants.
+    pub fn constant(mut self, weight: u32) -> PrimitiveSet {
+        self.constant_weight = weight;
+        self
+    }
+
+    /// Get the number of input variables.
+    pub fn variables(&self) -> usize {
+        self.variable_weights.len()
+    }
+
+    /// Get the weight of each function, in the order of `Function::ALL`.
+    pub fn branch_weights(&self) -> Vec<u32> {
+        self.function_weights.clone()
+    }
+
+    /// Get the weight of each variable, followed by the weight of constants.
+    pub fn leaf_weights(&self) -> Vec<u32> {
+        let mut weights = self.variable_weights.clone();
+        weights.push(self.constant_weight);
+        weights
+    }
+}
+
+/// A ready-made numeric expression tree for symbolic regression.
+///
+/// Environments hold the input variables, `x0` upwards. Generate equations with the weights
+/// of a `PrimitiveSet`; without weights every function is used, with one variable.
+/// Constants are drawn uniformly from [-1, 1) and can be tuned by `ConstantOptimisation`.
+#[derive(Clone, Debug)]
+pub enum Equation {
+    /// A function applied to its arguments.
+    Apply(Function, Vec<BoxTree<Equation>>),
+    /// An input variable.
+    Variable(usize),
+    /// An ephemeral random constant.
+    Constant(f64),
+}
+
+impl Tree for Equation {
+    type Environment = Vec<f64>;
+    type Action = f64;
+
+    fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Self> {
+        let function = Function::ALL[tg.choose_branch(Function::ALL.len())];
+        let args = (0..function.arity()).map(|_| Self::child(tg, current_depth + 1)).collect();
+        Equation::Apply(function, args).into()
+    }
+
+    /// Leaf weights are one per variable followed by one for constants, as given by
+    /// `PrimitiveSet::leaf_weights`, so a primitive set without variables only generates
+    /// constants.
+    fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Self> {
+        // Without weights there is one variable.
+        let leaves = match tg.leaf_weights().len() {
+            0 => 2,
+            weights => weights,
+        };
+        let leaf = tg.choose_leaf(leaves);
+        if leaf + 1 == leaves {
+            Equation::Constant(tg.gen_range(-1.0, 1.0)).into()
+        } else {
+            Equation::Variable(leaf).into()
+        }
+    }
+
+    fn count_children(&mut self) -> usize {
+        match *self {
+            Equation::Apply(_, ref args) => args.len(),
+            _ => 0,
+        }
+    }
+
+    fn children(&self) -> Vec<&BoxTree<Self>> {
+        match *self {
+            Equation::Apply(_, ref args) => args.iter().collect(),
+            _ => vec![],
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut BoxTree<Self>> {
+        match *self {
+            Equation::Apply(_, ref mut args) => args.iter_mut().collect(),
+            _ => vec![],
+        }
+    }
+
+    fn evaluate(&self, env: &Vec<f64>) -> f64 {
+        match *self {
+            Equation::Apply(function, ref args) => {
+                let values: Vec<f64> = args.iter().map(|arg| arg.evaluate(env)).collect();
+                function.apply(&values)
+            }
+            Equation::Variable(index) => env[index],
+            Equation::Constant(value) => value,
+        }
+    }
+}
+
+/// Constants are compared by their bits, as they are hashed, so that every equation equals
+/// itself: a NaN constant equals itself, and `0.0` differs from `-0.0`.
+impl PartialEq for Equation {
+    fn eq(&self, other: &Equation) -> bool {
+        match (self, other) {
+            (&Equation::Apply(function, ref args), &Equation::Apply(other, ref other_args)) => {
+                function == other && args == other_args
+            }
+            (&Equation::Variable(index), &Equation::Variable(other_index)) => index == other_index,
+            (&Equation::Constant(value), &Equation::Constant(other_value)) => {
+                value.to_bits() == other_value.to_bits()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Equation {}
+
+impl Hash for Equation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            Equation::Apply(function, ref args) => {
+                0.hash(state);
+                function.hash(state);
+                args.hash(state);
+            }
+            Equation::Variable(index) => {
+                1.hash(state);
+                index.hash(state);
+            }
+            Equation::Constant(value) => {
+                2.hash(state);
+                value.to_bits().hash(state);
+            }
+        }
+    }
+}
+
+/// Opcode for a node of an `Equation`. See `Compile`.
+#[derive(Clone, Copy, Debug)]
+pub enum EquationOp {
+    /// Corresponds to `Equation::Apply`.
+    Apply(Function),
+    /// Corresponds to `Equation::Variable`.
+    Variable(usize),
+    /// Corresponds to `Equation::Constant`.
+    Constant(f64),
+}
+
+/// Constants are compared by their bits, as for `Equation`.
+impl PartialEq for EquationOp {
+    fn eq(&self, other: &EquationOp) -> bool {
+        match (*self, *other) {
+            (EquationOp::Apply(function), EquationOp::Apply(other_function)) => {
+                function == other_function
+            }
+            (EquationOp::Variable(index), EquationOp::Variable(other)) => index == other,
+            (EquationOp::Constant(value), EquationOp::Constant(other_value)) => {
+                value.to_bits() == other_value.to_bits()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for EquationOp {}
+
+impl Hash for EquationOp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            EquationOp::Apply(function) => {
+                0.hash(state);
+                function.hash(state);
+            }
+            EquationOp::Variable(index) => {
+                1.hash(state);
+                index.hash(state);
+            }
+            EquationOp::Constant(value) => {
+                2.hash(state);
+                value.to_bits().hash(state);
+            }
+        }
+    }
+}
+
+impl Compile for Equation {
+    type Op = EquationOp;
+
+    fn op(&self) -> EquationOp {
+        match *self {
+            Equation::Apply(function, _) => EquationOp::Apply(function),
+            Equation::Variable(index) => EquationOp::Variable(index),
+            Equation::Constant(value) => EquationOp::Constant(value),
+        }
+    }
+
+    fn execute(op: &EquationOp, env: &Vec<f64>, args: &[f64]) -> f64 {
+        match *op {
+            EquationOp::Apply(function) => function.apply(args),
+            EquationOp::Variable(index) => env[index],
+            EquationOp::Constant(value) => value,
+        }
+    }
+
+    fn execute_batch(op: &EquationOp, envs: &[Vec<f64>], args: &[Vec<f64>], out: &mut Vec<f64>) {
+        match *op {
+            EquationOp::Apply(function) if function.arity() == 2 => {
+                out.extend(args[0].iter().zip(&args[1]).map(|(&a, &b)| function.apply(&[a, b])))
+            }
+            EquationOp::Apply(function) => {
+                out.extend(args[0].iter().map(|&a| function.apply(&[a])))
+            }
+            EquationOp::Variable(index) => out.extend(envs.iter().map(|env| env[index])),
+            EquationOp::Constant(value) => out.extend(envs.iter().map(|_| value)),
+        }
+    }
+}
+
+impl Flatten for Equation {
+    fn unflatten(op: EquationOp, children: Vec<BoxTree<Equation>>) -> BoxTree<Equation> {
+        match op {
+            EquationOp::Apply(function) => Equation::Apply(function, children),
+            EquationOp::Variable(index) => Equation::Variable(index),
+            EquationOp::Constant(value) => Equation::Constant(value),
+        }
+        .into()
+    }
+}
+
+impl Constants for Equation {
+    fn constant(&self) -> Option<f64> {
+        match *self {
+            Equation::Constant(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn set_constant(&mut self, value: f64) {
+        *self = Equation::Constant(value);
+    }
+}
+
+impl fmt::Display for Equation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Equation::Apply(function, ref args) => {
+                let symbol = match function {
+                    Function::Add => "+",
+                    Function::Sub => "-",
+                    Function::Mul => "*",
+                    Function::Div => "/",
+                    _ => function.name(),
+                };
+                match function.arity() {
+                    2 => write!(f, "({} {} {})", args[0], symbol, args[1]),
+                    _ => write!(f, "{}({})", symbol, args[0]),
+                }
+            }
+            Equation::Variable(index) => write!(f, "x{}", index),
+            Equation::Constant(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use std::collections::HashSet;
+
+    fn generate(primitives: &PrimitiveSet, rng: &mut StdRng) -> BoxTree<Equation> {
+        let mut tg = TreeGen::full(rng, 1, 3)
+            .with_weights(primitives.branch_weights(), primitives.leaf_weights());
+        Equation::tree(&mut tg)
+    }
+
+    #[test]
+    fn constants_are_compared_by_their_bits() {
+        assert!(Equation::Constant(0.0) != Equation::Constant(-0.0));
+        assert!(Equation::Constant(f64::NAN) == Equation::Constant(f64::NAN));
+        assert!(EquationOp::Constant(0.0) != EquationOp::Constant(-0.0));
+        assert!(EquationOp::Constant(f64::NAN) == EquationOp::Constant(f64::NAN));
+
+        let zero: BoxTree<Equation> = Equation::Constant(0.0).into();
+        let negative_zero: BoxTree<Equation> = Equation::Constant(-0.0).into();
+        assert!(zero.structural_hash() != negative_zero.structural_hash());
+    }
+
+    #[test]
+    fn constants_are_usable_as_keys() {
+        let nan = || -> BoxTree<Equation> {
+            let args = vec![Equation::Variable(0).into(), Equation::Constant(f64::NAN).into()];
+            Equation::Apply(Function::Add, args).into()
+        };
+        let mut equations = HashSet::new();
+        assert!(equations.insert(nan()));
+        assert!(!equations.insert(nan()));
+        assert!(equations.contains(&nan()));
+        assert!(equations.insert(Equation::Constant(0.0).into()));
+        assert!(equations.insert(Equation::Constant(-0.0).into()));
+        assert_eq!(equations.len(), 3);
+
+        let ops: HashSet<EquationOp> =
+            vec![EquationOp::Constant(f64::NAN), EquationOp::Constant(f64::NAN),
+                 EquationOp::Constant(0.0), EquationOp::Constant(-0.0)]
+                .into_iter()
+                .collect();
+        assert_eq!(ops.len(), 3);
+    }
+
+    #[test]
+    fn leaves_follow_the_primitive_set() {
+        let mut rng = StdRng::from_seed(&[47usize][..]);
+        for _ in 0..50 {
+            let equation = generate(&PrimitiveSet::arithmetic(0), &mut rng);
+            let variable = |node: NodeRef<Equation>| matches!(**node.tree, Equation::Variable(_));
+            assert!(!equation.pre_order().any(variable));
+        }
+
+        let primitives = PrimitiveSet::arithmetic(3).variable(1, 0);
+        let mut variables = HashSet::new();
+        for _ in 0..50 {
+            for node in generate(&primitives, &mut rng).pre_order() {
+                match **node.tree {
+                    Equation::Apply(function, _) => assert!(function.arity() == 2),
+                    Equation::Variable(index) => {
+                        variables.insert(index);
+                    }
+                    Equation::Constant(value) => assert!((-1.0..1.0).contains(&value)),
+                }
+            }
+        }
+        assert_eq!(variables, vec![0, 2].into_iter().collect());
+    }
+
+    #[test]
+    fn functions_are_found_by_name() {
+        for &function in Function::ALL.iter() {
+            assert_eq!(Function::from_name(function.name()), Some(function));
+        }
+        assert_eq!(Function::from_name("tan"), None);
+    }
+
+    #[test]
+    fn equations_evaluate_and_display() {
+        let sqrt = Equation::Apply(Function::Sqrt, vec![Equation::Variable(1).into()]);
+        let equation: BoxTree<Equation> =
+            Equation::Apply(Function::Div, vec![sqrt.into(), Equation::Constant(0.0).into()])
+                .into();
+        assert_eq!(equation.to_string(), "(sqrt(x1) / 0)");
+        assert_eq!(equation.evaluate(&vec![5.0, -4.0]), 1.0);
+        assert_eq!(protected_log(0.0), 0.0);
+        assert_eq!(protected_sqrt(-4.0), 2.0);
+    }
+
+    #[test]
+    fn compiled_flat_and_batch_evaluation_match_trees() {
+        let primitives = PrimitiveSet::all(2);
+        let mut rng = StdRng::from_seed(&[47usize][..]);
+        let envs: Vec<Vec<f64>> = (0..20).map(|i| vec![i as f64 / 4.0 - 2.5, 0.5]).collect();
+        for _ in 0..100 {
+            let tree = generate(&primitives, &mut rng);
+            let program = Program::compile(&tree);
+            let flat = FlatTree::from(&tree);
+            assert_eq!(flat.to_tree(), tree);
+            // Whole columns go through the `execute_batch` override.
+            let batch = program.evaluate_batch(&envs);
+            assert_eq!(batch.len(), envs.len());
+            for (value, env) in batch.iter().zip(&envs) {
+                let expected = tree.evaluate(env).to_bits();
+                assert_eq!(program.evaluate(env).to_bits(), expected);
+                assert_eq!(flat.evaluate(env).to_bits(), expected);
+                assert_eq!(value.to_bits(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn compile_emits_postfix_opcodes() {
+        let product = Equation::Apply(Function::Mul, vec![Equation::Variable(0).into(),
+                                                          Equation::Constant(2.0).into()]);
+        let tree: BoxTree<Equation> =
+            Equation::Apply(Function::Sin, vec![product.into()]).into();
+        let ops: Vec<EquationOp> =
+            Program::compile(&tree).instructions().iter().map(|i| i.op).collect();
+        assert_eq!(ops,
+                   vec![EquationOp::Variable(0),
+                        EquationOp::Constant(2.0),
+                        EquationOp::Apply(Function::Mul),
+                        EquationOp::Apply(Function::Sin)]);
+        assert_eq!(Program::compile(&tree).evaluate_batch(&[vec![0.0], vec![-1.5]]),
+                   vec![0.0, (-3.0f64).sin()]);
+    }
+}
@@ -0,0 +1,68 @@
+/// Mean squared error between outputs and targets.
+pub fn mse(outputs: &[f64], targets: &[f64]) -> f64 {
+    sum_of_squared_errors(outputs, targets) / outputs.len() as f64
+}
+
+/// Root mean squared error between outputs and targets.
+pub fn rmse(outputs: &[f64], targets: &[f64]) -> f64 {
+    mse(outputs, targets).sqrt()
+}
+
+/// Mean absolute error between outputs and targets.
+pub fn mae(outputs: &[f64], targets: &[f64]) -> f64 {
+    assert_eq!(outputs.len(), targets.len(), "need one target per output");
+    outputs.iter().zip(targets).map(|(output, target)| (output - target).abs()).sum::<f64>() /
+    outputs.len() as f64
+}
+
+/// Coefficient of determination (R²): 1 for a perfect fit, 0 for no better than the mean
+/// target, and negative for worse.
+///
+/// If every target is the same, gives 1 for a perfect fit and 0 otherwise.
+pub fn r_squared(outputs: &[f64], targets: &[f64]) -> f64 {
+    let residual = sum_of_squared_errors(outputs, targets);
+    let mean = targets.iter().sum::<f64>() / targets.len() as f64;
+    let total: f64 = targets.iter().map(|target| (target - mean).powi(2)).sum();
+    if total == 0.0 {
+        return if residual == 0.0 { 1.0 } else { 0.0 };
+    }
+    1.0 - residual / total
+}
+
+/// Sum of squared errors between outputs and targets.
+pub fn sum_of_squared_errors(outputs: &[f64], targets: &[f64]) -> f64 {
+    assert_eq!(outputs.len(), targets.len(), "need one target per output");
+    outputs.iter().zip(targets).map(|(output, target)| (output - target).powi(2)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_of_known_outputs() {
+        let targets = [1.0, 2.0, 3.0, 4.0];
+        let outputs = [1.0, 3.0, 1.0, 4.0];
+        assert_eq!(sum_of_squared_errors(&outputs, &targets), 5.0);
+        assert_eq!(mse(&outputs, &targets), 1.25);
+        assert_eq!(rmse(&outputs, &targets), 1.25f64.sqrt());
+        assert_eq!(mae(&outputs, &targets), 0.75);
+        assert_eq!(mse(&targets, &targets), 0.0);
+    }
+
+    #[test]
+    fn r_squared_compares_against_the_mean() {
+        let targets = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(r_squared(&targets, &targets), 1.0);
+        assert_eq!(r_squared(&[2.5; 4], &targets), 0.0);
+        assert_eq!(r_squared(&[4.0, 3.0, 2.0, 1.0], &targets), -3.0);
+        assert_eq!(r_squared(&[2.0; 3], &[2.0; 3]), 1.0);
+        assert_eq!(r_squared(&[1.0, 2.0, 3.0], &[2.0; 3]), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "need one target per output")]
+    fn lengths_must_match() {
+        mse(&[1.0, 2.0], &[1.0]);
+    }
+}
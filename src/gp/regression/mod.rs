@@ -0,0 +1,14 @@
+mod dataset;
+mod equation;
+mod metrics;
+mod scaling;
+mod simplify;
+
+/// Numeric datasets, read from CSV and split for validation.
+pub use self::dataset::*;
+/// A ready-made numeric expression tree and its primitives.
+pub use self::equation::*;
+/// Error metrics between outputs and targets.
+pub use self::metrics::*;
+/// Linear scaling of outputs onto targets.
+pub use self::scaling::*;
@@ -0,0 +1,169 @@
+use gp::regression::mse;
+use std::fmt;
+
+/// The least-squares slope and intercept mapping a tree's outputs onto targets (Keijzer's
+/// linear scaling).
+///
+/// Measuring error after scaling means evolution only has to find the shape of a function,
+/// not also its scale and offset.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct LinearScaling {
+    /// Factor outputs are multiplied by.
+    pub slope: f64,
+    /// Value added to outputs after multiplying by the slope.
+    pub intercept: f64,
+}
+
+impl LinearScaling {
+    /// Fit the slope and intercept minimising squared error between scaled outputs and
+    /// targets.
+    ///
+    /// If the outputs are constant the slope is 0, so every output scales to the mean
+    /// target. If any output is not finite nothing is fitted and outputs are left unscaled,
+    /// so that `LinearScaling::mse` gives an infinite error.
+    pub fn fit(outputs: &[f64], targets: &[f64]) -> LinearScaling {
+        assert_eq!(outputs.len(), targets.len(), "need one target per output");
+        if outputs.is_empty() || outputs.iter().any(|output| !output.is_finite()) {
+            return LinearScaling {
+                slope: 1.0,
+                intercept: 0.0,
+            };
+        }
+        let count = outputs.len() as f64;
+        let output_mean = outputs.iter().sum::<f64>() / count;
+        let target_mean = targets.iter().sum::<f64>() / count;
+        let mut covariance = 0.0;
+        let mut variance = 0.0;
+        for (output, target) in outputs.iter().zip(targets) {
+            covariance += (output - output_mean) * (target - target_mean);
+            variance += (output - output_mean) * (output - output_mean);
+        }
+        let slope = covariance / variance;
+        if variance == 0.0 || !slope.is_finite() {
+            return LinearScaling {
+                slope: 0.0,
+                intercept: target_mean,
+            };
+        }
+        LinearScaling {
+            slope,
+            intercept: target_mean - slope * output_mean,
+        }
+    }
+
+    /// Scale an output.
+    pub fn apply(&self, output: f64) -> f64 {
+        self.intercept + self.slope * output
+    }
+
+    /// Mean squared error between scaled outputs and targets. The error is infinite if any
+    /// output is not finite, as `benchmarks::symbolic` measures error.
+    pub fn mse(&self, outputs: &[f64], targets: &[f64]) -> f64 {
+        let scaled: Vec<f64> = outputs.iter().map(|&output| self.apply(output)).collect();
+        let error = mse(&scaled, targets);
+        if error.is_finite() { error } else { f64::INFINITY }
+    }
+
+    /// Fit the scaling and return it with the mean squared error after scaling.
+    pub fn fit_mse(outputs: &[f64], targets: &[f64]) -> (LinearScaling, f64) {
+        let scaling = Self::fit(outputs, targets);
+        (scaling, scaling.mse(outputs, targets))
+    }
+
+    /// Wrap an expression, such as a tree, to display it with this scaling applied.
+    pub fn display<'a, D>(&self, expression: &'a D) -> Scaled<'a, D>
+        where D: fmt::Display
+    {
+        Scaled {
+            scaling: *self,
+            expression,
+        }
+    }
+}
+
+/// Displays an expression with a `LinearScaling` applied, as `slope * (expression) +
+/// intercept`. See `LinearScaling::display`.
+#[derive(Clone, Copy, Debug)]
+pub struct Scaled<'a, D>
+    where D: 'a
+{
+    scaling: LinearScaling,
+    expression: &'a D,
+}
+
+impl<'a, D> fmt::Display for Scaled<'a, D>
+    where D: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let LinearScaling { slope, intercept } = self.scaling;
+        if slope == 0.0 {
+            return write!(f, "{}", intercept);
+        }
+        write!(f, "{} * ({})", slope, self.expression)?;
+        if intercept < 0.0 {
+            write!(f, " - {}", -intercept)
+        } else if intercept > 0.0 {
+            write!(f, " + {}", intercept)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_recovers_slope_and_intercept() {
+        let outputs = [1.0, 2.0, 4.0, 8.0];
+        let targets: Vec<f64> = outputs.iter().map(|x| 3.0 * x - 2.0).collect();
+        let (scaling, error) = LinearScaling::fit_mse(&outputs, &targets);
+        assert_eq!(scaling,
+                   LinearScaling {
+                       slope: 3.0,
+                       intercept: -2.0,
+                   });
+        assert_eq!(error, 0.0);
+        assert_eq!(scaling.apply(10.0), 28.0);
+    }
+
+    #[test]
+    fn constant_outputs_scale_to_the_mean() {
+        let (scaling, error) = LinearScaling::fit_mse(&[5.0, 5.0, 5.0], &[1.0, 2.0, 6.0]);
+        assert_eq!(scaling,
+                   LinearScaling {
+                       slope: 0.0,
+                       intercept: 3.0,
+                   });
+        assert_eq!(error, 14.0 / 3.0);
+    }
+
+    #[test]
+    fn non_finite_outputs_give_infinite_error() {
+        let targets = [1.0, 2.0, 3.0];
+        for &bad in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let outputs = [1.0, bad, 3.0];
+            let (scaling, error) = LinearScaling::fit_mse(&outputs, &targets);
+            assert_eq!(error, f64::INFINITY);
+            assert_eq!(scaling.mse(&outputs, &targets), f64::INFINITY);
+        }
+        // Outputs on new samples which are not finite are not hidden by the scaling either.
+        let constant = LinearScaling::fit(&[2.0, 2.0], &[1.0, 3.0]);
+        assert_eq!(constant.mse(&[2.0, f64::NAN], &[1.0, 3.0]), f64::INFINITY);
+    }
+
+    #[test]
+    fn display() {
+        let scaling = LinearScaling {
+            slope: 2.0,
+            intercept: -1.5,
+        };
+        assert_eq!(scaling.display(&"x0").to_string(), "2 * (x0) - 1.5");
+        let constant = LinearScaling {
+            slope: 0.0,
+            intercept: 4.0,
+        };
+        assert_eq!(constant.display(&"x0").to_string(), "4");
+    }
+}
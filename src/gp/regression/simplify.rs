@@ -0,0 +1,169 @@
+use gp::*;
+use gp::regression::{Equation, Function};
+
+impl BoxTree<Equation> {
+    /// Get an equivalent, usually smaller, equation for display.
+    ///
+    /// Folds constant subexpressions, removes identities such as `x + 0`, `x * 1` and
+    /// `x / x`, and merges constants across nested additions and multiplications.
+    /// Simplification assumes every value is finite, so the result may differ where the
+    /// original overflows.
+    pub fn simplify(&self) -> BoxTree<Equation> {
+        simplify(self).into()
+    }
+}
+
+fn simplify(equation: &Equation) -> Equation {
+    match *equation {
+        Equation::Apply(function, ref args) => {
+            simplify_apply(function, args.iter().map(|arg| simplify(arg).into()).collect())
+        }
+        ref leaf => leaf.clone(),
+    }
+}
+
+fn simplify_apply(function: Function, mut args: Vec<BoxTree<Equation>>) -> Equation {
+    let constants: Option<Vec<f64>> = args.iter().map(|arg| arg.constant()).collect();
+    if let Some(values) = constants {
+        let value = function.apply(&values);
+        if value.is_finite() {
+            return Equation::Constant(value);
+        }
+    }
+    if args.len() != 2 {
+        return Equation::Apply(function, args);
+    }
+
+    let (a, b) = (args[0].constant(), args[1].constant());
+    match function {
+        Function::Add | Function::Mul => {
+            let identity = if function == Function::Add { 0.0 } else { 1.0 };
+            if a == Some(identity) {
+                return args.remove(1).inner();
+            }
+            if b == Some(identity) {
+                return args.remove(0).inner();
+            }
+            if function == Function::Mul && (a == Some(0.0) || b == Some(0.0)) {
+                return Equation::Constant(0.0);
+            }
+            // Move a constant to the right, then merge it into a nested constant.
+            if a.is_some() {
+                args.swap(0, 1);
+            }
+            if let Some(outer) = args[1].constant() {
+                if let Some(merged) = merge_nested(function, &args[0], outer) {
+                    return merged;
+                }
+            }
+            Equation::Apply(function, args)
+        }
+        Function::Sub if b == Some(0.0) => args.remove(0).inner(),
+        Function::Sub if args[0] == args[1] => Equation::Constant(0.0),
+        Function::Div if b == Some(1.0) => args.remove(0).inner(),
+        Function::Div if args[0] == args[1] => Equation::Constant(1.0),
+        _ => Equation::Apply(function, args),
+    }
+}
+
+/// Simplify `inner <function> outer` where `inner` applies the same function to a constant,
+/// as in `(x + 1) + 2`.
+fn merge_nested(function: Function, inner: &Equation, outer: f64) -> Option<Equation> {
+    if let Equation::Apply(inner_function, ref inner_args) = *inner {
+        if inner_function != function {
+            return None;
+        }
+        for (i, arg) in inner_args.iter().enumerate() {
+            if let Some(value) = arg.constant() {
+                let merged = function.apply(&[value, outer]);
+                let rest = inner_args[1 - i].clone();
+                return Some(simplify_apply(function,
+                                           vec![rest, Equation::Constant(merged).into()]));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gp::regression::PrimitiveSet;
+    use rand::{SeedableRng, StdRng};
+
+    fn x() -> BoxTree<Equation> {
+        Equation::Variable(0).into()
+    }
+
+    fn c(value: f64) -> BoxTree<Equation> {
+        Equation::Constant(value).into()
+    }
+
+    fn apply(function: Function, args: Vec<BoxTree<Equation>>) -> BoxTree<Equation> {
+        Equation::Apply(function, args).into()
+    }
+
+    fn simplified(equation: BoxTree<Equation>) -> String {
+        equation.simplify().to_string()
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        let sum = apply(Function::Add, vec![c(1.0), c(0.5)]);
+        assert_eq!(simplified(apply(Function::Mul, vec![c(2.0), sum])), "3");
+        assert_eq!(simplified(apply(Function::Sin, vec![apply(Function::Sub, vec![x(), x()])])),
+                   "0");
+        // A non-finite result is left unfolded.
+        assert_eq!(simplified(apply(Function::Exp, vec![c(1000.0)])), "exp(1000)");
+    }
+
+    #[test]
+    fn identities_are_removed() {
+        assert_eq!(simplified(apply(Function::Add, vec![c(0.0), x()])), "x0");
+        assert_eq!(simplified(apply(Function::Mul, vec![x(), c(1.0)])), "x0");
+        assert_eq!(simplified(apply(Function::Mul, vec![c(0.0), x()])), "0");
+        assert_eq!(simplified(apply(Function::Sub, vec![x(), c(0.0)])), "x0");
+        assert_eq!(simplified(apply(Function::Div, vec![x(), c(1.0)])), "x0");
+        assert_eq!(simplified(apply(Function::Div, vec![x(), x()])), "1");
+        assert_eq!(simplified(apply(Function::Sub, vec![c(0.0), x()])), "(0 - x0)");
+    }
+
+    #[test]
+    fn nested_constants_are_merged() {
+        let nested = apply(Function::Add, vec![c(2.0), apply(Function::Add, vec![x(), c(1.0)])]);
+        assert_eq!(simplified(nested), "(x0 + 3)");
+        let nested = apply(Function::Mul, vec![apply(Function::Mul, vec![c(4.0), x()]), c(0.5)]);
+        assert_eq!(simplified(nested), "(x0 * 2)");
+        let quadruple = apply(Function::Mul, vec![c(4.0), x()]);
+        let cancelled = apply(Function::Mul, vec![quadruple, c(0.25)]);
+        assert_eq!(simplified(cancelled), "x0");
+    }
+
+    #[test]
+    fn simplified_equations_are_equivalent() {
+        let mut rng = StdRng::from_seed(&[49usize][..]);
+        let primitives = PrimitiveSet::all(1);
+        let inputs: Vec<Vec<f64>> = (0..20).map(|i| vec![i as f64 / 4.0 - 2.5]).collect();
+        for _ in 0..200 {
+            let equation = {
+                let mut tg = TreeGen::full(&mut rng, 1, 4)
+                    .with_weights(primitives.branch_weights(), primitives.leaf_weights());
+                Equation::tree(&mut tg)
+            };
+            let simplified = equation.simplify();
+            assert!(simplified.count_nodes() <= equation.count_nodes());
+            for input in &inputs {
+                let (before, after) = (equation.evaluate(input), simplified.evaluate(input));
+                if before.is_finite() && before.abs() < 1e6 {
+                    assert!((before - after).abs() <= 1e-9 * before.abs().max(1.0),
+                            "{} became {} at {:?}: {} != {}",
+                            equation,
+                            simplified,
+                            input,
+                            before,
+                            after);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use gp::tree::*;
+
+impl<T> Program<T>
+    where T: Compile,
+          T::Action: Clone
+{
+    /// Evaluate the program over a batch of environments.
+    ///
+    /// Each instruction runs once over a whole column of values (one per environment)
+    /// through `Compile::execute_batch`, rather than the whole program running once per
+    /// environment. Returns one value per environment.
+    pub fn evaluate_batch(&self, envs: &[T::Environment]) -> Vec<T::Action> {
+        let mut stack: Vec<Vec<T::Action>> = vec![];
+        // Column buffers which have been consumed, kept to avoid reallocating them.
+        let mut spare: Vec<Vec<T::Action>> = vec![];
+        for instruction in self.instructions() {
+            let args_start = stack.len() - instruction.arity;
+            let args = stack.split_off(args_start);
+            let mut out = spare.pop().unwrap_or_else(|| Vec::with_capacity(envs.len()));
+            out.clear();
+            T::execute_batch(&instruction.op, envs, &args, &mut out);
+            spare.extend(args);
+            stack.push(out);
+        }
+        stack.pop().expect("a compiled program always leaves a column on the stack")
+    }
+}
+
+impl<T> BoxTree<T>
+    where T: Compile,
+          T::Action: Clone
+{
+    /// Evaluate the tree over a batch of environments, node by node. Returns one value per
+    /// environment. See `Program::evaluate_batch`.
+    ///
+    /// This compiles the tree on every call; compile it once with `Program::compile` if the
+    /// same tree is evaluated repeatedly.
+    pub fn evaluate_batch(&self, envs: &[T::Environment]) -> Vec<T::Action> {
+        Program::compile(self).evaluate_batch(envs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    /// Integer sums, using the default `Compile::execute_batch`.
+    #[derive(Clone, Debug)]
+    enum Sum {
+        Add(BoxTree<Sum>, BoxTree<Sum>),
+        Input(usize),
+    }
+
+    impl Tree for Sum {
+        type Environment = Vec<i64>;
+        type Action = i64;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Sum> {
+            Sum::Add(Self::child(tg, current_depth + 1), Self::child(tg, current_depth + 1))
+                .into()
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Sum> {
+            Sum::Input(tg.gen_range(0, 2)).into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Sum>> {
+            match *self {
+                Sum::Add(ref a, ref b) => vec![a, b],
+                Sum::Input(_) => vec![],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Sum>> {
+            match *self {
+                Sum::Add(ref mut a, ref mut b) => vec![a, b],
+                Sum::Input(_) => vec![],
+            }
+        }
+
+        fn evaluate(&self, env: &Vec<i64>) -> i64 {
+            match *self {
+                Sum::Add(ref a, ref b) => a.evaluate(env) + b.evaluate(env),
+                Sum::Input(index) => env[index],
+            }
+        }
+    }
+
+    impl Compile for Sum {
+        type Op = Option<usize>;
+
+        fn op(&self) -> Option<usize> {
+            match *self {
+                Sum::Add(_, _) => None,
+                Sum::Input(index) => Some(index),
+            }
+        }
+
+        fn execute(op: &Option<usize>, env: &Vec<i64>, args: &[i64]) -> i64 {
+            match *op {
+                None => args[0] + args[1],
+                Some(index) => env[index],
+            }
+        }
+    }
+
+    #[test]
+    fn default_execute_batch_matches_evaluation() {
+        let mut rng = StdRng::from_seed(&[28usize][..]);
+        let envs: Vec<Vec<i64>> = (0..10).map(|i| vec![i, 10 - 3 * i]).collect();
+        for _ in 0..20 {
+            let tree = Sum::tree(&mut TreeGen::full(&mut rng, 1, 5));
+            let expected: Vec<i64> = envs.iter().map(|env| tree.evaluate(env)).collect();
+            assert_eq!(tree.evaluate_batch(&envs), expected);
+            assert_eq!(Program::compile(&tree).evaluate_batch(&envs), expected);
+        }
+    }
+
+    #[test]
+    fn single_node_batch() {
+        let tree: BoxTree<Sum> = Sum::Input(1).into();
+        assert_eq!(tree.evaluate_batch(&[vec![3, 4], vec![5, 6]]), vec![4, 6]);
+        assert_eq!(tree.evaluate_batch(&[]), vec![]);
+    }
+}
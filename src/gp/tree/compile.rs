@@ -0,0 +1,318 @@
+use gp::tree::*;
+use rand::Rng;
+use std::fmt::Debug;
+
+/// Trait for `Tree`s which can be compiled into a flat `Program`.
+///
+/// Each node is described by an opcode that holds the node's own data but not its children.
+/// Evaluating a `Program` must give the same result as `Tree::evaluate` on the original tree.
+pub trait Compile: Tree {
+    /// Opcode for a single node.
+    type Op: Clone + Debug;
+
+    /// Get the opcode for this node.
+    fn op(&self) -> Self::Op;
+
+    /// Whether `Tree::evaluate` on this node may skip some of its children, such as a
+    /// conditional whose branches act on the environment. Defaults to `false`.
+    ///
+    /// Programs evaluate every child before its parent, so they refuse to compile lazy
+    /// nodes. A conditional over children without side effects gives the same result
+    /// either way, so need not be lazy.
+    fn lazy(&self) -> bool {
+        false
+    }
+
+    /// Execute an opcode. `args` holds the values of the node's children, in the order
+    /// returned by `Tree::children`.
+    fn execute(op: &Self::Op, env: &Self::Environment, args: &[Self::Action]) -> Self::Action;
+
+    /// Execute an opcode over a batch of environments, pushing one result per environment
+    /// onto `out`. `args` holds one column per child, each with a value per environment.
+    ///
+    /// The default implementation calls `Compile::execute` for every environment. Override
+    /// it to process whole columns at once, which lets the compiler auto-vectorise
+    /// arithmetic primitives.
+    fn execute_batch(op: &Self::Op,
+                     envs: &[Self::Environment],
+                     args: &[Vec<Self::Action>],
+                     out: &mut Vec<Self::Action>)
+        where Self::Action: Clone
+    {
+        let mut row = Vec::with_capacity(args.len());
+        for (i, env) in envs.iter().enumerate() {
+            row.clear();
+            row.extend(args.iter().map(|column| column[i].clone()));
+            out.push(Self::execute(op, env, &row));
+        }
+    }
+}
+
+/// Trait for `Compile` trees whose opcodes can be generated on their own, without building
+/// a tree. This lets linear and Cartesian GP (see `lgp` and `cgp`) reuse a tree's
+/// primitives.
+pub trait Operators: Compile {
+    /// Generate a random opcode, with the number of arguments it takes.
+    fn random_op<R: Rng>(rng: &mut R) -> Instruction<Self::Op>;
+}
+
+/// A single instruction of a `Program`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Instruction<O> {
+    /// The node's opcode.
+    pub op: O,
+    /// How many values the instruction pops from the stack.
+    pub arity: usize,
+}
+
+/// A `Tree` compiled into postfix instructions, evaluated by a small stack machine.
+///
+/// Every child is evaluated before its parent, so trees with `Compile::lazy` nodes cannot
+/// be compiled. Evaluating a program otherwise gives the same results as `Tree::evaluate`,
+/// without following pointers from node to node.
+/// `examples/compiled_evaluation.rs` times the two against each other.
+#[derive(Clone, Debug)]
+pub struct Program<T>
+    where T: Compile
+{
+    instructions: Vec<Instruction<T::Op>>,
+}
+
+impl<T> Program<T>
+    where T: Compile
+{
+    /// Compile a tree into postfix instructions.
+    ///
+    /// Panics if the tree has a `Compile::lazy` node; check with `Program::can_compile`.
+    pub fn compile(tree: &BoxTree<T>) -> Program<T> {
+        let mut instructions = vec![];
+        let mut stack: Vec<(&BoxTree<T>, bool)> = vec![(tree, false)];
+        while let Some((node, expanded)) = stack.pop() {
+            assert!(!node.lazy(), "cannot compile a lazy node, as programs are strict");
+            let children = node.children();
+            if expanded || children.is_empty() {
+                instructions.push(Instruction {
+                    op: node.op(),
+                    arity: children.len(),
+                });
+            } else {
+                stack.push((node, true));
+                for child in children.into_iter().rev() {
+                    stack.push((child, false));
+                }
+            }
+        }
+        Program { instructions }
+    }
+
+    /// Whether a tree has no `Compile::lazy` nodes, so can be compiled.
+    pub fn can_compile(tree: &BoxTree<T>) -> bool {
+        !tree.pre_order().any(|node| node.tree.lazy())
+    }
+
+    /// Get the compiled instructions, in postfix order.
+    pub fn instructions(&self) -> &[Instruction<T::Op>] {
+        &self.instructions
+    }
+
+    /// Get the number of instructions.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Whether there are no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Evaluate the program.
+    pub fn evaluate(&self, env: &T::Environment) -> T::Action {
+        let mut stack = Vec::with_capacity(self.instructions.len());
+        self.evaluate_with_stack(env, &mut stack)
+    }
+
+    /// Evaluate the program, reusing a stack between calls to avoid reallocating it.
+    pub fn evaluate_with_stack(&self,
+                               env: &T::Environment,
+                               stack: &mut Vec<T::Action>)
+                               -> T::Action {
+        stack.clear();
+        for instruction in &self.instructions {
+            let args_start = stack.len() - instruction.arity;
+            let value = T::execute(&instruction.op, env, &stack[args_start..]);
+            stack.truncate(args_start);
+            stack.push(value);
+        }
+        stack.pop().expect("a compiled program always leaves a value on the stack")
+    }
+}
+
+impl<'a, T> From<&'a BoxTree<T>> for Program<T>
+    where T: Compile
+{
+    fn from(tree: &'a BoxTree<T>) -> Program<T> {
+        Program::compile(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    /// Integer arithmetic on one input, with a conditional which only evaluates the branch
+    /// it takes.
+    #[derive(Clone, Debug)]
+    enum Int {
+        Input,
+        Literal(i64),
+        Sub(BoxTree<Int>, BoxTree<Int>),
+        Double(BoxTree<Int>),
+        IfPositive(BoxTree<Int>, BoxTree<Int>, BoxTree<Int>),
+    }
+
+    #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+    enum IntOp {
+        Input,
+        Literal(i64),
+        Sub,
+        Double,
+        IfPositive,
+    }
+
+    impl Tree for Int {
+        type Environment = i64;
+        type Action = i64;
+
+        /// Generates everything but conditionals.
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Int> {
+            let child = Self::child(tg, current_depth + 1);
+            if tg.gen() {
+                Int::Double(child).into()
+            } else {
+                Int::Sub(child, Self::child(tg, current_depth + 1)).into()
+            }
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Int> {
+            if tg.gen() { Int::Input } else { Int::Literal(tg.gen_range(-3, 4)) }.into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Int>> {
+            match *self {
+                Int::Input | Int::Literal(_) => vec![],
+                Int::Sub(ref a, ref b) => vec![a, b],
+                Int::Double(ref a) => vec![a],
+                Int::IfPositive(ref a, ref b, ref c) => vec![a, b, c],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Int>> {
+            match *self {
+                Int::Input | Int::Literal(_) => vec![],
+                Int::Sub(ref mut a, ref mut b) => vec![a, b],
+                Int::Double(ref mut a) => vec![a],
+                Int::IfPositive(ref mut a, ref mut b, ref mut c) => vec![a, b, c],
+            }
+        }
+
+        fn evaluate(&self, env: &i64) -> i64 {
+            match *self {
+                Int::Input => *env,
+                Int::Literal(value) => value,
+                Int::Sub(ref a, ref b) => a.evaluate(env) - b.evaluate(env),
+                Int::Double(ref a) => 2 * a.evaluate(env),
+                Int::IfPositive(ref a, ref b, ref c) => {
+                    if a.evaluate(env) > 0 { b.evaluate(env) } else { c.evaluate(env) }
+                }
+            }
+        }
+    }
+
+    impl Compile for Int {
+        type Op = IntOp;
+
+        fn op(&self) -> IntOp {
+            match *self {
+                Int::Input => IntOp::Input,
+                Int::Literal(value) => IntOp::Literal(value),
+                Int::Sub(_, _) => IntOp::Sub,
+                Int::Double(_) => IntOp::Double,
+                Int::IfPositive(_, _, _) => IntOp::IfPositive,
+            }
+        }
+
+        fn lazy(&self) -> bool {
+            matches!(*self, Int::IfPositive(_, _, _))
+        }
+
+        fn execute(op: &IntOp, env: &i64, args: &[i64]) -> i64 {
+            match *op {
+                IntOp::Input => *env,
+                IntOp::Literal(value) => value,
+                IntOp::Sub => args[0] - args[1],
+                IntOp::Double => 2 * args[0],
+                IntOp::IfPositive => unreachable!("lazy nodes are never compiled"),
+            }
+        }
+    }
+
+    /// `2(x - 3)`, or with `conditional`, `2(if x > 0 { x } else { 3 })`.
+    fn tree(conditional: bool) -> BoxTree<Int> {
+        let x = || -> BoxTree<Int> { Int::Input.into() };
+        let three: BoxTree<Int> = Int::Literal(3).into();
+        let inner = if conditional {
+            Int::IfPositive(x(), x(), three)
+        } else {
+            Int::Sub(x(), three)
+        };
+        Int::Double(inner.into()).into()
+    }
+
+    #[test]
+    fn compile_emits_postfix_instructions() {
+        let program = Program::compile(&tree(false));
+        let ops: Vec<(IntOp, usize)> =
+            program.instructions().iter().map(|i| (i.op, i.arity)).collect();
+        assert_eq!(ops,
+                   vec![(IntOp::Input, 0),
+                        (IntOp::Literal(3), 0),
+                        (IntOp::Sub, 2),
+                        (IntOp::Double, 1)]);
+        assert_eq!((program.len(), program.is_empty()), (4, false));
+        assert_eq!(program.evaluate(&5), 4);
+    }
+
+    #[test]
+    fn program_matches_tree_evaluation() {
+        let mut rng = StdRng::from_seed(&[27usize][..]);
+        let mut stack = vec![];
+        for _ in 0..100 {
+            let tree = Int::tree(&mut TreeGen::full(&mut rng, 1, 6));
+            assert!(Program::can_compile(&tree));
+            let program = Program::from(&tree);
+            assert_eq!(program.len(), tree.count_nodes());
+            for env in -3..4 {
+                let expected = tree.evaluate(&env);
+                assert_eq!(program.evaluate(&env), expected);
+                assert_eq!(program.evaluate_with_stack(&env, &mut stack), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn lazy_nodes_are_not_compiled() {
+        assert!(!Program::can_compile(&tree(true)));
+        assert_eq!(tree(true).evaluate(&-1), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot compile a lazy node")]
+    fn compiling_a_lazy_node_panics() {
+        Program::compile(&tree(true));
+    }
+}
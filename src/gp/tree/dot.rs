@@ -0,0 +1,198 @@
+use gp::tree::*;
+use std::fmt::{Display, Write};
+
+/// Configures export of GP trees to Graphviz's DOT language.
+///
+/// Nodes are named after their 0-based pre-order index, the same numbering used by
+/// `BoxTree::map` and `BoxTree::get`. Render the output with e.g. `dot -Tsvg tree.dot`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Dot {
+    /// Name of the emitted `digraph`.
+    name: String,
+    /// Pre-order index of a subtree to colour.
+    highlight: Option<usize>,
+    /// Graphviz colour used for the highlighted subtree.
+    highlight_colour: String,
+}
+
+impl Dot {
+    /// Export trees as a `digraph` named `tree`, without any highlighting.
+    pub fn new() -> Dot {
+        Dot {
+            name: "tree".to_string(),
+            highlight: None,
+            highlight_colour: "lightblue".to_string(),
+        }
+    }
+
+    /// Set the name of the emitted `digraph`.
+    pub fn name(mut self, name: &str) -> Dot {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Colour the subtree rooted at a particular pre-order index.
+    ///
+    /// Pass `Individual::last_changed` to show the subtree changed by the latest crossover
+    /// or mutation.
+    pub fn highlight(mut self, index: Option<usize>) -> Dot {
+        self.highlight = index;
+        self
+    }
+
+    /// Set the Graphviz colour used for the highlighted subtree.
+    pub fn highlight_colour(mut self, colour: &str) -> Dot {
+        self.highlight_colour = colour.to_string();
+        self
+    }
+
+    /// Render a tree, labelling each node with its `Display` output.
+    ///
+    /// `Display` implementations usually print the whole subtree below a node, so for large
+    /// trees `Dot::render_with` and a label function for single nodes is a better fit.
+    pub fn render<T>(&self, tree: &BoxTree<T>) -> String
+        where T: Tree + Display
+    {
+        self.render_with(tree, |node| node.to_string())
+    }
+
+    /// Render a tree, labelling each node with the output of `label`.
+    pub fn render_with<T, F>(&self, tree: &BoxTree<T>, mut label: F) -> String
+        where T: Tree,
+              F: FnMut(&T) -> String
+    {
+        let mut out = String::new();
+        writeln!(out, "digraph {} {{", quote(&self.name)).unwrap();
+        writeln!(out, "    node [shape=box];").unwrap();
+
+        // Each entry is (node, parent index, whether inside the highlighted subtree).
+        let mut stack: Vec<(&BoxTree<T>, Option<usize>, bool)> = vec![(tree, None, false)];
+        let mut i = 0;
+        while let Some((node, parent, highlighted)) = stack.pop() {
+            let highlighted = highlighted || self.highlight == Some(i);
+            write!(out, "    n{} [label={}", i, quote(&label(node))).unwrap();
+            if highlighted {
+                write!(out,
+                       ", style=filled, fillcolor={}",
+                       quote(&self.highlight_colour))
+                    .unwrap();
+            }
+            writeln!(out, "];").unwrap();
+            if let Some(parent) = parent {
+                writeln!(out, "    n{} -> n{};", parent, i).unwrap();
+            }
+
+            let mut children = node.children();
+            children.reverse();
+            for child in children {
+                stack.push((child, Some(i), highlighted));
+            }
+            i += 1;
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl Default for Dot {
+    fn default() -> Dot {
+        Dot::new()
+    }
+}
+
+/// Quote and escape a string as a DOT identifier.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::fmt;
+
+    /// Nodes named by a string, displayed with their children in brackets.
+    #[derive(Clone, Debug)]
+    struct Named(&'static str, Vec<BoxTree<Named>>);
+
+    impl Tree for Named {
+        type Environment = ();
+        type Action = ();
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Named> {
+            Named("pair", (0..2).map(|_| Self::child(tg, current_depth + 1)).collect()).into()
+        }
+
+        fn leaf<R: Rng>(_: &mut TreeGen<R>, _: usize) -> BoxTree<Named> {
+            Named("leaf", vec![]).into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.1.len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Named>> {
+            self.1.iter().collect()
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Named>> {
+            self.1.iter_mut().collect()
+        }
+
+        fn evaluate(&self, _: &()) {}
+    }
+
+    impl Display for Named {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.0)?;
+            if !self.1.is_empty() {
+                let children: Vec<String> = self.1.iter().map(|c| c.to_string()).collect();
+                write!(f, "({})", children.join(", "))?;
+            }
+            Ok(())
+        }
+    }
+
+    /// `add(x, sin(2))`.
+    fn tree() -> BoxTree<Named> {
+        let sin = Named("sin", vec![Named("2", vec![]).into()]);
+        Named("add", vec![Named("x", vec![]).into(), sin.into()]).into()
+    }
+
+    #[test]
+    fn render_numbers_nodes_in_pre_order() {
+        let dot = Dot::new().render_with(&tree(), |node| node.0.to_string());
+        assert_eq!(dot,
+                   "digraph \"tree\" {\n    node [shape=box];\n    n0 [label=\"add\"];\n    \
+                    n1 [label=\"x\"];\n    n0 -> n1;\n    n2 [label=\"sin\"];\n    \
+                    n0 -> n2;\n    n3 [label=\"2\"];\n    n2 -> n3;\n}\n");
+    }
+
+    #[test]
+    fn highlight_colours_whole_subtree() {
+        let dot = Dot::new().name("changed").highlight(Some(2)).highlight_colour("red");
+        let out = dot.render(&tree());
+        assert!(out.starts_with("digraph \"changed\" {"));
+        assert!(out.contains("n0 [label=\"add(x, sin(2))\"];"));
+        assert!(out.contains("n2 [label=\"sin(2)\", style=filled, fillcolor=\"red\"];"));
+        assert!(out.contains("n3 [label=\"2\", style=filled, fillcolor=\"red\"];"));
+        assert!(out.contains("n1 [label=\"x\"];"));
+    }
+
+    #[test]
+    fn quote_escapes_special_characters() {
+        assert_eq!(quote("a \"b\"\\c\nd"), "\"a \\\"b\\\"\\\\c\\nd\"");
+    }
+}
@@ -0,0 +1,612 @@
+use gp::tree::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// Trait for `Tree`s which can be stored in a `FlatTree`.
+///
+/// Extends `Compile`, whose opcodes hold each node's data, with rebuilding a node from its
+/// opcode and children.
+pub trait Flatten: Compile {
+    /// Rebuild a node from its opcode and children, in the order of `Tree::children`.
+    fn unflatten(op: Self::Op, children: Vec<BoxTree<Self>>) -> BoxTree<Self>;
+}
+
+/// A single node of a `FlatTree`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub struct FlatNode<O> {
+    /// The node's opcode.
+    pub op: O,
+    /// Number of children of the node.
+    pub arity: usize,
+    /// Number of nodes in the subtree rooted at this node, including itself.
+    pub size: usize,
+}
+
+/// A tree stored as a single vector of nodes in pre-order.
+///
+/// Every subtree is a contiguous range of nodes, so cloning, crossover and mutation need one
+/// allocation rather than one per node. Nodes are numbered by the same 0-based pre-order
+/// index as `BoxTree::map`.
+#[derive(Clone, Debug)]
+pub struct FlatTree<T>
+    where T: Flatten
+{
+    nodes: Vec<FlatNode<T::Op>>,
+}
+
+impl<T> FlatTree<T>
+    where T: Flatten
+{
+    /// Flatten a `BoxTree`.
+    pub fn from_tree(tree: &BoxTree<T>) -> FlatTree<T> {
+        let mut nodes = Vec::new();
+        push_subtree(tree, &mut nodes);
+        FlatTree { nodes }
+    }
+
+    /// Rebuild a `BoxTree`.
+    pub fn to_tree(&self) -> BoxTree<T> {
+        // Build bottom-up over the nodes in reverse pre-order. The children of a node are
+        // then on top of the stack, first child topmost.
+        let mut stack: Vec<BoxTree<T>> = Vec::new();
+        for node in self.nodes.iter().rev() {
+            let args_start = stack.len() - node.arity;
+            let mut children = stack.split_off(args_start);
+            children.reverse();
+            stack.push(T::unflatten(node.op.clone(), children));
+        }
+        stack.pop().expect("a flat tree always has a root")
+    }
+
+    /// Get the nodes, in pre-order.
+    pub fn nodes(&self) -> &[FlatNode<T::Op>] {
+        &self.nodes
+    }
+
+    /// Count the number of nodes in the tree.
+    pub fn count_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Get a copy of the subtree rooted at a 0-based pre-order index.
+    pub fn subtree(&self, index: usize) -> Option<FlatTree<T>> {
+        self.nodes.get(index).map(|node| {
+            FlatTree { nodes: self.nodes[index..index + node.size].to_vec() }
+        })
+    }
+
+    /// Replace the subtree at a 0-based pre-order index, returning the subtree that was
+    /// there.
+    ///
+    /// Returns `None`, dropping `subtree`, if there is no node at the index.
+    pub fn replace_subtree(&mut self, index: usize, subtree: FlatTree<T>) -> Option<FlatTree<T>> {
+        let old_size = match self.nodes.get(index) {
+            Some(node) => node.size,
+            None => return None,
+        };
+        let new_size = subtree.nodes.len();
+        // Ancestors are the earlier nodes whose subtrees extend over the index.
+        for ancestor in 0..index {
+            if ancestor + self.nodes[ancestor].size > index {
+                self.nodes[ancestor].size = self.nodes[ancestor].size + new_size - old_size;
+            }
+        }
+        let old = self.nodes.splice(index..index + old_size, subtree.nodes).collect();
+        Some(FlatTree { nodes: old })
+    }
+
+    /// Swap the subtree at `index` with the subtree at `other_index` in another tree.
+    ///
+    /// Returns `false`, changing neither tree, if either index does not exist.
+    pub fn swap_subtrees(&mut self,
+                         index: usize,
+                         other: &mut FlatTree<T>,
+                         other_index: usize)
+                         -> bool {
+        let (subtree, other_subtree) = match (self.subtree(index), other.subtree(other_index)) {
+            (Some(subtree), Some(other_subtree)) => (subtree, other_subtree),
+            _ => return false,
+        };
+        self.replace_subtree(index, other_subtree);
+        other.replace_subtree(other_index, subtree);
+        true
+    }
+
+    /// Evaluate the tree. Gives the same result as `Program::evaluate`.
+    pub fn evaluate(&self, env: &T::Environment) -> T::Action {
+        let mut stack = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter().rev() {
+            let args_start = stack.len() - node.arity;
+            // Children are on top of the stack first child topmost, so put them in order.
+            stack[args_start..].reverse();
+            let value = T::execute(&node.op, env, &stack[args_start..]);
+            stack.truncate(args_start);
+            stack.push(value);
+        }
+        stack.pop().expect("a flat tree always has a root")
+    }
+
+    /// Iterate over the nodes in pre-order. The same as `FlatTree::pre_order`.
+    pub fn iter(&self) -> FlatPreOrder<'_, T> {
+        self.pre_order()
+    }
+
+    /// Iterate over the nodes in pre-order (parents before children, left to right).
+    ///
+    /// This is the order the nodes are stored in.
+    pub fn pre_order(&self) -> FlatPreOrder<'_, T> {
+        FlatPreOrder {
+            nodes: &self.nodes,
+            index: 0,
+            ancestors: vec![],
+        }
+    }
+
+    /// Iterate over the nodes in post-order (children before parents, left to right).
+    pub fn post_order(&self) -> FlatPostOrder<'_, T> {
+        FlatPostOrder {
+            nodes: &self.nodes,
+            index: 0,
+            ancestors: vec![],
+        }
+    }
+
+    /// Iterate over the nodes breadth-first (level by level, left to right).
+    pub fn breadth_first(&self) -> FlatBreadthFirst<'_, T> {
+        let mut queue = VecDeque::new();
+        queue.push_back((0, 0, None));
+        FlatBreadthFirst {
+            nodes: &self.nodes,
+            queue,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a FlatTree<T>
+    where T: Flatten
+{
+    type Item = FlatNodeRef<'a, T>;
+    type IntoIter = FlatPreOrder<'a, T>;
+
+    fn into_iter(self) -> FlatPreOrder<'a, T> {
+        self.pre_order()
+    }
+}
+
+impl<'a, T> From<&'a BoxTree<T>> for FlatTree<T>
+    where T: Flatten
+{
+    fn from(tree: &'a BoxTree<T>) -> FlatTree<T> {
+        FlatTree::from_tree(tree)
+    }
+}
+
+impl<T> PartialEq for FlatTree<T>
+    where T: Flatten,
+          T::Op: PartialEq
+{
+    fn eq(&self, other: &FlatTree<T>) -> bool {
+        self.nodes == other.nodes
+    }
+}
+
+impl<T> Eq for FlatTree<T>
+    where T: Flatten,
+          T::Op: Eq
+{
+}
+
+impl<T> Hash for FlatTree<T>
+    where T: Flatten,
+          T::Op: Hash
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.nodes.hash(state)
+    }
+}
+
+impl<T> fmt::Display for FlatTree<T>
+    where T: Flatten + fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_tree())
+    }
+}
+
+/// A node visited by a traversal of a `FlatTree`. See `FlatTree::pre_order`.
+#[derive(Debug)]
+pub struct FlatNodeRef<'a, T>
+    where T: 'a + Flatten
+{
+    /// The node itself. Its subtree is the `size` nodes starting at `index`.
+    pub node: &'a FlatNode<T::Op>,
+    /// 0-based pre-order index of the node, which is its position in `FlatTree::nodes`.
+    pub index: usize,
+    /// 0-based depth of the node.
+    pub depth: usize,
+    /// Pre-order index of the node's parent, or `None` for the root.
+    pub parent: Option<usize>,
+}
+
+/// Pre-order traversal of a `FlatTree`. See `FlatTree::pre_order`.
+#[derive(Debug)]
+pub struct FlatPreOrder<'a, T>
+    where T: 'a + Flatten
+{
+    nodes: &'a [FlatNode<T::Op>],
+    index: usize,
+    /// Pre-order indices of the ancestors of the next node, root first.
+    ancestors: Vec<usize>,
+}
+
+impl<'a, T> Iterator for FlatPreOrder<'a, T>
+    where T: 'a + Flatten
+{
+    type Item = FlatNodeRef<'a, T>;
+
+    fn next(&mut self) -> Option<FlatNodeRef<'a, T>> {
+        let index = self.index;
+        let node = self.nodes.get(index)?;
+        self.index += 1;
+        // Drop the ancestors whose subtrees end before this node.
+        while let Some(&ancestor) = self.ancestors.last() {
+            if ancestor + self.nodes[ancestor].size > index {
+                break;
+            }
+            self.ancestors.pop();
+        }
+        let item = FlatNodeRef {
+            node,
+            index,
+            depth: self.ancestors.len(),
+            parent: self.ancestors.last().cloned(),
+        };
+        self.ancestors.push(index);
+        Some(item)
+    }
+}
+
+/// Post-order traversal of a `FlatTree`. See `FlatTree::post_order`.
+#[derive(Debug)]
+pub struct FlatPostOrder<'a, T>
+    where T: 'a + Flatten
+{
+    nodes: &'a [FlatNode<T::Op>],
+    /// Pre-order index of the next node to descend into.
+    index: usize,
+    /// Pre-order indices of the nodes descended into but not yet visited, root first.
+    ancestors: Vec<usize>,
+}
+
+impl<'a, T> Iterator for FlatPostOrder<'a, T>
+    where T: 'a + Flatten
+{
+    type Item = FlatNodeRef<'a, T>;
+
+    fn next(&mut self) -> Option<FlatNodeRef<'a, T>> {
+        loop {
+            // A node is visited once every node of its subtree has been descended into.
+            if let Some(&last) = self.ancestors.last() {
+                if last + self.nodes[last].size <= self.index {
+                    self.ancestors.pop();
+                    return Some(FlatNodeRef {
+                        node: &self.nodes[last],
+                        index: last,
+                        depth: self.ancestors.len(),
+                        parent: self.ancestors.last().cloned(),
+                    });
+                }
+            }
+            if self.index == self.nodes.len() {
+                return None;
+            }
+            self.ancestors.push(self.index);
+            self.index += 1;
+        }
+    }
+}
+
+/// Breadth-first traversal of a `FlatTree`. See `FlatTree::breadth_first`.
+#[derive(Debug)]
+pub struct FlatBreadthFirst<'a, T>
+    where T: 'a + Flatten
+{
+    nodes: &'a [FlatNode<T::Op>],
+    /// Pre-order index, depth and parent of each node still to visit.
+    queue: VecDeque<(usize, usize, Option<usize>)>,
+}
+
+impl<'a, T> Iterator for FlatBreadthFirst<'a, T>
+    where T: 'a + Flatten
+{
+    type Item = FlatNodeRef<'a, T>;
+
+    fn next(&mut self) -> Option<FlatNodeRef<'a, T>> {
+        let (index, depth, parent) = self.queue.pop_front()?;
+        let node = &self.nodes[index];
+        // The first child follows its parent, and each later child follows the subtree of
+        // the one before.
+        let mut child = index + 1;
+        for _ in 0..node.arity {
+            self.queue.push_back((child, depth + 1, Some(index)));
+            child += self.nodes[child].size;
+        }
+        Some(FlatNodeRef {
+            node,
+            index,
+            depth,
+            parent,
+        })
+    }
+}
+
+/// Push the subtree below `tree` onto `nodes` in pre-order. Returns the subtree's size.
+fn push_subtree<T>(tree: &BoxTree<T>, nodes: &mut Vec<FlatNode<T::Op>>) -> usize
+    where T: Flatten
+{
+    let index = nodes.len();
+    let children = tree.children();
+    nodes.push(FlatNode {
+        op: tree.op(),
+        arity: children.len(),
+        size: 1,
+    });
+    let mut size = 1;
+    for child in children {
+        size += push_subtree(child, nodes);
+    }
+    nodes[index].size = size;
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, StdRng};
+
+    /// Logic gates over a vector of inputs. Inputs only swap with inputs and gates with
+    /// gates, so that `compatible_indices` has something to check.
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    enum Gate {
+        Input(usize),
+        Not(BoxTree<Gate>),
+        And(BoxTree<Gate>, BoxTree<Gate>),
+        Or(BoxTree<Gate>, BoxTree<Gate>),
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Hash)]
+    enum GateOp {
+        Input(usize),
+        Not,
+        And,
+        Or,
+    }
+
+    impl Tree for Gate {
+        type Environment = Vec<bool>;
+        type Action = bool;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Gate> {
+            let child = |tg: &mut TreeGen<R>| Self::child(tg, current_depth + 1);
+            match tg.gen_range(0, 3) {
+                0 => Gate::Not(child(tg)),
+                1 => Gate::And(child(tg), child(tg)),
+                _ => Gate::Or(child(tg), child(tg)),
+            }
+            .into()
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Gate> {
+            input(tg.gen_range(0, 3))
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Gate>> {
+            match *self {
+                Gate::Input(_) => vec![],
+                Gate::Not(ref a) => vec![a],
+                Gate::And(ref a, ref b) | Gate::Or(ref a, ref b) => vec![a, b],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Gate>> {
+            match *self {
+                Gate::Input(_) => vec![],
+                Gate::Not(ref mut a) => vec![a],
+                Gate::And(ref mut a, ref mut b) | Gate::Or(ref mut a, ref mut b) => vec![a, b],
+            }
+        }
+
+        fn compatible(&self, other: &Gate) -> bool {
+            self.children().is_empty() == other.children().is_empty()
+        }
+
+        fn constrained() -> bool {
+            true
+        }
+
+        fn evaluate(&self, env: &Vec<bool>) -> bool {
+            match *self {
+                Gate::Input(i) => env[i],
+                Gate::Not(ref a) => !a.evaluate(env),
+                Gate::And(ref a, ref b) => a.evaluate(env) && b.evaluate(env),
+                Gate::Or(ref a, ref b) => a.evaluate(env) || b.evaluate(env),
+            }
+        }
+    }
+
+    impl Compile for Gate {
+        type Op = GateOp;
+
+        fn op(&self) -> GateOp {
+            match *self {
+                Gate::Input(i) => GateOp::Input(i),
+                Gate::Not(_) => GateOp::Not,
+                Gate::And(_, _) => GateOp::And,
+                Gate::Or(_, _) => GateOp::Or,
+            }
+        }
+
+        fn execute(op: &GateOp, env: &Vec<bool>, args: &[bool]) -> bool {
+            match *op {
+                GateOp::Input(i) => env[i],
+                GateOp::Not => !args[0],
+                GateOp::And => args[0] && args[1],
+                GateOp::Or => args[0] || args[1],
+            }
+        }
+    }
+
+    impl Flatten for Gate {
+        fn unflatten(op: GateOp, children: Vec<BoxTree<Gate>>) -> BoxTree<Gate> {
+            let mut children = children.into_iter();
+            let mut child = || children.next().unwrap();
+            match op {
+                GateOp::Input(i) => Gate::Input(i),
+                GateOp::Not => Gate::Not(child()),
+                GateOp::And => Gate::And(child(), child()),
+                GateOp::Or => Gate::Or(child(), child()),
+            }
+            .into()
+        }
+    }
+
+    fn input(i: usize) -> BoxTree<Gate> {
+        Gate::Input(i).into()
+    }
+
+    /// `and(or(i0, not(i1)), i2)`.
+    fn gate() -> BoxTree<Gate> {
+        let or = Gate::Or(input(0), Gate::Not(input(1)).into());
+        Gate::And(or.into(), input(2)).into()
+    }
+
+    fn random_trees(seed: usize) -> Vec<BoxTree<Gate>> {
+        let mut rng = StdRng::from_seed(&[seed][..]);
+        (0..50).map(|_| Gate::tree(&mut TreeGen::full(&mut rng, 1, 6))).collect()
+    }
+
+    fn indices<'a, I>(nodes: I) -> Vec<(usize, usize, Option<usize>)>
+        where I: Iterator<Item = FlatNodeRef<'a, Gate>>
+    {
+        nodes.map(|node| (node.index, node.depth, node.parent)).collect()
+    }
+
+    fn box_indices<'a, I>(nodes: I) -> Vec<(usize, usize, Option<usize>)>
+        where I: Iterator<Item = NodeRef<'a, Gate>>
+    {
+        nodes.map(|node| (node.index, node.depth, node.parent)).collect()
+    }
+
+    #[test]
+    fn flat_and_box_trees_evaluate_identically() {
+        for tree in random_trees(32) {
+            let flat = FlatTree::from(&tree);
+            assert_eq!(flat.count_nodes(), tree.count_nodes());
+            assert_eq!(flat.to_tree(), tree);
+            for bits in 0..8 {
+                let env: Vec<bool> = (0..3).map(|i| bits & (1 << i) != 0).collect();
+                assert_eq!(flat.evaluate(&env), tree.evaluate(&env));
+            }
+        }
+    }
+
+    #[test]
+    fn sizes_are_subtree_sizes() {
+        for tree in random_trees(33) {
+            let flat = FlatTree::from(&tree);
+            for node in tree.pre_order() {
+                assert_eq!(flat.nodes()[node.index].size, node.tree.count_nodes());
+                assert_eq!(flat.nodes()[node.index].arity, node.tree.children().len());
+            }
+        }
+    }
+
+    #[test]
+    fn traversals() {
+        let flat = FlatTree::from(&gate());
+        let ops: Vec<GateOp> = flat.post_order().map(|node| node.node.op).collect();
+        assert_eq!(ops,
+                   vec![GateOp::Input(0),
+                        GateOp::Input(1),
+                        GateOp::Not,
+                        GateOp::Or,
+                        GateOp::Input(2),
+                        GateOp::And]);
+        assert_eq!(indices(flat.breadth_first()),
+                   vec![(0, 0, None),
+                        (1, 1, Some(0)),
+                        (5, 1, Some(0)),
+                        (2, 2, Some(1)),
+                        (3, 2, Some(1)),
+                        (4, 3, Some(3))]);
+        assert_eq!(indices(flat.iter()), indices((&flat).into_iter()));
+    }
+
+    #[test]
+    fn traversals_match_box_trees() {
+        for tree in random_trees(34) {
+            let flat = FlatTree::from(&tree);
+            assert_eq!(indices(flat.pre_order()), box_indices(tree.pre_order()));
+            assert_eq!(indices(flat.post_order()), box_indices(tree.post_order()));
+            assert_eq!(indices(flat.breadth_first()), box_indices(tree.breadth_first()));
+
+            let mut box_visits = vec![];
+            tree.visit(|index, depth, arity| box_visits.push((index, depth, arity)));
+            let mut flat_visits = vec![];
+            flat.visit(|index, depth, arity| flat_visits.push((index, depth, arity)));
+            assert_eq!(flat_visits, box_visits);
+        }
+    }
+
+    #[test]
+    fn subtree_operations_match_box_trees() {
+        let mut rng = StdRng::from_seed(&[35usize][..]);
+        let trees = random_trees(35);
+        for pair in trees.chunks(2) {
+            let (mut box1, mut box2) = (pair[0].clone(), pair[1].clone());
+            let (mut flat1, mut flat2) = (FlatTree::from(&box1), FlatTree::from(&box2));
+            let index1 = rng.gen_range(0, box1.count_nodes());
+            let index2 = rng.gen_range(0, box2.count_nodes());
+
+            assert_eq!(flat1.subtree(index1).unwrap().to_tree(),
+                       *box1.subtree_at(index1).unwrap());
+            assert_eq!(flat1.compatible_indices(index1, &flat2),
+                       box1.compatible_indices(index1, &box2));
+            assert!(Representation::swap_subtrees(&mut box1, index1, &mut box2, index2));
+            assert!(flat1.swap_subtrees(index1, &mut flat2, index2));
+            assert_eq!(flat1.to_tree(), box1);
+            assert_eq!(flat2.to_tree(), box2);
+            assert_eq!(flat1, FlatTree::from(&box1));
+
+            let replacement = FlatTree::from(&pair[0]);
+            let old = flat1.replace_subtree(index1, replacement.clone()).unwrap();
+            let old_box = Representation::replace_subtree(&mut box1, index1, pair[0].clone());
+            assert_eq!(old.to_tree(), old_box.unwrap());
+            assert_eq!(flat1, FlatTree::from(&box1));
+        }
+    }
+
+    #[test]
+    fn compatible_indices_checks_rebuilt_subtrees() {
+        let flat = FlatTree::from(&gate());
+        // Gates are at 0, 1 and 3, inputs at 2, 4 and 5.
+        assert_eq!(flat.compatible_indices(0, &flat), vec![0, 1, 3]);
+        assert_eq!(flat.compatible_indices(5, &flat), vec![2, 4, 5]);
+        assert!(flat.compatible_indices(6, &flat).is_empty());
+    }
+
+    #[test]
+    fn missing_indices() {
+        let mut flat = FlatTree::from(&input(0));
+        let mut other = flat.clone();
+        assert!(flat.subtree(1).is_none());
+        assert!(flat.replace_subtree(1, other.clone()).is_none());
+        assert!(!flat.swap_subtrees(0, &mut other, 1));
+        assert_eq!(flat, other);
+    }
+}
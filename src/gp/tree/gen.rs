@@ -1,4 +1,35 @@
+use gp::grammar::Grammar;
+use gp::tree::*;
 use rand::Rng;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+/// Trait for `Tree`s whose kinds of node can be listed up front.
+///
+/// Generators such as PTC2 choose the kind of each node, and so its arity, before building
+/// it. `Tree::branch` and `Tree::leaf` cannot offer this.
+pub trait Primitives: Tree {
+    /// Identifies a kind of node, e.g. a fieldless copy of the `Tree` enum.
+    type Kind: Copy + Debug;
+
+    /// Every kind of branch node, with its number of children.
+    fn branches() -> Vec<(Self::Kind, usize)>;
+
+    /// Every kind of leaf node.
+    fn leaves() -> Vec<Self::Kind>;
+
+    /// Build a node of a particular kind from its children, in the order of
+    /// `Tree::children`. Random data such as constants can be drawn from `tg`.
+    fn build<R: Rng>(kind: Self::Kind,
+                     children: Vec<BoxTree<Self>>,
+                     tg: &mut TreeGen<R>)
+                     -> BoxTree<Self>;
+
+    /// Generate a new tree by PTC2, using a TreeGen created by `TreeGen::ptc2`.
+    fn ptc2<R: Rng>(tg: &mut TreeGen<R>) -> BoxTree<Self> {
+        tg.ptc2_tree()
+    }
+}
 
 /// The tree generation mode in use. See `TreeGen`.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -9,6 +40,12 @@ enum TreeGenMode {
     Full,
     /// Corresponds to `TreeGen::full_ranged`.
     FullRanged(usize),
+    /// Corresponds to `TreeGen::half_and_half`. Holds whether the current tree is perfect,
+    /// and its chosen depth.
+    HalfAndHalf(bool, usize),
+    /// Corresponds to `TreeGen::ptc2`. Holds the target size of the current tree, and the
+    /// number of nodes generated so far.
+    Ptc2(usize, usize),
 }
 
 /// Configures depth and properties of GP trees.
@@ -24,6 +61,14 @@ pub struct TreeGen<'a, R>
     min_depth: usize,
     /// The maximum depth of trees to generate.
     max_depth: usize,
+    /// Relative probabilities of each tree size, for `TreeGen::ptc2`.
+    sizes: Vec<u32>,
+    /// Relative probabilities of each kind of branch node. Empty for equal probabilities.
+    branch_weights: Vec<u32>,
+    /// Relative probabilities of each kind of leaf node. Empty for equal probabilities.
+    leaf_weights: Vec<u32>,
+    /// Grammar to generate derivation trees from. See `TreeGen::with_grammar`.
+    grammar: Option<Rc<Grammar>>,
 }
 
 impl<'a, R> TreeGen<'a, R>
@@ -33,59 +78,160 @@ impl<'a, R> TreeGen<'a, R>
     /// [min_depth, max_depth].
     ///
     /// **This is the equivalent of DEAP's `genFull`.**
-    pub fn perfect(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<R> {
+    pub fn perfect(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<'_, R> {
         let chosen_depth = rng.gen_range(min_depth, max_depth + 1);
-        TreeGen {
-            rng: rng,
-            mode: TreeGenMode::Perfect(chosen_depth),
-            min_depth: min_depth,
-            max_depth: max_depth,
-        }
+        Self::with_mode(rng, TreeGenMode::Perfect(chosen_depth), min_depth, max_depth)
     }
 
     /// Generate full trees with leaves at varying depths. Leaf depths are
     /// linearly distributed between min_depth and a chosen depth in the range.
     ///
     /// **This is NOT the same as DEAP's `genFull`. See `TreeGen::perfect`.**
-    pub fn full(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<R> {
-        TreeGen {
-            rng: rng,
-            mode: TreeGenMode::Full,
-            min_depth: min_depth,
-            max_depth: max_depth,
-        }
+    pub fn full(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<'_, R> {
+        Self::with_mode(rng, TreeGenMode::Full, min_depth, max_depth)
     }
 
     /// Generate full trees with leaves at varying depths. Leaf depths are
     /// linearly distributed between min_depth and a chosen depth in the range.
     ///
     /// **This is the equivalent of DEAP's `genGrow`.**
-    pub fn full_ranged(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<R> {
+    pub fn full_ranged(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<'_, R> {
         let chosen_depth = rng.gen_range(min_depth, max_depth + 1);
-        TreeGen {
-            rng: rng,
-            mode: TreeGenMode::FullRanged(chosen_depth),
-            min_depth: min_depth,
-            max_depth: max_depth,
-        }
+        Self::with_mode(rng, TreeGenMode::FullRanged(chosen_depth), min_depth, max_depth)
+    }
+
+    /// Randomly choose between `TreeGen::perfect` and `TreeGen::full_ranged`, and a depth
+    /// in the range, separately for every tree generated.
+    ///
+    /// **This is the equivalent of DEAP's `genHalfAndHalf`.** To spread a population evenly
+    /// over depths and methods, see `Initialisation::ramped_half_and_half`.
+    pub fn half_and_half(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<'_, R> {
+        Self::with_mode(rng, TreeGenMode::HalfAndHalf(true, min_depth), min_depth, max_depth)
+    }
+
+    /// Generate trees by Probabilistic Tree Creation 2 (PTC2), which controls tree size
+    /// rather than depth. `sizes[n]` is the relative probability of a tree having `n` nodes.
+    /// No node is placed deeper than `max_depth`.
+    ///
+    /// Exact PTC2 needs to know the arity of every kind of node up front, so it is only
+    /// available for `Tree`s implementing `Primitives`, through `Primitives::ptc2`. For
+    /// other trees, and when `Mutation` generates subtrees, nodes are made leaves once the
+    /// target size is reached, so trees somewhat overshoot their target size.
+    pub fn ptc2<'b>(rng: &'b mut R, sizes: &[u32], max_depth: usize) -> TreeGen<'b, R> {
+        assert!(sizes.iter().any(|&weight| weight > 0),
+                "PTC2 needs at least one tree size with non-zero probability");
+        let mut tg = Self::with_mode(rng, TreeGenMode::Ptc2(1, 0), 0, max_depth);
+        tg.sizes = sizes.to_vec();
+        tg
+    }
+
+    /// Set the relative probabilities of choosing each kind of branch and leaf node.
+    ///
+    /// Weights are given in the order kinds are numbered by `Tree::branch` and `Tree::leaf`
+    /// when calling `TreeGen::choose_branch` and `TreeGen::choose_leaf`, and in the order of
+    /// `Primitives::branches` and `Primitives::leaves`. A weight of zero means that kind is
+    /// never chosen. Empty weights mean every kind is equally likely.
+    ///
+    /// Set these once per run, on every TreeGen used for initialisation and mutation.
+    pub fn with_weights(mut self, branch_weights: Vec<u32>, leaf_weights: Vec<u32>) -> Self {
+        self.branch_weights = branch_weights;
+        self.leaf_weights = leaf_weights;
+        self
     }
 
-    /// Randomly choose between `TreeGen::perfect` and `TreeGen::full_ranged`.
+    /// Set the grammar to generate `Derivation` trees from, starting at its first rule.
     ///
-    /// **This is the equivalent of DEAP's `genHalfAndHalf`.**
-    // @TODO: This choice needs to happen at runtime.
-    pub fn half_and_half(rng: &mut R, min_depth: usize, max_depth: usize) -> TreeGen<R> {
-        if rng.gen() {
-            Self::perfect(rng, min_depth, max_depth)
-        } else {
-            Self::full_ranged(rng, min_depth, max_depth)
+    /// Set this on every TreeGen used to generate derivation trees with `Tree::tree`, e.g.
+    /// through `Individual::new`. Mutation regenerates subtrees from each subtree's own
+    /// grammar, so does not need it.
+    pub fn with_grammar(mut self, grammar: Rc<Grammar>) -> Self {
+        self.grammar = Some(grammar);
+        self
+    }
+
+    /// Choose which of `count` kinds of branch node to generate, numbered from 0, according
+    /// to the weights set by `TreeGen::with_weights`. Used by `Tree::branch`.
+    pub fn choose_branch(&mut self, count: usize) -> usize {
+        weighted_index(self.rng, &self.branch_weights, count)
+    }
+
+    /// Choose which of `count` kinds of leaf node to generate, numbered from 0, according
+    /// to the weights set by `TreeGen::with_weights`. Used by `Tree::leaf`.
+    pub fn choose_leaf(&mut self, count: usize) -> usize {
+        weighted_index(self.rng, &self.leaf_weights, count)
+    }
+
+    /// Choose an index into `weights` with probability proportional to its weight.
+    pub fn choose_weighted(&mut self, weights: &[u32]) -> usize {
+        weighted_index(self.rng, weights, weights.len())
+    }
+
+    /// Get the branch weights set by `TreeGen::with_weights`.
+    pub fn branch_weights(&self) -> &[u32] {
+        &self.branch_weights
+    }
+
+    /// Get the leaf weights set by `TreeGen::with_weights`.
+    pub fn leaf_weights(&self) -> &[u32] {
+        &self.leaf_weights
+    }
+
+    /// Get the grammar set by `TreeGen::with_grammar`.
+    pub fn grammar(&self) -> Option<&Rc<Grammar>> {
+        self.grammar.as_ref()
+    }
+
+    /// Get the minimum depth of generated trees.
+    pub fn min_depth(&self) -> usize {
+        self.min_depth
+    }
+
+    /// Get the maximum depth of generated trees.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    fn with_mode(rng: &mut R,
+                 mode: TreeGenMode,
+                 min_depth: usize,
+                 max_depth: usize)
+                 -> TreeGen<'_, R> {
+        TreeGen {
+            rng,
+            mode,
+            min_depth,
+            max_depth,
+            sizes: vec![],
+            branch_weights: vec![],
+            leaf_weights: vec![],
+            grammar: None,
         }
     }
 
+    /// Choose the target size of a new tree, for `TreeGen::ptc2`.
+    fn choose_size(&mut self) -> usize {
+        weighted_index(self.rng, &self.sizes, self.sizes.len())
+    }
+
     /// Chooses whether to generate a Leaf node. Used by `Tree::child`.
     pub fn have_reached_a_leaf(&mut self, current_depth: usize) -> bool {
+        // Every tree starts at its root, so choose afresh for each new tree.
+        match self.mode {
+            TreeGenMode::HalfAndHalf(..) if current_depth == 0 => {
+                let perfect = self.gen();
+                let chosen_depth = self.gen_range(self.min_depth, self.max_depth + 1);
+                self.mode = TreeGenMode::HalfAndHalf(perfect, chosen_depth);
+            }
+            TreeGenMode::Ptc2(..) if current_depth == 0 => {
+                let target_size = self.choose_size();
+                self.mode = TreeGenMode::Ptc2(target_size, 0);
+            }
+            _ => {}
+        }
+
         match self.mode {
-            TreeGenMode::Perfect(chosen_depth) => current_depth == chosen_depth,
+            TreeGenMode::Perfect(chosen_depth) |
+            TreeGenMode::HalfAndHalf(true, chosen_depth) => current_depth == chosen_depth,
             TreeGenMode::Full => {
                 // This given an equal 1-in-depth_interval chance at every intermediary depth.
                 // Earlier checks ensure in the (1/depth)*(depth-1) case we reach chosen_depth,
@@ -95,7 +241,8 @@ impl<'a, R> TreeGen<'a, R>
                 current_depth == self.max_depth ||
                 (current_depth >= self.min_depth) && self.gen_weighted_bool(depth_interval as u32)
             }
-            TreeGenMode::FullRanged(chosen_depth) => {
+            TreeGenMode::FullRanged(chosen_depth) |
+            TreeGenMode::HalfAndHalf(false, chosen_depth) => {
                 // This given an equal 1-in-depth_interval chance at every intermediary depth.
                 // Earlier checks ensure in the (1/depth)*(depth-1) case we reach chosen_depth,
                 // we do finally place a Leaf.
@@ -104,8 +251,88 @@ impl<'a, R> TreeGen<'a, R>
                 current_depth == chosen_depth ||
                 (current_depth >= self.min_depth) && self.gen_weighted_bool(depth_interval as u32)
             }
+            TreeGenMode::Ptc2(target_size, generated) => {
+                self.mode = TreeGenMode::Ptc2(target_size, generated + 1);
+                current_depth >= self.max_depth || generated + 1 >= target_size
+            }
+        }
+    }
+
+    /// Generate a tree by PTC2. See `TreeGen::ptc2` and `Primitives::ptc2`.
+    fn ptc2_tree<T>(&mut self) -> BoxTree<T>
+        where T: Primitives
+    {
+        assert!(!self.sizes.is_empty(),
+                "PTC2 trees need a TreeGen created by TreeGen::ptc2");
+        let target_size = self.choose_size();
+        let branches = T::branches();
+        let leaves = T::leaves();
+
+        // Nodes are chosen before being built, so first record their kinds and children.
+        let mut kinds: Vec<T::Kind> = vec![];
+        let mut children: Vec<Vec<usize>> = vec![];
+        // Open slots for nodes, as (parent node and child position, depth).
+        let mut open: Vec<(Option<(usize, usize)>, usize)> = vec![(None, 0)];
+        while !open.is_empty() {
+            let slot_index = self.gen_range(0, open.len());
+            let (parent, depth) = open.swap_remove(slot_index);
+            let node = kinds.len();
+            // Add branches until the target size is reached or exceeded, then fill the
+            // remaining slots with leaves.
+            let grow = node + open.len() + 1 < target_size && depth < self.max_depth &&
+                       !branches.is_empty();
+            if grow {
+                let (kind, arity) = branches[self.choose_branch(branches.len())];
+                kinds.push(kind);
+                children.push(vec![0; arity]);
+                open.extend((0..arity).map(|position| (Some((node, position)), depth + 1)));
+            } else {
+                kinds.push(leaves[self.choose_leaf(leaves.len())]);
+                children.push(vec![]);
+            }
+            if let Some((parent, position)) = parent {
+                children[parent][position] = node;
+            }
+        }
+        build_ptc2_node::<T, R>(0, &kinds, &children, self)
+    }
+}
+
+/// Build a node chosen by `TreeGen::ptc2_tree`, after building its children.
+fn build_ptc2_node<T, R>(node: usize,
+                         kinds: &[T::Kind],
+                         children: &[Vec<usize>],
+                         tg: &mut TreeGen<R>)
+                         -> BoxTree<T>
+    where T: Primitives,
+          R: Rng
+{
+    let built_children = children[node]
+        .iter()
+        .map(|&child| build_ptc2_node(child, kinds, children, tg))
+        .collect();
+    T::build(kinds[node], built_children, tg)
+}
+
+/// Choose an index into `0..count` with probability proportional to its weight. If
+/// `weights` is empty, every index is equally likely.
+fn weighted_index<R>(rng: &mut R, weights: &[u32], count: usize) -> usize
+    where R: Rng
+{
+    if weights.is_empty() {
+        return rng.gen_range(0, count);
+    }
+    assert_eq!(weights.len(), count, "need one weight per kind of node");
+    let total: u64 = weights.iter().map(|&weight| weight as u64).sum();
+    assert!(total > 0, "need at least one non-zero weight");
+    let mut remaining = rng.gen_range(0, total);
+    for (index, &weight) in weights.iter().enumerate() {
+        if remaining < weight as u64 {
+            return index;
         }
+        remaining -= weight as u64;
     }
+    unreachable!()
 }
 
 impl<'a, R> Rng for TreeGen<'a, R>
@@ -125,3 +352,173 @@ impl<'a, R> Rng for TreeGen<'a, R>
         self.rng.fill_bytes(dest)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    /// Binary trees with numbered leaves, evaluating to the sum of their leaves.
+    #[derive(Clone, Debug)]
+    enum Pair {
+        Join(BoxTree<Pair>, BoxTree<Pair>),
+        Leaf(usize),
+    }
+
+    impl Tree for Pair {
+        type Environment = ();
+        type Action = usize;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Pair> {
+            Pair::Join(Self::child(tg, current_depth + 1), Self::child(tg, current_depth + 1))
+                .into()
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Pair> {
+            Pair::Leaf(tg.choose_leaf(3)).into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Pair>> {
+            match *self {
+                Pair::Join(ref a, ref b) => vec![a, b],
+                Pair::Leaf(_) => vec![],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Pair>> {
+            match *self {
+                Pair::Join(ref mut a, ref mut b) => vec![a, b],
+                Pair::Leaf(_) => vec![],
+            }
+        }
+
+        fn evaluate(&self, _: &()) -> usize {
+            match *self {
+                Pair::Join(ref a, ref b) => a.evaluate(&()) + b.evaluate(&()),
+                Pair::Leaf(leaf) => leaf,
+            }
+        }
+    }
+
+    impl Primitives for Pair {
+        type Kind = Option<usize>;
+
+        fn branches() -> Vec<(Option<usize>, usize)> {
+            vec![(None, 2)]
+        }
+
+        fn leaves() -> Vec<Option<usize>> {
+            vec![Some(0), Some(1), Some(2)]
+        }
+
+        fn build<R: Rng>(kind: Option<usize>,
+                         mut children: Vec<BoxTree<Pair>>,
+                         _: &mut TreeGen<R>)
+                         -> BoxTree<Pair> {
+            match kind {
+                None => {
+                    let b = children.pop().unwrap();
+                    let a = children.pop().unwrap();
+                    Pair::Join(a, b).into()
+                }
+                Some(leaf) => Pair::Leaf(leaf).into(),
+            }
+        }
+    }
+
+    fn height(tree: &BoxTree<Pair>) -> usize {
+        tree.pre_order().map(|node| node.depth).max().unwrap()
+    }
+
+    fn rng() -> StdRng {
+        StdRng::from_seed(&[35usize][..])
+    }
+
+    #[test]
+    fn ptc2_hits_target_size() {
+        let mut rng = rng();
+        let mut sizes = vec![0; 12];
+        sizes[7] = 1;
+        sizes[11] = 1;
+        let mut tg = TreeGen::ptc2(&mut rng, &sizes, 10);
+        for _ in 0..50 {
+            let size = Pair::ptc2(&mut tg).count_nodes();
+            assert!(size == 7 || size == 11, "size {}", size);
+        }
+    }
+
+    #[test]
+    fn ptc2_respects_max_depth() {
+        let mut rng = rng();
+        let mut sizes = vec![0; 64];
+        sizes[63] = 1;
+        let mut tg = TreeGen::ptc2(&mut rng, &sizes, 3);
+        for _ in 0..50 {
+            let tree = Pair::ptc2(&mut tg);
+            assert!(height(&tree) <= 3);
+        }
+    }
+
+    #[test]
+    fn ptc2_mode_bounds_tree_size_for_other_trees() {
+        let mut rng = rng();
+        let mut tg = TreeGen::ptc2(&mut rng, &[0, 0, 0, 1], 10);
+        for _ in 0..50 {
+            // Nodes become leaves once the target is reached, so binary trees of a
+            // target size 3 have 3 or 5 nodes.
+            let size = Pair::tree(&mut tg).count_nodes();
+            assert!(size == 3 || size == 5, "size {}", size);
+        }
+    }
+
+    #[test]
+    fn weights_exclude_kinds() {
+        let mut rng = rng();
+        let mut tg = TreeGen::full(&mut rng, 0, 3).with_weights(vec![1], vec![0, 1, 0]);
+        for _ in 0..50 {
+            let tree = Pair::tree(&mut tg);
+            // Every leaf is 1, so the sum counts the leaves.
+            let leaves = tree.pre_order().filter(|node| node.tree.children().is_empty()).count();
+            assert_eq!(tree.evaluate(&()), leaves);
+        }
+    }
+
+    #[test]
+    fn weights_set_frequencies() {
+        let mut rng = rng();
+        let mut tg = TreeGen::full(&mut rng, 0, 0).with_weights(vec![], vec![1, 0, 3]);
+        assert_eq!(tg.leaf_weights(), &[1, 0, 3]);
+        assert!(tg.branch_weights().is_empty());
+        let mut counts = [0; 3];
+        for _ in 0..4000 {
+            counts[tg.choose_leaf(3)] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!(counts[2] > 2 * counts[0], "{:?}", counts);
+
+        let mut counts = [0; 3];
+        for _ in 0..3000 {
+            counts[tg.choose_branch(3)] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 800), "{:?}", counts);
+        assert_eq!(tg.choose_weighted(&[0, 0, 5]), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "one weight per kind")]
+    fn weights_must_match_kinds() {
+        let mut rng = rng();
+        TreeGen::full(&mut rng, 0, 0).with_weights(vec![], vec![1, 1]).choose_leaf(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one tree size")]
+    fn ptc2_needs_a_size() {
+        let mut rng = rng();
+        TreeGen::ptc2(&mut rng, &[0, 0], 3);
+    }
+}
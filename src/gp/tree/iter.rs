@@ -0,0 +1,598 @@
+use gp::tree::*;
+use std::collections::VecDeque;
+
+/// A node visited by an immutable traversal. See `BoxTree::pre_order`.
+#[derive(Debug)]
+pub struct NodeRef<'a, T>
+    where T: 'a + Tree
+{
+    /// The subtree rooted at this node.
+    pub tree: &'a BoxTree<T>,
+    /// 0-based pre-order index of the node, as used by `BoxTree::map` and `BoxTree::get`.
+    /// This is the same whichever order the traversal visits nodes in.
+    pub index: usize,
+    /// 0-based depth of the node.
+    pub depth: usize,
+    /// Pre-order index of the node's parent, or `None` for the root.
+    pub parent: Option<usize>,
+}
+
+/// A node visited by a mutable traversal. See `BoxTree::pre_order_mut`.
+#[derive(Debug)]
+pub struct NodeMut<'a, T>
+    where T: 'a + Tree
+{
+    /// The subtree rooted at this node.
+    pub tree: &'a mut BoxTree<T>,
+    /// 0-based pre-order index of the node in the tree as it was before traversal.
+    pub index: usize,
+    /// 0-based depth of the node.
+    pub depth: usize,
+    /// Pre-order index of the node's parent, or `None` for the root.
+    pub parent: Option<usize>,
+}
+
+/// Pre-order (depth-first, parents before children) traversal. See `BoxTree::pre_order`.
+#[derive(Debug)]
+pub struct PreOrder<'a, T>
+    where T: 'a + Tree
+{
+    stack: Vec<(&'a BoxTree<T>, usize, Option<usize>)>,
+    index: usize,
+}
+
+impl<'a, T> Iterator for PreOrder<'a, T>
+    where T: 'a + Tree
+{
+    type Item = NodeRef<'a, T>;
+
+    fn next(&mut self) -> Option<NodeRef<'a, T>> {
+        let (tree, depth, parent) = self.stack.pop()?;
+        let index = self.index;
+        self.index += 1;
+        for child in tree.children().into_iter().rev() {
+            self.stack.push((child, depth + 1, Some(index)));
+        }
+        Some(NodeRef {
+            tree,
+            index,
+            depth,
+            parent,
+        })
+    }
+}
+
+/// Post-order (depth-first, children before parents) traversal. See `BoxTree::post_order`.
+#[derive(Debug)]
+pub struct PostOrder<'a, T>
+    where T: 'a + Tree
+{
+    /// Nodes still to visit. Expanded nodes have already had their children pushed and
+    /// carry their pre-order index.
+    stack: Vec<PostOrderEntry<'a, T>>,
+    index: usize,
+}
+
+#[derive(Debug)]
+struct PostOrderEntry<'a, T>
+    where T: 'a + Tree
+{
+    tree: &'a BoxTree<T>,
+    depth: usize,
+    parent: Option<usize>,
+    expanded: Option<usize>,
+}
+
+impl<'a, T> Iterator for PostOrder<'a, T>
+    where T: 'a + Tree
+{
+    type Item = NodeRef<'a, T>;
+
+    fn next(&mut self) -> Option<NodeRef<'a, T>> {
+        while let Some(entry) = self.stack.pop() {
+            if let Some(index) = entry.expanded {
+                return Some(NodeRef {
+                    tree: entry.tree,
+                    index,
+                    depth: entry.depth,
+                    parent: entry.parent,
+                });
+            }
+
+            // Nodes are first popped in pre-order, so this is when their index is known.
+            let index = self.index;
+            self.index += 1;
+            let children = entry.tree.children();
+            if children.is_empty() {
+                return Some(NodeRef {
+                    tree: entry.tree,
+                    index,
+                    depth: entry.depth,
+                    parent: entry.parent,
+                });
+            }
+            self.stack.push(PostOrderEntry { expanded: Some(index), ..entry });
+            for child in children.into_iter().rev() {
+                self.stack.push(PostOrderEntry {
+                    tree: child,
+                    depth: entry.depth + 1,
+                    parent: Some(index),
+                    expanded: None,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Breadth-first (level by level, left to right) traversal. See `BoxTree::breadth_first`.
+#[derive(Debug)]
+pub struct BreadthFirst<'a, T>
+    where T: 'a + Tree
+{
+    queue: VecDeque<NodeRef<'a, T>>,
+    /// Size of the subtree rooted at each node, by pre-order index.
+    sizes: Vec<usize>,
+}
+
+impl<'a, T> Iterator for BreadthFirst<'a, T>
+    where T: 'a + Tree
+{
+    type Item = NodeRef<'a, T>;
+
+    fn next(&mut self) -> Option<NodeRef<'a, T>> {
+        let node = self.queue.pop_front()?;
+        let mut child_index = node.index + 1;
+        for child in node.tree.children() {
+            self.queue.push_back(NodeRef {
+                tree: child,
+                index: child_index,
+                depth: node.depth + 1,
+                parent: Some(node.index),
+            });
+            child_index += self.sizes[child_index];
+        }
+        Some(node)
+    }
+}
+
+/// Mutable pre-order traversal. See `BoxTree::pre_order_mut`.
+///
+/// Nodes nest inside each other, so this cannot be an `Iterator`: each node must be
+/// released before the next is requested. Use it with `while let` and `next_node`.
+#[derive(Debug)]
+pub struct PreOrderMut<'a, T>
+    where T: 'a + Tree
+{
+    stack: Vec<(&'a mut BoxTree<T>, usize, Option<usize>)>,
+    /// The node last returned. Its children are expanded on the following call, so any
+    /// changes made to it are traversed.
+    current: Option<(&'a mut BoxTree<T>, usize, usize)>,
+    index: usize,
+}
+
+impl<'a, T> PreOrderMut<'a, T>
+    where T: 'a + Tree
+{
+    /// Get the next node, or `None` once every node has been visited.
+    pub fn next_node(&mut self) -> Option<NodeMut<'_, T>> {
+        if let Some((tree, index, depth)) = self.current.take() {
+            for child in tree.children_mut().into_iter().rev() {
+                self.stack.push((child, depth + 1, Some(index)));
+            }
+        }
+        let (tree, depth, parent) = self.stack.pop()?;
+        let index = self.index;
+        self.index += 1;
+        self.current = Some((tree, index, depth));
+        self.current.as_mut().map(|&mut (ref mut tree, index, depth)| {
+            NodeMut {
+                tree,
+                index,
+                depth,
+                parent,
+            }
+        })
+    }
+}
+
+/// Mutable post-order traversal. See `BoxTree::post_order_mut`.
+///
+/// Nodes nest inside each other, so this cannot be an `Iterator`: each node must be
+/// released before the next is requested. Use it with `while let` and `next_node`.
+#[derive(Debug)]
+pub struct PostOrderMut<'a, T>
+    where T: 'a + Tree
+{
+    root: &'a mut BoxTree<T>,
+    /// Child-index paths from the root to each node, with the node's pre-order index,
+    /// depth and parent, in reverse post-order.
+    paths: Vec<(Vec<usize>, usize, usize, Option<usize>)>,
+}
+
+impl<'a, T> PostOrderMut<'a, T>
+    where T: 'a + Tree
+{
+    /// Get the next node, or `None` once every node has been visited.
+    pub fn next_node(&mut self) -> Option<NodeMut<'_, T>> {
+        let (path, index, depth, parent) = self.paths.pop()?;
+        // Walk down from the root: children are visited first, so changes to them cannot
+        // have moved the current node.
+        let tree = self.root
+            .subtree_mut(&path)
+            .expect("post-order path refers to a removed child");
+        Some(NodeMut {
+            tree,
+            index,
+            depth,
+            parent,
+        })
+    }
+}
+
+/// Mutable breadth-first traversal. See `BoxTree::breadth_first_mut`.
+///
+/// Nodes nest inside each other, so this cannot be an `Iterator`: each node must be
+/// released before the next is requested. Use it with `while let` and `next_node`.
+#[derive(Debug)]
+pub struct BreadthFirstMut<'a, T>
+    where T: 'a + Tree
+{
+    /// Nodes still to visit, with whether the node is at a position which existed before
+    /// traversal.
+    queue: VecDeque<(NodeMut<'a, T>, bool)>,
+    /// The node last returned. Its children are expanded on the following call, so any
+    /// changes made to it are traversed.
+    current: Option<(NodeMut<'a, T>, bool)>,
+    /// Size and number of children of each node before traversal, by pre-order index.
+    shapes: Vec<(usize, usize)>,
+    /// Index for the next node at a position which did not exist before traversal.
+    next_new: usize,
+}
+
+impl<'a, T> BreadthFirstMut<'a, T>
+    where T: 'a + Tree
+{
+    /// Get the next node, or `None` once every node has been visited.
+    pub fn next_node(&mut self) -> Option<NodeMut<'_, T>> {
+        if let Some((node, existed)) = self.current.take() {
+            let original_children = if existed { self.shapes[node.index].1 } else { 0 };
+            let mut child_index = node.index + 1;
+            for (i, child) in node.tree.children_mut().into_iter().enumerate() {
+                let existed = i < original_children;
+                let index = if existed { child_index } else { self.next_new };
+                if existed {
+                    child_index += self.shapes[index].0;
+                } else {
+                    self.next_new += 1;
+                }
+                let child = NodeMut {
+                    tree: child,
+                    index,
+                    depth: node.depth + 1,
+                    parent: Some(node.index),
+                };
+                self.queue.push_back((child, existed));
+            }
+        }
+        self.current = Some(self.queue.pop_front()?);
+        self.current.as_mut().map(|&mut (ref mut node, _)| {
+            NodeMut {
+                tree: &mut *node.tree,
+                index: node.index,
+                depth: node.depth,
+                parent: node.parent,
+            }
+        })
+    }
+}
+
+impl<T> BoxTree<T>
+    where T: Tree
+{
+    /// Iterate over the nodes in pre-order. The same as `BoxTree::pre_order`.
+    pub fn iter(&self) -> PreOrder<'_, T> {
+        self.pre_order()
+    }
+
+    /// Iterate over the nodes in pre-order (parents before children, left to right).
+    ///
+    /// This is the order used by `BoxTree::map` and `BoxTree::fold`.
+    pub fn pre_order(&self) -> PreOrder<'_, T> {
+        PreOrder {
+            stack: vec![(self, 0, None)],
+            index: 0,
+        }
+    }
+
+    /// Iterate over the nodes in post-order (children before parents, left to right).
+    pub fn post_order(&self) -> PostOrder<'_, T> {
+        PostOrder {
+            stack: vec![PostOrderEntry {
+                            tree: self,
+                            depth: 0,
+                            parent: None,
+                            expanded: None,
+                        }],
+            index: 0,
+        }
+    }
+
+    /// Iterate over the nodes breadth-first (level by level, left to right).
+    pub fn breadth_first(&self) -> BreadthFirst<'_, T> {
+        let mut shapes = vec![];
+        collect_shapes(self, &mut shapes);
+        let mut queue = VecDeque::new();
+        queue.push_back(NodeRef {
+            tree: self,
+            index: 0,
+            depth: 0,
+            parent: None,
+        });
+        BreadthFirst {
+            queue,
+            sizes: shapes.into_iter().map(|(size, _)| size).collect(),
+        }
+    }
+
+    /// Traverse the nodes mutably in pre-order. Changes made to a node are seen when its
+    /// children are visited.
+    pub fn pre_order_mut(&mut self) -> PreOrderMut<'_, T> {
+        PreOrderMut {
+            stack: vec![(self, 0, None)],
+            current: None,
+            index: 0,
+        }
+    }
+
+    /// Traverse the nodes mutably in post-order.
+    ///
+    /// Finding each node walks down from the root, so this costs more than the other
+    /// traversals.
+    pub fn post_order_mut(&mut self) -> PostOrderMut<'_, T> {
+        let mut paths = vec![];
+        let mut path = vec![];
+        let mut index = 0;
+        collect_post_order_paths(self, &mut path, &mut index, 0, None, &mut paths);
+        paths.reverse();
+        PostOrderMut {
+            root: self,
+            paths,
+        }
+    }
+
+    /// Traverse the nodes mutably breadth-first. Changes made to a node are seen when its
+    /// children are visited.
+    ///
+    /// Indices are those of the tree before traversal, so a replaced node keeps the index of
+    /// the node it replaced. Nodes at positions which did not exist before are numbered
+    /// from the original number of nodes upwards, in the order they are visited.
+    pub fn breadth_first_mut(&mut self) -> BreadthFirstMut<'_, T> {
+        let mut shapes = vec![];
+        collect_shapes(self, &mut shapes);
+        let next_new = shapes.len();
+        let mut queue = VecDeque::new();
+        let root = NodeMut {
+            tree: self,
+            index: 0,
+            depth: 0,
+            parent: None,
+        };
+        queue.push_back((root, true));
+        BreadthFirstMut {
+            queue,
+            current: None,
+            shapes,
+            next_new,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BoxTree<T>
+    where T: Tree
+{
+    type Item = NodeRef<'a, T>;
+    type IntoIter = PreOrder<'a, T>;
+
+    fn into_iter(self) -> PreOrder<'a, T> {
+        self.pre_order()
+    }
+}
+
+/// Push the size and number of children of every subtree onto `shapes`, in pre-order.
+/// Returns the size of `tree`.
+fn collect_shapes<T>(tree: &BoxTree<T>, shapes: &mut Vec<(usize, usize)>) -> usize
+    where T: Tree
+{
+    let index = shapes.len();
+    let children = tree.children();
+    shapes.push((1, children.len()));
+    let size = 1 + children.into_iter().map(|child| collect_shapes(child, shapes)).sum::<usize>();
+    shapes[index].0 = size;
+    size
+}
+
+/// Push the path, pre-order index, depth and parent of every node onto `paths`, in
+/// post-order.
+fn collect_post_order_paths<T>(tree: &BoxTree<T>,
+                               path: &mut Vec<usize>,
+                               index: &mut usize,
+                               depth: usize,
+                               parent: Option<usize>,
+                               paths: &mut Vec<(Vec<usize>, usize, usize, Option<usize>)>)
+    where T: Tree
+{
+    let own_index = *index;
+    *index += 1;
+    for (i, child) in tree.children().into_iter().enumerate() {
+        path.push(i);
+        collect_post_order_paths(child, path, index, depth + 1, Some(own_index), paths);
+        path.pop();
+    }
+    paths.push((path.clone(), own_index, depth, parent));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::fmt;
+
+    /// Nodes labelled by a character, displayed as `label(child, ...)`.
+    #[derive(Clone, Debug)]
+    struct Labelled {
+        label: char,
+        children: Vec<BoxTree<Labelled>>,
+    }
+
+    impl Tree for Labelled {
+        type Environment = ();
+        type Action = usize;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Labelled> {
+            let children = (0..tg.gen_range(1, 4)).map(|_| Self::child(tg, current_depth + 1));
+            labelled('b', children.collect())
+        }
+
+        fn leaf<R: Rng>(_: &mut TreeGen<R>, _: usize) -> BoxTree<Labelled> {
+            leaf('l')
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children.len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Labelled>> {
+            self.children.iter().collect()
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Labelled>> {
+            self.children.iter_mut().collect()
+        }
+
+        /// Count the nodes.
+        fn evaluate(&self, _: &()) -> usize {
+            1 + self.children.iter().map(|child| child.evaluate(&())).sum::<usize>()
+        }
+    }
+
+    impl fmt::Display for Labelled {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.label)?;
+            if !self.children.is_empty() {
+                let children: Vec<String> = self.children.iter().map(|c| c.to_string()).collect();
+                write!(f, "({})", children.join(", "))?;
+            }
+            Ok(())
+        }
+    }
+
+    fn labelled(label: char, children: Vec<BoxTree<Labelled>>) -> BoxTree<Labelled> {
+        Labelled { label, children }.into()
+    }
+
+    fn leaf(label: char) -> BoxTree<Labelled> {
+        labelled(label, vec![])
+    }
+
+    /// `*(+(a, s(b)), c)`, numbered in pre-order as `* + a s b c`.
+    fn tree() -> BoxTree<Labelled> {
+        let sum = labelled('+', vec![leaf('a'), labelled('s', vec![leaf('b')])]);
+        labelled('*', vec![sum, leaf('c')])
+    }
+
+    fn visits<'a, I>(nodes: I) -> Vec<(usize, usize, Option<usize>)>
+        where I: Iterator<Item = NodeRef<'a, Labelled>>
+    {
+        nodes.map(|node| (node.index, node.depth, node.parent)).collect()
+    }
+
+    #[test]
+    fn pre_order() {
+        assert_eq!(visits(tree().pre_order()),
+                   vec![(0, 0, None),
+                        (1, 1, Some(0)),
+                        (2, 2, Some(1)),
+                        (3, 2, Some(1)),
+                        (4, 3, Some(3)),
+                        (5, 1, Some(0))]);
+    }
+
+    #[test]
+    fn post_order() {
+        assert_eq!(visits(tree().post_order()),
+                   vec![(2, 2, Some(1)),
+                        (4, 3, Some(3)),
+                        (3, 2, Some(1)),
+                        (1, 1, Some(0)),
+                        (5, 1, Some(0)),
+                        (0, 0, None)]);
+    }
+
+    #[test]
+    fn breadth_first_keeps_pre_order_indices() {
+        assert_eq!(visits(tree().breadth_first()),
+                   vec![(0, 0, None),
+                        (1, 1, Some(0)),
+                        (5, 1, Some(0)),
+                        (2, 2, Some(1)),
+                        (3, 2, Some(1)),
+                        (4, 3, Some(3))]);
+    }
+
+    #[test]
+    fn mutable_traversals_match_immutable_ones() {
+        let mut tree = tree();
+        let mut indices = vec![];
+        {
+            let mut nodes = tree.post_order_mut();
+            while let Some(node) = nodes.next_node() {
+                indices.push(node.index);
+            }
+        }
+        assert_eq!(indices, vec![2, 4, 3, 1, 5, 0]);
+
+        indices.clear();
+        let mut nodes = tree.breadth_first_mut();
+        while let Some(node) = nodes.next_node() {
+            indices.push(node.index);
+        }
+        assert_eq!(indices, vec![0, 1, 5, 2, 3, 4]);
+    }
+
+    #[test]
+    fn breadth_first_mut_traverses_changes() {
+        let mut tree = tree();
+        let mut visited = vec![];
+        {
+            let mut nodes = tree.breadth_first_mut();
+            while let Some(node) = nodes.next_node() {
+                // Grow a into s(t(b)), and rename c to d.
+                match node.tree.label {
+                    'a' => {
+                        let t = labelled('t', vec![leaf('b')]);
+                        *node.tree = labelled('s', vec![t]);
+                    }
+                    'c' => *node.tree = leaf('d'),
+                    _ => {}
+                }
+                visited.push((node.index, node.tree.to_string()));
+            }
+        }
+        let indices: Vec<usize> = visited.iter().map(|&(index, _)| index).collect();
+        // The replaced a keeps index 2; its new descendants are numbered from 6.
+        assert_eq!(indices, vec![0, 1, 5, 2, 3, 6, 4, 7]);
+        assert_eq!(visited[2].1, "d");
+        assert_eq!(visited[7].1, "b");
+        assert_eq!(tree.to_string(), "*(+(s(t(b)), s(b)), d)");
+        assert_eq!(tree.evaluate(&()), 8);
+    }
+
+    #[test]
+    fn into_iterator_is_pre_order() {
+        let tree = tree();
+        let indices: Vec<usize> = (&tree).into_iter().map(|node| node.index).collect();
+        assert_eq!(indices, (0..6).collect::<Vec<_>>());
+        assert_eq!(tree.iter().count(), tree.count_nodes());
+    }
+}
@@ -1,11 +1,25 @@
 mod gen;
+mod dot;
+mod compile;
+mod batch;
+mod iter;
+mod path;
+mod flat;
+mod repr;
 
 pub use self::gen::*;
+pub use self::dot::*;
+pub use self::compile::*;
+pub use self::iter::*;
+pub use self::flat::*;
+pub use self::repr::*;
 
 use rand::Rng;
 use std::fmt::{self, Debug};
 use std::ops::{Deref, DerefMut};
 use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 /// Trait to be implemented by Genetic Programs trees.
 pub trait Tree
@@ -46,6 +60,23 @@ pub trait Tree
     /// Get mutable children of this node.
     fn children_mut(&mut self) -> Vec<&mut BoxTree<Self>>;
 
+    /// Whether crossover may swap the subtree rooted at this node with one rooted at
+    /// `other`. Defaults to always.
+    fn compatible(&self, _other: &Self) -> bool {
+        true
+    }
+
+    /// Whether `Tree::compatible` can ever return `false`. Override this along with
+    /// `compatible`; while it is `false`, crossover skips checking compatibility.
+    fn constrained() -> bool {
+        false
+    }
+
+    /// Generate a subtree to replace this node during mutation. Defaults to `Tree::tree`.
+    fn regenerate<R: Rng>(&self, tg: &mut TreeGen<R>) -> BoxTree<Self> {
+        Self::tree(tg)
+    }
+
     /// Get indexed child of this node. Number children from 0; suggested to go left-to-right.
     //fn get_mut_child(&mut self, index: usize) -> Option<&mut BoxTree<Self>>;
     /// Used to evaluate the root node of a tree.
@@ -53,7 +84,7 @@ pub trait Tree
 }
 
 /// `Box` Wrapper for implementations of Tree.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct BoxTree<T>(Box<T>) where T: Tree;
 
 impl<T> BoxTree<T>
@@ -65,20 +96,13 @@ impl<T> BoxTree<T>
     }
 
     /// Count the number of nodes below this node in the tree.
-    pub fn count_nodes(&mut self) -> usize {
-        self.fold(0, |count, _, _, _| count + 1)
+    pub fn count_nodes(&self) -> usize {
+        self.pre_order().count()
     }
 
     /// Get a clone of a particular value.
-    pub fn get(&mut self, target_index: usize) -> Option<T> {
-        let mut node = None;
-        self.map_while(|current, index, _| if index == target_index {
-            node = Some(current.clone());
-            false
-        } else {
-            true
-        });
-        node
+    pub fn get(&self, target_index: usize) -> Option<T> {
+        self.pre_order().nth(target_index).map(|node| T::clone(node.tree))
     }
 
     /// Traverse the tree with the ability to mutate nodes in-place.
@@ -159,6 +183,20 @@ impl<T> BoxTree<T>
     }
 }
 
+impl<T> BoxTree<T>
+    where T: Tree + Hash
+{
+    /// Hash the structure and contents of the tree below this node.
+    ///
+    /// Structurally identical trees have the same hash, so this can be used to spot
+    /// duplicate individuals. Different trees may collide, however unlikely.
+    pub fn structural_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
 /// Make `BoxTree` invisible in `Debug` output. At the cost of a little invisibility this
 /// makes `Tree`s far more readable.
 impl<T> Debug for BoxTree<T>
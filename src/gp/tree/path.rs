@@ -0,0 +1,209 @@
+use gp::tree::*;
+use std::mem;
+
+/// Subtrees are addressed by a path of 0-based child indices from the root, where an
+/// empty path is the root itself. Child indices follow the order of `Tree::children`.
+/// `BoxTree::path_to` converts a pre-order index into a path.
+impl<T> BoxTree<T>
+    where T: Tree
+{
+    /// Get the subtree at a path.
+    pub fn subtree(&self, path: &[usize]) -> Option<&BoxTree<T>> {
+        let mut tree = self;
+        for &child_index in path {
+            tree = tree.children().into_iter().nth(child_index)?;
+        }
+        Some(tree)
+    }
+
+    /// Get the subtree at a path, mutably.
+    pub fn subtree_mut(&mut self, path: &[usize]) -> Option<&mut BoxTree<T>> {
+        let mut tree = self;
+        for &child_index in path {
+            let parent = tree;
+            tree = parent.children_mut().into_iter().nth(child_index)?;
+        }
+        Some(tree)
+    }
+
+    /// Replace the subtree at a path, returning the subtree that was there.
+    ///
+    /// Returns `None`, dropping `subtree`, if there is no subtree at the path.
+    pub fn replace_subtree(&mut self, path: &[usize], subtree: BoxTree<T>) -> Option<BoxTree<T>> {
+        self.subtree_mut(path).map(|target| mem::replace(target, subtree))
+    }
+
+    /// Swap the subtree at `path` with the subtree at `other_path` in another tree.
+    ///
+    /// Returns `false`, changing neither tree, if either path does not exist.
+    pub fn swap_subtrees(&mut self,
+                         path: &[usize],
+                         other: &mut BoxTree<T>,
+                         other_path: &[usize])
+                         -> bool {
+        match (self.subtree_mut(path), other.subtree_mut(other_path)) {
+            (Some(target), Some(other_target)) => {
+                mem::swap(target, other_target);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Find the path to the node at a 0-based pre-order index, as used by `BoxTree::map`
+    /// and `BoxTree::get`.
+    pub fn path_to(&self, index: usize) -> Option<Vec<usize>> {
+        let mut path = vec![];
+        let mut tree = self;
+        let mut remaining = index;
+        'descend: while remaining > 0 {
+            // Skip over this node, then over whole subtrees until reaching the one
+            // containing the target.
+            remaining -= 1;
+            for (child_index, child) in tree.children().into_iter().enumerate() {
+                let size = child.count_nodes();
+                if remaining < size {
+                    path.push(child_index);
+                    tree = child;
+                    continue 'descend;
+                }
+                remaining -= size;
+            }
+            return None;
+        }
+        Some(path)
+    }
+
+    /// Get the subtree at a 0-based pre-order index.
+    pub fn subtree_at(&self, index: usize) -> Option<&BoxTree<T>> {
+        self.pre_order().nth(index).map(|node| node.tree)
+    }
+
+    /// Get the subtree at a 0-based pre-order index, mutably.
+    pub fn subtree_at_mut(&mut self, index: usize) -> Option<&mut BoxTree<T>> {
+        let path = self.path_to(index)?;
+        self.subtree_mut(&path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gp::tree::*;
+    use rand::Rng;
+    use std::fmt;
+
+    /// Nested lists of numbers, displayed as `[0, [1, 2]]`.
+    #[derive(Clone, Debug, PartialEq)]
+    enum List {
+        Number(u8),
+        Items(Vec<BoxTree<List>>),
+    }
+
+    impl Tree for List {
+        type Environment = ();
+        type Action = u32;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<List> {
+            let items = (0..tg.gen_range(1, 4)).map(|_| Self::child(tg, current_depth + 1));
+            List::Items(items.collect()).into()
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<List> {
+            number(tg.gen_range(0, 10))
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<List>> {
+            match *self {
+                List::Number(_) => vec![],
+                List::Items(ref items) => items.iter().collect(),
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<List>> {
+            match *self {
+                List::Number(_) => vec![],
+                List::Items(ref mut items) => items.iter_mut().collect(),
+            }
+        }
+
+        /// Sum the numbers.
+        fn evaluate(&self, _: &()) -> u32 {
+            match *self {
+                List::Number(number) => u32::from(number),
+                List::Items(ref items) => items.iter().map(|item| item.evaluate(&())).sum(),
+            }
+        }
+    }
+
+    impl fmt::Display for List {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                List::Number(number) => write!(f, "{}", number),
+                List::Items(ref items) => {
+                    let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+                    write!(f, "[{}]", items.join(", "))
+                }
+            }
+        }
+    }
+
+    fn number(number: u8) -> BoxTree<List> {
+        List::Number(number).into()
+    }
+
+    fn items(items: Vec<BoxTree<List>>) -> BoxTree<List> {
+        List::Items(items).into()
+    }
+
+    /// `[[0, [1]], 2]`.
+    fn list() -> BoxTree<List> {
+        items(vec![items(vec![number(0), items(vec![number(1)])]), number(2)])
+    }
+
+    #[test]
+    fn path_to_matches_pre_order() {
+        let tree = list();
+        let paths: Vec<Vec<usize>> =
+            (0..tree.count_nodes()).map(|index| tree.path_to(index).unwrap()).collect();
+        assert_eq!(paths,
+                   vec![vec![], vec![0], vec![0, 0], vec![0, 1], vec![0, 1, 0], vec![1]]);
+        for (index, path) in paths.iter().enumerate() {
+            assert_eq!(tree.subtree(path), tree.subtree_at(index));
+        }
+        assert_eq!(tree.path_to(6), None);
+        assert_eq!(tree.subtree_at(6), None);
+    }
+
+    #[test]
+    fn missing_paths() {
+        let mut tree = list();
+        assert!(tree.subtree(&[2]).is_none());
+        assert!(tree.subtree(&[1, 0]).is_none());
+        assert!(tree.replace_subtree(&[0, 2], number(3)).is_none());
+        let mut other = number(4);
+        assert!(!tree.swap_subtrees(&[5], &mut other, &[]));
+        assert!(!tree.swap_subtrees(&[], &mut other, &[0]));
+        assert_eq!(tree, list());
+        assert_eq!(other, number(4));
+    }
+
+    #[test]
+    fn replace_and_swap() {
+        let mut tree = list();
+        assert_eq!(tree.replace_subtree(&[0, 1, 0], number(3)), Some(number(1)));
+        assert_eq!(tree.to_string(), "[[0, [3]], 2]");
+
+        let mut other = items(vec![number(4)]);
+        assert!(tree.swap_subtrees(&[1], &mut other, &[0]));
+        assert_eq!(tree.to_string(), "[[0, [3]], 4]");
+        assert_eq!(other.to_string(), "[2]");
+
+        *tree.subtree_at_mut(2).unwrap() = number(5);
+        assert_eq!(tree.to_string(), "[[5, [3]], 4]");
+        assert_eq!(tree.evaluate(&()), 12);
+    }
+}
@@ -0,0 +1,193 @@
+use gp::tree::*;
+use rand::Rng;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+/// Storage for a GP tree, so that `Individual`, `Crossover` and `Mutation` work with
+/// either `BoxTree` or `FlatTree`.
+///
+/// Nodes are addressed by their 0-based pre-order index, as used by `BoxTree::map`. Trees
+/// must be hashable, so that `Individual` can cache their structural hash.
+pub trait Representation
+    where Self: Sized + Debug + Clone
+{
+    /// The `Tree` being stored.
+    type Tree: Tree;
+
+    /// Generate a new tree within the bounds specified by TreeGen.
+    fn generate<R: Rng>(tg: &mut TreeGen<R>) -> Self;
+
+    /// Count the number of nodes in the tree.
+    fn count_nodes(&self) -> usize;
+
+    /// Hash the structure and contents of the tree, to spot duplicate individuals. Hashes
+    /// are only comparable between trees of the same representation.
+    fn structural_hash(&self) -> u64;
+
+    /// Visit every node in pre-order. The callback receives the 0-based pre-order index,
+    /// the 0-based depth and the number of children of each node.
+    fn visit<F>(&self, f: F) where F: FnMut(usize, usize, usize);
+
+    /// Replace the subtree at a pre-order index, returning the subtree that was there.
+    /// Returns `None`, dropping `subtree`, if there is no node at the index.
+    fn replace_subtree(&mut self, index: usize, subtree: Self) -> Option<Self>;
+
+    /// Swap the subtree at `index` with the subtree at `other_index` in another tree.
+    /// Returns `false`, changing neither tree, if either index does not exist.
+    fn swap_subtrees(&mut self, index: usize, other: &mut Self, other_index: usize) -> bool;
+
+    /// Pre-order indices of the nodes in `other` whose subtrees may be swapped with the
+    /// subtree at `index`, according to `Tree::compatible`. Defaults to every node.
+    fn compatible_indices(&self, _index: usize, other: &Self) -> Vec<usize> {
+        (0..other.count_nodes()).collect()
+    }
+
+    /// Generate a subtree to replace the one at `index` during mutation, according to
+    /// `Tree::regenerate`. Defaults to `Representation::generate`.
+    fn regenerate<R: Rng>(&self, _index: usize, tg: &mut TreeGen<R>) -> Self {
+        Self::generate(tg)
+    }
+
+    /// Evaluate the tree.
+    fn evaluate(&self,
+                env: &<Self::Tree as Tree>::Environment)
+                -> <Self::Tree as Tree>::Action;
+}
+
+impl<T> Representation for BoxTree<T>
+    where T: Tree + Hash
+{
+    type Tree = T;
+
+    fn generate<R: Rng>(tg: &mut TreeGen<R>) -> BoxTree<T> {
+        T::tree(tg)
+    }
+
+    fn count_nodes(&self) -> usize {
+        BoxTree::count_nodes(self)
+    }
+
+    fn structural_hash(&self) -> u64 {
+        BoxTree::structural_hash(self)
+    }
+
+    fn visit<F>(&self, mut f: F)
+        where F: FnMut(usize, usize, usize)
+    {
+        for node in self.pre_order() {
+            f(node.index, node.depth, node.tree.children().len());
+        }
+    }
+
+    fn replace_subtree(&mut self, index: usize, subtree: BoxTree<T>) -> Option<BoxTree<T>> {
+        let path = self.path_to(index)?;
+        BoxTree::replace_subtree(self, &path, subtree)
+    }
+
+    fn swap_subtrees(&mut self, index: usize, other: &mut BoxTree<T>, other_index: usize) -> bool {
+        match (self.path_to(index), other.path_to(other_index)) {
+            (Some(path), Some(other_path)) => {
+                BoxTree::swap_subtrees(self, &path, other, &other_path)
+            }
+            _ => false,
+        }
+    }
+
+    fn compatible_indices(&self, index: usize, other: &BoxTree<T>) -> Vec<usize> {
+        match self.subtree_at(index) {
+            Some(subtree) => {
+                other.pre_order()
+                    .filter(|node| subtree.compatible(node.tree))
+                    .map(|node| node.index)
+                    .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    fn regenerate<R: Rng>(&self, index: usize, tg: &mut TreeGen<R>) -> BoxTree<T> {
+        match self.subtree_at(index) {
+            Some(subtree) => T::regenerate(subtree, tg),
+            None => T::tree(tg),
+        }
+    }
+
+    fn evaluate(&self, env: &T::Environment) -> T::Action {
+        T::evaluate(self, env)
+    }
+}
+
+impl<T> Representation for FlatTree<T>
+    where T: Flatten,
+          T::Op: Hash
+{
+    type Tree = T;
+
+    /// Trees are generated as a `BoxTree` and then flattened.
+    fn generate<R: Rng>(tg: &mut TreeGen<R>) -> FlatTree<T> {
+        FlatTree::from_tree(&T::tree(tg))
+    }
+
+    fn count_nodes(&self) -> usize {
+        FlatTree::count_nodes(self)
+    }
+
+    fn structural_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn visit<F>(&self, mut f: F)
+        where F: FnMut(usize, usize, usize)
+    {
+        for node in self.pre_order() {
+            f(node.index, node.depth, node.node.arity);
+        }
+    }
+
+    fn replace_subtree(&mut self, index: usize, subtree: FlatTree<T>) -> Option<FlatTree<T>> {
+        FlatTree::replace_subtree(self, index, subtree)
+    }
+
+    fn swap_subtrees(&mut self,
+                     index: usize,
+                     other: &mut FlatTree<T>,
+                     other_index: usize)
+                     -> bool {
+        FlatTree::swap_subtrees(self, index, other, other_index)
+    }
+
+    /// Opcodes alone cannot be checked with `Tree::compatible`, so when `Tree::constrained`
+    /// is true both trees are rebuilt as `BoxTree`s to be checked. Otherwise every node is
+    /// compatible.
+    fn compatible_indices(&self, index: usize, other: &FlatTree<T>) -> Vec<usize> {
+        if !T::constrained() {
+            return (0..other.count_nodes()).collect();
+        }
+        match self.subtree(index) {
+            Some(subtree) => {
+                let subtree = subtree.to_tree();
+                other.to_tree()
+                    .pre_order()
+                    .filter(|node| subtree.compatible(node.tree))
+                    .map(|node| node.index)
+                    .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    /// Subtrees are rebuilt as `BoxTree`s to be regenerated.
+    fn regenerate<R: Rng>(&self, index: usize, tg: &mut TreeGen<R>) -> FlatTree<T> {
+        match self.subtree(index) {
+            Some(subtree) => FlatTree::from_tree(&T::regenerate(&subtree.to_tree(), tg)),
+            None => Self::generate(tg),
+        }
+    }
+
+    fn evaluate(&self, env: &T::Environment) -> T::Action {
+        FlatTree::evaluate(self, env)
+    }
+}
@@ -0,0 +1,545 @@
+use gp::tree::*;
+use rand::Rng;
+use std::fmt;
+
+/// A register-machine instruction: `dst = op(src[0], src[1])`.
+///
+/// Only the first `arity` source registers are read, so operators take at most two
+/// arguments. Operators without arguments, such as inputs and constants, load values
+/// from the environment.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub struct LinearInstruction<O> {
+    /// Register the result is written to.
+    pub dst: usize,
+    /// The operator's opcode.
+    pub op: O,
+    /// How many source registers the operator reads.
+    pub arity: usize,
+    /// Source registers.
+    pub src: [usize; 2],
+}
+
+impl<O> LinearInstruction<O> {
+    /// Get the source registers the instruction reads.
+    pub fn sources(&self) -> &[usize] {
+        &self.src[..self.arity]
+    }
+}
+
+/// A linear GP program: instructions run in order on a bank of registers.
+///
+/// Registers start at `Default::default()` and the program's result is register 0 once
+/// every instruction has run. Instructions are `Operators` opcodes, so the primitives of a
+/// tree GP can be reused for linear GP.
+#[derive(Clone, Debug)]
+pub struct LinearProgram<T>
+    where T: Operators
+{
+    instructions: Vec<LinearInstruction<T::Op>>,
+    registers: usize,
+}
+
+impl<T> LinearProgram<T>
+    where T: Operators
+{
+    /// Create a program from instructions, using `registers` registers.
+    ///
+    /// Panics if there are no registers, or if an instruction reads or writes a missing
+    /// register or reads more than two.
+    pub fn new(instructions: Vec<LinearInstruction<T::Op>>,
+               registers: usize)
+               -> LinearProgram<T> {
+        assert!(registers >= 1, "need at least one register");
+        for instruction in &instructions {
+            assert!(instruction.arity <= 2, "instructions read at most two registers");
+            let in_range = |register: usize| register < registers;
+            assert!(in_range(instruction.dst) && instruction.src.iter().all(|&src| in_range(src)),
+                    "instruction uses a missing register");
+        }
+        LinearProgram {
+            instructions,
+            registers,
+        }
+    }
+
+    /// Generate a random program of `length` instructions using `registers` registers.
+    ///
+    /// Panics if there are no registers.
+    pub fn random<R: Rng>(rng: &mut R, length: usize, registers: usize) -> LinearProgram<T> {
+        assert!(registers >= 1, "need at least one register");
+        LinearProgram {
+            instructions: (0..length)
+                .map(|_| random_instruction::<T, R>(rng, registers))
+                .collect(),
+            registers,
+        }
+    }
+
+    /// Get the instructions, in execution order.
+    pub fn instructions(&self) -> &[LinearInstruction<T::Op>] {
+        &self.instructions
+    }
+
+    /// Get the number of registers.
+    pub fn registers(&self) -> usize {
+        self.registers
+    }
+
+    /// Get the number of instructions.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Whether there are no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// Evaluate the program, as `Tree::evaluate` does for trees.
+    pub fn evaluate(&self, env: &T::Environment) -> T::Action
+        where T::Action: Clone + Default
+    {
+        let mut registers = Vec::with_capacity(self.registers);
+        self.evaluate_with_registers(env, &mut registers)
+    }
+
+    /// Evaluate the program, reusing registers between calls to avoid reallocating them.
+    pub fn evaluate_with_registers(&self,
+                                   env: &T::Environment,
+                                   registers: &mut Vec<T::Action>)
+                                   -> T::Action
+        where T::Action: Clone + Default
+    {
+        registers.clear();
+        registers.resize(self.registers, T::Action::default());
+        let mut args = Vec::with_capacity(2);
+        for instruction in &self.instructions {
+            args.clear();
+            args.extend(instruction.sources().iter().map(|&src| registers[src].clone()));
+            registers[instruction.dst] = T::execute(&instruction.op, env, &args);
+        }
+        registers[0].clone()
+    }
+
+    /// Find which instructions affect the result. The others are introns.
+    ///
+    /// Works backwards from register 0, marking each instruction that writes a register
+    /// still to be read.
+    pub fn effective(&self) -> Vec<bool> {
+        let mut live = vec![false; self.registers];
+        live[0] = true;
+        let mut effective = vec![false; self.instructions.len()];
+        for (i, instruction) in self.instructions.iter().enumerate().rev() {
+            if live[instruction.dst] {
+                effective[i] = true;
+                live[instruction.dst] = false;
+                for &src in instruction.sources() {
+                    live[src] = true;
+                }
+            }
+        }
+        effective
+    }
+
+    /// Count the instructions which affect the result.
+    pub fn count_effective(&self) -> usize {
+        self.effective().into_iter().filter(|&effective| effective).count()
+    }
+
+    /// Remove introns. The result of evaluating the program is unchanged.
+    pub fn remove_introns(&mut self) {
+        let effective = self.effective();
+        let mut i = 0;
+        self.instructions.retain(|_| {
+            i += 1;
+            effective[i - 1]
+        });
+    }
+}
+
+impl<T> fmt::Display for LinearProgram<T>
+    where T: Operators
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for instruction in &self.instructions {
+            write!(f, "r{} = {:?}", instruction.dst, instruction.op)?;
+            let sources: Vec<String> =
+                instruction.sources().iter().map(|src| format!("r{}", src)).collect();
+            if !sources.is_empty() {
+                write!(f, "({})", sources.join(", "))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generate a random instruction using `registers` registers.
+fn random_instruction<T, R>(rng: &mut R, registers: usize) -> LinearInstruction<T::Op>
+    where T: Operators,
+          R: Rng
+{
+    let Instruction { op, arity } = T::random_op(rng);
+    assert!(arity <= 2, "linear GP operators take at most two arguments");
+    LinearInstruction {
+        dst: rng.gen_range(0, registers),
+        op,
+        arity,
+        src: [rng.gen_range(0, registers), rng.gen_range(0, registers)],
+    }
+}
+
+/// The linear crossover mode in use. See `LinearCrossover`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum LinearCrossoverMode {
+    /// Corresponds to `LinearCrossover::one_point`.
+    OnePoint,
+    /// Corresponds to `LinearCrossover::two_point`.
+    TwoPoint(usize),
+}
+
+/// Configures crossover (mating) between linear GP programs.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct LinearCrossover {
+    mode: LinearCrossoverMode,
+}
+
+impl LinearCrossover {
+    /// Get an operator to perform one-point crossover.
+    ///
+    /// A cut point is chosen independently in each program and the instructions after the
+    /// cut points are swapped. Every program keeps at least one instruction.
+    pub fn one_point() -> LinearCrossover {
+        LinearCrossover { mode: LinearCrossoverMode::OnePoint }
+    }
+
+    /// Get an operator to perform two-point (linear) crossover.
+    ///
+    /// A run of instructions is chosen independently in each program and the runs are
+    /// swapped. Programs are unchanged if either would grow beyond `max_length`.
+    pub fn two_point(max_length: usize) -> LinearCrossover {
+        LinearCrossover { mode: LinearCrossoverMode::TwoPoint(max_length) }
+    }
+
+    /// Crossover (mate) two programs according to the configured crossover mode.
+    pub fn mate<T, R>(&self,
+                      program1: &mut LinearProgram<T>,
+                      program2: &mut LinearProgram<T>,
+                      mut rng: R)
+        where T: Operators,
+              R: Rng
+    {
+        if program1.is_empty() || program2.is_empty() {
+            return;
+        }
+        let instructions1 = &mut program1.instructions;
+        let instructions2 = &mut program2.instructions;
+        match self.mode {
+            LinearCrossoverMode::OnePoint => {
+                let tail1 = instructions1.split_off(rng.gen_range(1, instructions1.len() + 1));
+                let tail2 = instructions2.split_off(rng.gen_range(1, instructions2.len() + 1));
+                instructions1.extend(tail2);
+                instructions2.extend(tail1);
+            }
+            LinearCrossoverMode::TwoPoint(max_length) => {
+                let start1 = rng.gen_range(0, instructions1.len());
+                let end1 = rng.gen_range(start1 + 1, instructions1.len() + 1);
+                let start2 = rng.gen_range(0, instructions2.len());
+                let end2 = rng.gen_range(start2 + 1, instructions2.len() + 1);
+                let (run1, run2) = (end1 - start1, end2 - start2);
+                if instructions1.len() - run1 + run2 > max_length ||
+                   instructions2.len() - run2 + run1 > max_length {
+                    return;
+                }
+                let segment2: Vec<_> = instructions2.drain(start2..end2).collect();
+                let segment1: Vec<_> = instructions1.splice(start1..end1, segment2).collect();
+                let tail2 = instructions2.split_off(start2);
+                instructions2.extend(segment1);
+                instructions2.extend(tail2);
+            }
+        }
+    }
+}
+
+/// The linear mutation mode in use. See `LinearMutation`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum LinearMutationMode {
+    /// Corresponds to `LinearMutation::micro`.
+    Micro,
+    /// Corresponds to `LinearMutation::insertion_deletion`.
+    InsertionDeletion(usize),
+}
+
+/// Configures mutation of linear GP programs.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct LinearMutation {
+    mode: LinearMutationMode,
+}
+
+impl LinearMutation {
+    /// Change one part of a random instruction: its destination, its operator or one of
+    /// its source registers.
+    pub fn micro() -> LinearMutation {
+        LinearMutation { mode: LinearMutationMode::Micro }
+    }
+
+    /// Either insert a random instruction at a random position or delete a random
+    /// instruction. Programs are kept between one and `max_length` instructions long.
+    pub fn insertion_deletion(max_length: usize) -> LinearMutation {
+        LinearMutation { mode: LinearMutationMode::InsertionDeletion(max_length) }
+    }
+
+    /// Mutate a program according to the configured mutation mode.
+    pub fn mutate<T, R>(&self, program: &mut LinearProgram<T>, mut rng: R)
+        where T: Operators,
+              R: Rng
+    {
+        let registers = program.registers;
+        let length = program.instructions.len();
+        match self.mode {
+            LinearMutationMode::Micro => {
+                if length == 0 {
+                    return;
+                }
+                let instruction = &mut program.instructions[rng.gen_range(0, length)];
+                match rng.gen_range(0, 4) {
+                    0 => instruction.dst = rng.gen_range(0, registers),
+                    1 => {
+                        let Instruction { op, arity } = T::random_op(&mut rng);
+                        assert!(arity <= 2, "linear GP operators take at most two arguments");
+                        instruction.op = op;
+                        instruction.arity = arity;
+                    }
+                    2 => instruction.src[0] = rng.gen_range(0, registers),
+                    _ => instruction.src[1] = rng.gen_range(0, registers),
+                }
+            }
+            LinearMutationMode::InsertionDeletion(max_length) => {
+                let insert = match (length > 1, length < max_length) {
+                    (true, true) => rng.gen(),
+                    (false, true) => true,
+                    (true, false) => false,
+                    (false, false) => return,
+                };
+                if insert {
+                    let instruction = random_instruction::<T, _>(&mut rng, registers);
+                    program.instructions.insert(rng.gen_range(0, length + 1), instruction);
+                } else {
+                    program.instructions.remove(rng.gen_range(0, length));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    /// Sums of an input and ones, negated.
+    #[derive(Clone, Debug)]
+    enum Calc {
+        X,
+        One,
+        Add(BoxTree<Calc>, BoxTree<Calc>),
+        Neg(BoxTree<Calc>),
+    }
+
+    #[derive(PartialEq, Clone, Copy, Debug)]
+    enum CalcOp {
+        X,
+        One,
+        Add,
+        Neg,
+    }
+
+    impl Tree for Calc {
+        type Environment = f64;
+        type Action = f64;
+
+        fn branch<R: Rng>(tg: &mut TreeGen<R>, current_depth: usize) -> BoxTree<Calc> {
+            let child = Self::child(tg, current_depth + 1);
+            if tg.gen() {
+                Calc::Neg(child).into()
+            } else {
+                Calc::Add(child, Self::child(tg, current_depth + 1)).into()
+            }
+        }
+
+        fn leaf<R: Rng>(tg: &mut TreeGen<R>, _: usize) -> BoxTree<Calc> {
+            if tg.gen() { Calc::X } else { Calc::One }.into()
+        }
+
+        fn count_children(&mut self) -> usize {
+            self.children().len()
+        }
+
+        fn children(&self) -> Vec<&BoxTree<Calc>> {
+            match *self {
+                Calc::X | Calc::One => vec![],
+                Calc::Add(ref left, ref right) => vec![left, right],
+                Calc::Neg(ref child) => vec![child],
+            }
+        }
+
+        fn children_mut(&mut self) -> Vec<&mut BoxTree<Calc>> {
+            match *self {
+                Calc::X | Calc::One => vec![],
+                Calc::Add(ref mut left, ref mut right) => vec![left, right],
+                Calc::Neg(ref mut child) => vec![child],
+            }
+        }
+
+        fn evaluate(&self, env: &f64) -> f64 {
+            let args: Vec<f64> = self.children().iter().map(|child| child.evaluate(env)).collect();
+            Calc::execute(&self.op(), env, &args)
+        }
+    }
+
+    impl Compile for Calc {
+        type Op = CalcOp;
+
+        fn op(&self) -> CalcOp {
+            match *self {
+                Calc::X => CalcOp::X,
+                Calc::One => CalcOp::One,
+                Calc::Add(..) => CalcOp::Add,
+                Calc::Neg(..) => CalcOp::Neg,
+            }
+        }
+
+        fn execute(op: &CalcOp, env: &f64, args: &[f64]) -> f64 {
+            match *op {
+                CalcOp::X => *env,
+                CalcOp::One => 1.0,
+                CalcOp::Add => args[0] + args[1],
+                CalcOp::Neg => -args[0],
+            }
+        }
+    }
+
+    impl Operators for Calc {
+        fn random_op<R: Rng>(rng: &mut R) -> Instruction<CalcOp> {
+            let (op, arity) = match rng.gen_range(0, 4) {
+                0 => (CalcOp::X, 0),
+                1 => (CalcOp::One, 0),
+                2 => (CalcOp::Add, 2),
+                _ => (CalcOp::Neg, 1),
+            };
+            Instruction { op, arity }
+        }
+    }
+
+    fn instruction(dst: usize, op: CalcOp, src: &[usize]) -> LinearInstruction<CalcOp> {
+        let mut registers = [0; 2];
+        registers[..src.len()].copy_from_slice(src);
+        LinearInstruction {
+            dst,
+            op,
+            arity: src.len(),
+            src: registers,
+        }
+    }
+
+    fn double_x() -> LinearProgram<Calc> {
+        LinearProgram::new(vec![instruction(1, CalcOp::X, &[]),
+                                instruction(2, CalcOp::One, &[]),
+                                instruction(0, CalcOp::Add, &[1, 1]),
+                                instruction(1, CalcOp::Neg, &[2])],
+                           3)
+    }
+
+    fn random_programs(seed: usize) -> Vec<LinearProgram<Calc>> {
+        let mut rng = StdRng::from_seed(&[seed][..]);
+        (0..50).map(|_| LinearProgram::random(&mut rng, 12, 4)).collect()
+    }
+
+    #[test]
+    fn sources_are_limited_by_arity() {
+        let program = double_x();
+        let sources: Vec<&[usize]> =
+            program.instructions().iter().map(|instruction| instruction.sources()).collect();
+        assert_eq!(sources, vec![&[][..], &[][..], &[1, 1][..], &[2][..]]);
+    }
+
+    #[test]
+    fn evaluate_and_introns() {
+        let mut program = double_x();
+        let mut registers = vec![];
+        assert_eq!(program.evaluate(&3.0), 6.0);
+        assert_eq!(program.evaluate_with_registers(&-2.0, &mut registers), -4.0);
+        assert_eq!(program.effective(), vec![true, false, true, false]);
+        assert_eq!(program.count_effective(), 2);
+        assert_eq!(program.to_string(), "r1 = X\nr2 = One\nr0 = Add(r1, r1)\nr1 = Neg(r2)\n");
+        program.remove_introns();
+        assert_eq!(program.len(), 2);
+        assert_eq!(program.evaluate(&3.0), 6.0);
+    }
+
+    #[test]
+    fn removing_introns_keeps_results() {
+        for mut program in random_programs(39) {
+            let before: Vec<f64> = (-2..3).map(|x| program.evaluate(&(x as f64))).collect();
+            let effective = program.count_effective();
+            program.remove_introns();
+            assert_eq!(program.len(), effective);
+            assert_eq!(program.count_effective(), effective);
+            let after: Vec<f64> = (-2..3).map(|x| program.evaluate(&(x as f64))).collect();
+            assert_eq!(after, before);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "missing register")]
+    fn new_checks_registers() {
+        LinearProgram::<Calc>::new(vec![instruction(0, CalcOp::Neg, &[3])], 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one register")]
+    fn new_needs_a_register() {
+        LinearProgram::<Calc>::new(vec![], 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one register")]
+    fn random_needs_a_register() {
+        let mut rng = StdRng::from_seed(&[39usize][..]);
+        LinearProgram::<Calc>::random(&mut rng, 5, 0);
+    }
+
+    #[test]
+    fn crossover_keeps_lengths_in_bounds() {
+        let mut rng = StdRng::from_seed(&[39usize][..]);
+        let operators = [(LinearCrossover::one_point(), usize::MAX),
+                         (LinearCrossover::two_point(16), 16)];
+        for &(crossover, max_length) in &operators {
+            let mut programs = random_programs(40);
+            for _ in 0..10 {
+                for pair in programs.chunks_mut(2) {
+                    let (first, second) = pair.split_at_mut(1);
+                    let total = first[0].len() + second[0].len();
+                    crossover.mate(&mut first[0], &mut second[0], &mut rng);
+                    assert_eq!(first[0].len() + second[0].len(), total);
+                    assert!(!first[0].is_empty() && !second[0].is_empty());
+                    assert!(first[0].len() <= max_length && second[0].len() <= max_length);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mutation_keeps_programs_valid() {
+        let mut rng = StdRng::from_seed(&[39usize][..]);
+        for mut program in random_programs(41) {
+            for _ in 0..20 {
+                LinearMutation::micro().mutate(&mut program, &mut rng);
+                LinearMutation::insertion_deletion(14).mutate(&mut program, &mut rng);
+                assert!(!program.is_empty() && program.len() <= 14);
+            }
+            let program = LinearProgram::<Calc>::new(program.instructions().to_vec(), 4);
+            assert!(program.evaluate(&1.0).is_finite());
+        }
+    }
+}
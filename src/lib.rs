@@ -22,6 +22,20 @@ extern crate rand;
 
 /// Genetic Programming.
 pub mod gp;
+/// Genetic Algorithms.
+pub mod ga;
+/// Differential Evolution.
+pub mod de;
+/// Covariance Matrix Adaptation Evolution Strategy.
+pub mod cmaes;
+/// Grammatical Evolution.
+pub mod ge;
+/// Linear Genetic Programming.
+pub mod lgp;
+/// Cartesian Genetic Programming.
+pub mod cgp;
+/// Selection and generational replacement, shared by GP and GA.
+pub mod selection;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,173 @@
+use rand::Rng;
+use std::cmp::Ordering;
+
+/// The selection mode in use. See `Selection`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum SelectionMode {
+    /// Corresponds to `Selection::tournament`.
+    Tournament(usize),
+}
+
+/// Configures selection of individuals by their cost, which is minimised.
+///
+/// Individuals are referred to by their index in the population, so selection works the
+/// same for GP individuals, GA chromosomes or anything else. NaN costs lose to every other
+/// cost.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Selection {
+    mode: SelectionMode,
+}
+
+impl Selection {
+    /// Get an operator to select the best of `size` individuals drawn at random, with
+    /// replacement.
+    pub fn tournament(size: usize) -> Selection {
+        assert!(size > 0, "tournaments need at least one entrant");
+        Selection { mode: SelectionMode::Tournament(size) }
+    }
+
+    /// Select the index of an individual according to the configured selection mode.
+    ///
+    /// Panics if `costs` is empty.
+    pub fn select<R: Rng>(&self, costs: &[f64], mut rng: R) -> usize {
+        assert!(!costs.is_empty(), "cannot select from an empty population");
+        match self.mode {
+            SelectionMode::Tournament(size) => {
+                (0..size)
+                    .map(|_| rng.gen_range(0, costs.len()))
+                    .min_by(|&a, &b| compare_costs(costs[a], costs[b]))
+                    .expect("tournaments have at least one entrant")
+            }
+        }
+    }
+}
+
+/// Generational replacement: each generation is bred from selected parents of the last,
+/// keeping the best few unchanged.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Generational {
+    selection: Selection,
+    elitism: usize,
+}
+
+impl Generational {
+    /// Breed generations from parents chosen by `selection`, without elitism.
+    pub fn new(selection: Selection) -> Generational {
+        Generational {
+            selection,
+            elitism: 0,
+        }
+    }
+
+    /// Copy the best `elitism` individuals of each generation into the next unchanged.
+    pub fn elitism(mut self, elitism: usize) -> Generational {
+        self.elitism = elitism;
+        self
+    }
+
+    /// Breed the next generation, of the same size as `population`. `costs` holds the cost
+    /// of each individual, which is minimised.
+    ///
+    /// `breed` makes two offspring from two selected parents, for example by crossover and
+    /// mutation. Any offspring beyond the population size are dropped.
+    pub fn next<I, R, F>(&self,
+                         population: &[I],
+                         costs: &[f64],
+                         mut rng: R,
+                         mut breed: F)
+                         -> Vec<I>
+        where I: Clone,
+              R: Rng,
+              F: FnMut(&I, &I, &mut R) -> (I, I)
+    {
+        assert_eq!(population.len(), costs.len(), "need a cost for every individual");
+        let mut next: Vec<I> =
+            ranking(costs).into_iter().take(self.elitism).map(|i| population[i].clone()).collect();
+        while next.len() < population.len() {
+            let parent1 = &population[self.selection.select(costs, &mut rng)];
+            let parent2 = &population[self.selection.select(costs, &mut rng)];
+            let (child1, child2) = breed(parent1, parent2, &mut rng);
+            next.push(child1);
+            if next.len() < population.len() {
+                next.push(child2);
+            }
+        }
+        next
+    }
+}
+
+/// Indices of individuals from lowest to highest cost. NaN costs come last.
+pub fn ranking(costs: &[f64]) -> Vec<usize> {
+    let mut ranking: Vec<usize> = (0..costs.len()).collect();
+    ranking.sort_by(|&a, &b| compare_costs(costs[a], costs[b]));
+    ranking
+}
+
+/// Order costs from lowest to highest, with NaN last.
+fn compare_costs(a: f64, b: f64) -> Ordering {
+    match a.partial_cmp(&b) {
+        Some(ordering) => ordering,
+        None => a.is_nan().cmp(&b.is_nan()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ga::*;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn ranking_puts_nan_last() {
+        assert_eq!(ranking(&[3.0, f64::NAN, -1.0, 2.0]), vec![2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn tournaments_prefer_lower_costs() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let costs = [4.0, f64::NAN, 0.0, 2.0, 1.0];
+        let mut wins = vec![0; costs.len()];
+        for _ in 0..1000 {
+            wins[Selection::tournament(3).select(&costs, &mut rng)] += 1;
+        }
+        assert!(wins[2] > wins[4] && wins[4] > wins[3] && wins[3] > wins[0]);
+        assert!(wins[0] > wins[1]);
+        assert!((0..100).all(|_| Selection::tournament(1).select(&[f64::NAN], &mut rng) == 0));
+    }
+
+    #[test]
+    fn elites_are_kept() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let population = vec![5, 3, 8, 1, 9];
+        let costs: Vec<f64> = population.iter().map(|&x| x as f64).collect();
+        let next = Generational::new(Selection::tournament(2))
+            .elitism(2)
+            .next(&population, &costs, &mut rng, |&a, &b, _| (a + 100, b + 100));
+        assert_eq!(next.len(), population.len());
+        assert_eq!(next[..2], [1, 3]);
+        assert!(next[2..].iter().all(|&x| x > 100));
+    }
+
+    #[test]
+    fn genetic_algorithm_solves_one_max() {
+        let mut rng = StdRng::from_seed(&[41usize][..]);
+        let crossover = Crossover::uniform(0.5);
+        let mutation = Mutation::bit_flip(1.0 / 32.0);
+        let generational = Generational::new(Selection::tournament(3)).elitism(1);
+        let mut population: Vec<BitString> =
+            (0..40).map(|_| BitString::random(&mut rng, 32)).collect();
+        for _ in 0..60 {
+            let costs: Vec<f64> =
+                population.iter().map(|chromosome| -(chromosome.count_ones() as f64)).collect();
+            population = generational.next(&population, &costs, &mut rng, |a, b, rng| {
+                let (mut child1, mut child2) = (a.clone(), b.clone());
+                crossover.mate(&mut child1, &mut child2, &mut *rng);
+                mutation.mutate(&mut child1, &mut *rng);
+                mutation.mutate(&mut child2, &mut *rng);
+                (child1, child2)
+            });
+        }
+        let best = population.iter().map(|chromosome| chromosome.count_ones()).max();
+        assert_eq!(best, Some(32));
+    }
+}